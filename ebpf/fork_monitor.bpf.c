@@ -23,9 +23,15 @@ struct event {
     u32 uid;
     u32 gid;
     u32 parent_pid;
+    u32 netns_inode; // 0 if the task has no namespaces anymore (it is exiting)
+    u32 child_tid;   // new task, for forks only
     char command[TASK_COMM_LEN];
 };
 
+static __always_inline u32 task_netns_inode(struct task_struct *task) {
+    return BPF_CORE_READ(task, nsproxy, net_ns, ns.inum);
+}
+
 void process_generic_event(u32 type) {
     u64 pid_tgid = bpf_get_current_pid_tgid();
     u64 uid_gid = bpf_get_current_uid_gid();
@@ -40,6 +46,8 @@ void process_generic_event(u32 type) {
         .gid = uid_gid >> 32,
 
         .parent_pid = BPF_CORE_READ(task, real_parent, tgid),
+        .netns_inode = task_netns_inode(task),
+        .child_tid = 0,
         .command = {0},
     };
 
@@ -52,6 +60,7 @@ SEC("tracepoint/sched/sched_process_fork")
 int trace_sched_process_fork(struct trace_event_raw_sched_process_fork *ctx) {
     u64 pid_tgid = bpf_get_current_pid_tgid();
     u64 uid_gid  = bpf_get_current_uid_gid();
+    struct task_struct *task = (struct task_struct *)bpf_get_current_task();
 
     struct event event = {
         .type        = TYPE_FORK,
@@ -60,6 +69,8 @@ int trace_sched_process_fork(struct trace_event_raw_sched_process_fork *ctx) {
         .uid         = uid_gid & 0xFFFFFFFF,
         .gid         = uid_gid >> 32,
         .parent_pid  = ctx->parent_pid,
+        .netns_inode = task_netns_inode(task),
+        .child_tid   = ctx->child_pid,
         .command     = {0},
     };
 
@@ -87,14 +98,15 @@ int trace_clone(struct trace_event_raw_sys_enter *ctx) {
     return 0;
 }
 
-SEC("tracepoint/syscalls/sys_enter_unshare")
-int trace_unshare(struct trace_event_raw_sys_enter *ctx) {
+// Namespace changing syscalls are reported on exit, so that the event has the namespace the task ended up in.
+SEC("tracepoint/syscalls/sys_exit_unshare")
+int trace_unshare(struct trace_event_raw_sys_exit *ctx) {
     process_generic_event(TYPE_UNSHARE);
     return 0;
 }
 
-SEC("tracepoint/syscalls/sys_enter_setns")
-int trace_setns(struct trace_event_raw_sys_enter *ctx) {
+SEC("tracepoint/syscalls/sys_exit_setns")
+int trace_setns(struct trace_event_raw_sys_exit *ctx) {
     process_generic_event(TYPE_SETNS);
     return 0;
 }
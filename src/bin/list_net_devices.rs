@@ -1,7 +1,7 @@
 use std::collections::HashSet;
 
 use futures::{FutureExt, StreamExt, stream::FuturesUnordered};
-use net_device_mapping::{net_device::query_netns_links, netns::NetworkNamespace};
+use net_device_mapping::{net_device::LinkQuerier, netns::NetworkNamespace};
 use rtnetlink::packet_route::link::{InfoKind, LinkAttribute, LinkInfo};
 
 #[tokio::main]
@@ -11,10 +11,12 @@ async fn main() -> Result<(), anyhow::Error> {
 
     let network_namespaces = NetworkNamespace::all().await?;
 
+    let workers = std::thread::available_parallelism().map_or(4, |n| n.get());
+    let querier = LinkQuerier::new(workers);
     let futures: FuturesUnordered<_> = network_namespaces
         .into_iter()
         .filter_map(|netns| netns.any_file().map(|file| (netns, file)))
-        .map(|(netns, file)| query_netns_links(file).map(|x| (netns, x)))
+        .map(|(netns, file)| querier.query_links(file).map(|x| (netns, x)))
         .collect();
 
     let mut results = futures.collect::<Vec<_>>().await;
@@ -34,8 +36,12 @@ async fn main() -> Result<(), anyhow::Error> {
         )?;
 
         match result {
-            Ok(links) => {
-                for link in links {
+            Ok(dump) => {
+                if let Some(err) = dump.interrupted {
+                    println!("Warning: list of devices is incomplete - {err}");
+                }
+
+                for link in dump.items {
                     writeln!(links_file, "{link:?}")?;
                     let id = link.header.index;
                     let name = link_name(link.attributes.iter()).unwrap();
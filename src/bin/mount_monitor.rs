@@ -1,11 +1,11 @@
-use net_device_mapping::util::StoppableStream;
+use net_device_mapping::util::{SHUTDOWN_TIMEOUT, StoppableStream, shutdown_tasks};
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
-    let (events, fut) = net_device_mapping::mount_monitor::monitor_mountinfo()?;
-    let (mut events, mut stop) = StoppableStream::new(events);
+    let monitor = net_device_mapping::mount_monitor::monitor_mountinfo()?;
+    let (mut events, mut stop) = StoppableStream::new(monitor.events);
 
-    tokio::spawn(fut);
+    let task = tokio::spawn(monitor.fut);
     ctrlc::set_handler(move || stop.send(()).unwrap())?;
 
     println!("Monitoring mounting changes");
@@ -13,5 +13,13 @@ async fn main() -> Result<(), anyhow::Error> {
         println!("{event:?}");
     }
 
+    // Receiver is gone, so the monitor should be stopping by now.
+    drop(events);
+    for (_name, result) in shutdown_tasks(vec![("mount monitor", task)], SHUTDOWN_TIMEOUT).await {
+        if let Some(result) = result {
+            result??;
+        }
+    }
+
     Ok(())
 }
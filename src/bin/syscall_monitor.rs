@@ -1,17 +1,28 @@
-use net_device_mapping::util::StoppableStream;
+use net_device_mapping::{
+    syscall_monitor::monitor_syscalls,
+    util::{SHUTDOWN_TIMEOUT, shutdown_tasks},
+};
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
-    let (events, fut) = net_device_mapping::syscall_monitor::monitor_syscalls()?;
-    let (mut events, mut stop) = StoppableStream::new(events);
+    let (mut events, mut stop, fut) = monitor_syscalls()?;
 
-    tokio::spawn(fut);
-    ctrlc::set_handler(move || stop.send(()).unwrap())?;
+    let task = tokio::spawn(fut);
+    ctrlc::set_handler(move || {
+        let _ = stop.send(());
+    })?;
 
     println!("Monitoring specific syscalls from all processes");
+    // Ends once the monitor is stopped.
     while let Ok(event) = events.recv().await {
         println!("{event:?}");
     }
 
+    for (_name, result) in shutdown_tasks(vec![("syscall monitor", task)], SHUTDOWN_TIMEOUT).await {
+        if let Some(result) = result {
+            result??;
+        }
+    }
+
     Ok(())
 }
@@ -0,0 +1,75 @@
+/// Linux capabilities relevant to the monitors of this crate. Value is the capability bit number.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    NetAdmin = 12,
+    SysAdmin = 21,
+    Perfmon = 38,
+    Bpf = 39,
+}
+
+/// Which monitors the current process is able to run, given its effective capabilities.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapabilityReport {
+    /// Raw effective capabilities mask (`CapEff` in `/proc/self/status`).
+    pub effective: u64,
+
+    /// `syscall_monitor`: loading and attaching eBPF tracepoints requires `CAP_BPF` + `CAP_PERFMON`,
+    /// or `CAP_SYS_ADMIN` on kernels older than 5.8.
+    pub syscall_monitor: bool,
+    /// `nsid_monitor`: subscribing to `RTNLGRP_NSID` does not require any capabilities.
+    pub nsid_monitor: bool,
+    /// `mount_monitor`: only reads `/proc/self/mountinfo`, does not require any capabilities.
+    pub mount_monitor: bool,
+    /// `netns_tracker`: requires all the monitors above.
+    pub netns_tracker: bool,
+    /// `net_device` queries: moving a thread to another network namespace requires `CAP_SYS_ADMIN`.
+    pub device_queries: bool,
+    /// Changing network devices (creating, deleting, configuring) requires `CAP_NET_ADMIN`.
+    pub device_changes: bool,
+}
+
+impl CapabilityReport {
+    pub fn from_effective(effective: u64) -> Self {
+        let has = |cap: Capability| effective & (1 << cap as u8) != 0;
+
+        let syscall_monitor =
+            (has(Capability::Bpf) && has(Capability::Perfmon)) || has(Capability::SysAdmin);
+        let nsid_monitor = true;
+        let mount_monitor = true;
+
+        Self {
+            effective,
+            syscall_monitor,
+            nsid_monitor,
+            mount_monitor,
+            netns_tracker: syscall_monitor && nsid_monitor && mount_monitor,
+            device_queries: has(Capability::SysAdmin),
+            device_changes: has(Capability::NetAdmin),
+        }
+    }
+
+    pub fn has(&self, capability: Capability) -> bool {
+        self.effective & (1 << capability as u8) != 0
+    }
+}
+
+/// Inspects effective capabilities of the current process, and reports which monitors are able to run.
+///
+/// If `/proc/self/status` can not be read, the process is assumed to have no capabilities.
+pub fn check_capabilities() -> CapabilityReport {
+    let effective = std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| parse_effective_capabilities(&status))
+        .unwrap_or(0);
+
+    CapabilityReport::from_effective(effective)
+}
+
+/// Parses `CapEff:\t<hex mask>` line from contents of `/proc/<pid>/status`.
+pub fn parse_effective_capabilities(status: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|mask| u64::from_str_radix(mask.trim(), 16).ok())
+}
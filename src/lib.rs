@@ -1,5 +1,7 @@
 pub mod util;
 
+pub mod capabilities;
+
 pub mod mount_monitor;
 pub mod netns_tracker;
 pub mod nsid_monitor;
@@ -7,3 +9,11 @@ pub mod syscall_monitor;
 
 pub mod net_device;
 pub mod netns;
+pub mod netns_identity;
+pub mod wireguard;
+
+#[cfg(feature = "machined")]
+pub mod machined;
+
+#[cfg(any(test, feature = "testutil"))]
+pub mod testutil;
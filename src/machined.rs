@@ -0,0 +1,128 @@
+//! Names of systemd-machined machines (`systemd-nspawn` containers, VMs), for network namespaces.
+//!
+//! Machines and their leader processes are queried from machined over D-Bus (`org.freedesktop.machine1`).
+
+use std::{collections::HashMap, os::unix::fs::MetadataExt, path::PathBuf};
+
+use thiserror::Error;
+use zbus::zvariant::OwnedObjectPath;
+
+use crate::netns::{INode, NetworkNamespace, Pid};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("D-Bus request to machined failed - {0}")]
+    DBus(#[from] zbus::Error),
+    #[error("io error - {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.machine1.Manager",
+    default_service = "org.freedesktop.machine1",
+    default_path = "/org/freedesktop/machine1"
+)]
+trait Manager {
+    /// Name, class, service and object path of each registered machine.
+    fn list_machines(&self) -> zbus::fdo::Result<Vec<(String, String, String, OwnedObjectPath)>>;
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.machine1.Machine",
+    default_service = "org.freedesktop.machine1"
+)]
+trait Machine {
+    #[zbus(property)]
+    fn leader(&self) -> zbus::Result<u32>;
+}
+
+/// Asks machined for leaders of all registered machines: leader pid for each machine name.
+///
+/// Returns an empty map if machined is not available on the bus.
+pub async fn machine_leaders(connection: &zbus::Connection) -> Result<HashMap<String, Pid>, Error> {
+    let manager = ManagerProxy::new(connection).await?;
+    let machines = match manager.list_machines().await {
+        Ok(machines) => machines,
+        Err(zbus::fdo::Error::ServiceUnknown(_)) => return Ok(HashMap::new()),
+        Err(err) => return Err(zbus::Error::from(err).into()),
+    };
+
+    let mut leaders = HashMap::new();
+    for (name, _class, _service, path) in machines {
+        let machine = MachineProxy::builder(connection)
+            .path(path)?
+            .build()
+            .await?;
+        // Machine may have been terminated since it was listed.
+        let Ok(leader) = machine.leader().await else {
+            continue;
+        };
+        leaders.insert(name, leader);
+    }
+
+    Ok(leaders)
+}
+
+/// Fills `machine` of the namespaces that leaders of machines registered in machined run in.
+///
+/// Connects to the system bus.
+pub async fn label_machines(namespaces: &mut [NetworkNamespace]) -> Result<(), Error> {
+    let connection = zbus::Connection::system().await?;
+    label_machines_with(&connection, namespaces).await
+}
+
+/// Same as `label_machines`, but machined is asked over an existing connection.
+///
+/// Namespace of the host (the one of pid 1) is never labeled, even though machines without a network
+/// of their own (`systemd-nspawn` without `--private-network`) run in it.
+pub async fn label_machines_with(
+    connection: &zbus::Connection,
+    namespaces: &mut [NetworkNamespace],
+) -> Result<(), Error> {
+    let leaders = machine_leaders(connection).await?;
+    let machines = tokio::task::spawn_blocking(move || machine_namespaces(leaders))
+        .await
+        .map_err(std::io::Error::other)??;
+
+    for netns in namespaces {
+        if let Some(name) = machines.get(&netns.inode) {
+            netns.machine = Some(name.clone());
+        }
+    }
+
+    Ok(())
+}
+
+fn netns_path(pid: Pid) -> PathBuf {
+    PathBuf::from("/proc")
+        .join(pid.to_string())
+        .join("ns")
+        .join("net")
+}
+
+/// Machine name for each network namespace, other than the host one, a machine leader runs in.
+/// If leaders of several machines share a namespace, the alphabetically first name is used.
+fn machine_namespaces(leaders: HashMap<String, Pid>) -> std::io::Result<HashMap<INode, String>> {
+    let host = std::fs::metadata(netns_path(1))?.ino();
+
+    let mut namespaces: HashMap<INode, String> = HashMap::new();
+    for (name, leader) in leaders {
+        // Leader may have exited since machined was asked.
+        let Ok(metadata) = std::fs::metadata(netns_path(leader)) else {
+            continue;
+        };
+        if metadata.ino() == host {
+            continue;
+        }
+        namespaces
+            .entry(metadata.ino())
+            .and_modify(|existing| {
+                if name < *existing {
+                    *existing = name.clone();
+                }
+            })
+            .or_insert(name);
+    }
+
+    Ok(namespaces)
+}
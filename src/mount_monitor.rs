@@ -2,17 +2,25 @@ use std::{
     collections::{HashMap, HashSet},
     path::PathBuf,
     str::FromStr,
+    sync::Arc,
 };
 
 use mountinfo::MountInfo;
 use thiserror::Error;
-use tokio::sync::broadcast::{Receiver, Sender, error::SendError};
+use tokio::sync::broadcast::{
+    Receiver, Sender,
+    error::{RecvError, SendError},
+};
 use uuid::Uuid;
 
-use crate::util::SendMonitor;
+use crate::{
+    netns::{INode, is_nsfs, netns_inode_of_nsfs_file},
+    util::{CountingSender, EventKind, MonitorStats, SendMonitor},
+};
 
 /// Exact copy of `mountinfo::ReadWrite`, but implements `Clone` and other traits.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ReadWrite {
     ReadOnly,
     ReadWrite,
@@ -35,7 +43,8 @@ impl Into<mountinfo::ReadWrite> for ReadWrite {
 }
 
 /// Exact copy of `mountinfo::MountOptions`, but implements `Clone` and other traits.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MountOptions {
     /// If it was mounted as read-only or read-write.
     pub read_write: ReadWrite,
@@ -60,7 +69,8 @@ impl Into<mountinfo::MountOptions> for MountOptions {
 }
 
 /// Exact copy of `mountinfo::FsType`, but implements `Clone` and other traits.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FsType {
     /// procfs filesystem. Pseudo filesystem that exposes the kernel's process table.
     /// Usually mounted at /proc.
@@ -118,7 +128,10 @@ impl Into<mountinfo::FsType> for FsType {
 }
 
 /// Exact copy of `mountinfo::MountPoint`, but implements `Clone`.
-#[derive(Debug, Clone, PartialEq)]
+///
+/// Ordered by path, then by id. Rest of the fields only break ties, so that the order agrees with `Eq`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MountPoint {
     /// The id of the mount point. It is unique for each mount point,
     /// but can be resused afer a call to the umount syscall.
@@ -136,6 +149,33 @@ pub struct MountPoint {
     /// Some additional mount options
     pub options: MountOptions,
 }
+impl Ord for MountPoint {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (&self.path, self.id)
+            .cmp(&(&other.path, other.id))
+            .then_with(|| {
+                (
+                    self.parent_id,
+                    &self.root,
+                    &self.what,
+                    &self.fstype,
+                    &self.options,
+                )
+                    .cmp(&(
+                        other.parent_id,
+                        &other.root,
+                        &other.what,
+                        &other.fstype,
+                        &other.options,
+                    ))
+            })
+    }
+}
+impl PartialOrd for MountPoint {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
 impl From<mountinfo::MountPoint> for MountPoint {
     fn from(value: mountinfo::MountPoint) -> Self {
         Self {
@@ -163,11 +203,74 @@ impl Into<mountinfo::MountPoint> for MountPoint {
     }
 }
 
+/// What made the monitor rescan `/proc/self/mountinfo` and find a change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChangeSource {
+    /// Mountpoints that already existed when the monitor started.
+    Initial,
+    /// Kernel reported a change of the mount table.
+    Kernel,
+    /// libmount reported a change of its userspace table (utab), e.g. `mount(8)` was used.
+    Userspace,
+    /// Consumer requested a rescan (see `RescanRequest`).
+    Rescan,
+}
+
+impl From<EventKind> for ChangeSource {
+    fn from(value: EventKind) -> Self {
+        match value {
+            EventKind::Kernel => ChangeSource::Kernel,
+            EventKind::Userspace => ChangeSource::Userspace,
+        }
+    }
+}
+
+/// Last field of `Added` and `Modified` is the inode of the network namespace an nsfs mount refers to.
+/// It is `None` for other mounts, and for nsfs mounts of other namespace types.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MountChange {
-    Added(Uuid, MountPoint),
-    Removed(Uuid),
-    Modified(Uuid, MountPoint),
+    Added(Uuid, MountPoint, ChangeSource, Option<INode>),
+    Removed(Uuid, ChangeSource),
+    Modified(Uuid, MountPoint, ChangeSource, Option<INode>),
+}
+
+impl MountChange {
+    /// Namespace of an nsfs mount is only resolved by `send_changes`.
+    fn added(uuid: Uuid, mount: MountPoint, source: ChangeSource) -> Self {
+        MountChange::Added(uuid, mount, source, None)
+    }
+
+    fn modified(uuid: Uuid, mount: MountPoint, source: ChangeSource) -> Self {
+        MountChange::Modified(uuid, mount, source, None)
+    }
+
+    pub fn source(&self) -> ChangeSource {
+        match self {
+            MountChange::Added(_, _, source, _)
+            | MountChange::Removed(_, source)
+            | MountChange::Modified(_, _, source, _) => *source,
+        }
+    }
+
+    /// Network namespace inode of an added or modified nsfs mount.
+    pub fn netns_inode(&self) -> Option<INode> {
+        match self {
+            MountChange::Added(_, _, _, inode) | MountChange::Modified(_, _, _, inode) => *inode,
+            MountChange::Removed(_, _) => None,
+        }
+    }
+}
+
+/// Resolves the namespace once here, so that consumers do not have to open the file again. Blocks on opening the file.
+fn netns_inode(mount: &MountPoint) -> Option<INode> {
+    if !is_nsfs(&mount.fstype) {
+        return None;
+    }
+
+    // Mount may already be gone by the time it is inspected.
+    netns_inode_of_nsfs_file(&mount.path).ok().flatten()
 }
 
 #[derive(Debug, Error)]
@@ -175,7 +278,7 @@ pub enum Error {
     #[error("io error - {0}")]
     Io(#[from] std::io::Error),
     #[error("send error - {0}")]
-    Send(#[from] SendError<MountChange>),
+    Send(#[from] Box<SendError<MountChange>>),
     #[error("libmount monitor has no file descriptor - {0}")]
     NoMonitorFd(std::io::Error),
 }
@@ -200,11 +303,10 @@ impl State {
         })
     }
 
-    /// Returns `false` if sending an event failed (sender is closed). `true` otherwise
-    pub fn update_mountinfo(
-        &mut self,
-        send_events: &mut Sender<MountChange>,
-    ) -> std::io::Result<bool> {
+    /// Rescans mountinfo, and returns changes since the last scan.
+    ///
+    /// Network namespaces of nsfs mounts are not resolved yet, see `send_changes`.
+    pub fn update_mountinfo(&mut self, source: ChangeSource) -> std::io::Result<Vec<MountChange>> {
         let rescanned: Vec<MountPoint> = MountInfo::new()?
             .mounting_points
             .into_iter()
@@ -222,6 +324,7 @@ impl State {
             }
         }
 
+        let mut changes = Vec::new();
         // This will become our new state:
         let mut new_map: HashMap<Uuid, MountPoint> = HashMap::new();
         // Track which old UUIDs we’ve seen again:
@@ -236,27 +339,15 @@ impl State {
                     // Did the mountpoint move paths?  Treat as remove + add
                     if mp.path != old_mp.path {
                         // Removal of the old
-                        if send_events.send(MountChange::Removed(uuid)).is_err() {
-                            return Ok(false);
-                        }
+                        changes.push(MountChange::Removed(uuid, source));
                         // Addition of the “new” mount
                         let new_uuid = Uuid::new_v4();
-                        if send_events
-                            .send(MountChange::Added(new_uuid, mp.clone()))
-                            .is_err()
-                        {
-                            return Ok(false);
-                        }
+                        changes.push(MountChange::added(new_uuid, mp.clone(), source));
                         new_map.insert(new_uuid, mp);
                     }
                     // Same path but other metadata changed?
                     else if &mp != old_mp {
-                        if send_events
-                            .send(MountChange::Modified(uuid, mp.clone()))
-                            .is_err()
-                        {
-                            return Ok(false);
-                        }
+                        changes.push(MountChange::modified(uuid, mp.clone(), source));
                         new_map.insert(uuid, mp);
                     }
                     // Unchanged
@@ -272,24 +363,14 @@ impl State {
             if let Some(&uuid) = old_by_path.get(&mp.path) {
                 let old_mp = &self.mountinfo[&uuid];
                 if &mp != old_mp {
-                    if send_events
-                        .send(MountChange::Modified(uuid, mp.clone()))
-                        .is_err()
-                    {
-                        return Ok(false);
-                    }
+                    changes.push(MountChange::modified(uuid, mp.clone(), source));
                 }
                 new_map.insert(uuid, mp);
                 seen_old.insert(uuid);
             } else {
                 // Entirely new mount
                 let uuid = Uuid::new_v4();
-                if send_events
-                    .send(MountChange::Added(uuid, mp.clone()))
-                    .is_err()
-                {
-                    return Ok(false);
-                }
+                changes.push(MountChange::added(uuid, mp.clone(), source));
                 new_map.insert(uuid, mp);
             }
         }
@@ -297,69 +378,170 @@ impl State {
         // 4. Anything in the old state we *didn't* see above has been removed:
         for (&uuid, _) in &self.mountinfo {
             if !seen_old.contains(&uuid) {
-                if send_events.send(MountChange::Removed(uuid)).is_err() {
-                    return Ok(false);
-                }
+                changes.push(MountChange::Removed(uuid, source));
             }
         }
 
         // 5. Replace state
         self.mountinfo = new_map;
 
-        Ok(true)
+        Ok(changes)
     }
 
-    /// Sends all the stored mountpoints as newly `MountChange::Added`.
-    ///
-    /// Returns `false` if sending an event failed (sender is closed). `true` otherwise
-    pub fn send_mountinfo(&self, send_events: &mut Sender<MountChange>) -> bool {
-        for (uuid, mount) in &self.mountinfo {
-            let change: MountChange = MountChange::Added(*uuid, mount.clone());
-            if send_events.send(change).is_err() {
-                return false;
-            }
+    /// All the stored mountpoints as newly `MountChange::Added`.
+    pub fn initial_changes(&self) -> Vec<MountChange> {
+        self.mountinfo
+            .iter()
+            .map(|(uuid, mount)| MountChange::added(*uuid, mount.clone(), ChangeSource::Initial))
+            .collect()
+    }
+}
+
+/// Resolves network namespaces of nsfs mounts among `changes`, then sends all of them.
+///
+/// Resolving opens the mounted files, so it is done in one go on a blocking thread, and only if there are nsfs mounts.
+/// Returns `false` if sending an event failed (sender is closed). `true` otherwise
+async fn send_changes(
+    send_events: &mut CountingSender<MountChange>,
+    mut changes: Vec<MountChange>,
+) -> std::io::Result<bool> {
+    let has_nsfs = changes.iter().any(|change| match change {
+        MountChange::Added(_, mount, _, _) | MountChange::Modified(_, mount, _, _) => {
+            is_nsfs(&mount.fstype)
         }
+        MountChange::Removed(_, _) => false,
+    });
+    if has_nsfs {
+        changes = tokio::task::spawn_blocking(move || {
+            for change in &mut changes {
+                if let MountChange::Added(_, mount, _, inode)
+                | MountChange::Modified(_, mount, _, inode) = change
+                {
+                    *inode = netns_inode(mount);
+                }
+            }
+            changes
+        })
+        .await
+        .map_err(std::io::Error::other)?;
+    }
 
-        true
+    for change in changes {
+        if send_events.send(change).is_err() {
+            return Ok(false);
+        }
     }
+
+    Ok(true)
+}
+
+/// Requests an immediate rescan of `/proc/self/mountinfo`, even if libmount did not report any change.
+/// Changes found are sent as usual `MountChange` events.
+pub type RescanRequest = ();
+
+/// Starts watching the mount table, see [`MountMonitor`].
+pub fn monitor_mountinfo()
+-> Result<MountMonitor<impl Send + Future<Output = Result<(), Error>>>, Error> {
+    start_monitor(true)
 }
 
-pub fn monitor_mountinfo() -> Result<
-    (
-        Receiver<MountChange>,
-        impl Send + Future<Output = Result<(), Error>>,
-    ),
-    Error,
-> {
+/// Same as `monitor_mountinfo`, but only libmount's userspace table (utab) is watched, not the kernel mount table.
+///
+/// Mounts made without `mount(8)` are only noticed on a `RescanRequest`. On hosts where mounts change all the
+/// time (e.g. many containers starting), this avoids a rescan of `/proc/self/mountinfo` on every one of them.
+pub fn monitor_userspace_mountinfo()
+-> Result<MountMonitor<impl Send + Future<Output = Result<(), Error>>>, Error> {
+    start_monitor(false)
+}
+
+/// Requests the whole current mount table, answered with a `MountSnapshot`.
+pub type SnapshotRequest = ();
+
+/// All mountpoints known to the monitor, under the same UUIDs its `MountChange` events use.
+pub type MountSnapshot = HashMap<Uuid, MountPoint>;
+
+/// Started mount monitor.
+#[derive(Debug)]
+pub struct MountMonitor<F> {
+    pub events: Receiver<MountChange>,
+    /// Requests a rescan, see [`RescanRequest`].
+    pub rescan: Sender<RescanRequest>,
+    /// Requests the current mount table, which is then sent to `snapshots`.
+    ///
+    /// Snapshots are answered from the state of the monitor, `/proc/self/mountinfo` is not read again.
+    /// Since requests and changes are processed in order, a snapshot reflects every change sent before it.
+    pub snapshot: Sender<SnapshotRequest>,
+    pub snapshots: Receiver<MountSnapshot>,
+    pub stats: Arc<MonitorStats>,
+    /// Drives the monitor loop.
+    pub fut: F,
+}
+
+/// Kernel mount table is only watched if `kernel_events` is set.
+fn start_monitor(
+    kernel_events: bool,
+) -> Result<MountMonitor<impl Send + Future<Output = Result<(), Error>>>, Error> {
     let mut monitor = SendMonitor::new();
-    monitor.enable_kernel(true)?;
+    monitor.enable_kernel(kernel_events)?;
     monitor.enable_userspace(true, None)?;
     let (mut mount_stream, mount_fut) = monitor.stream()?;
 
-    let (mut send, recv) = tokio::sync::broadcast::channel(1024);
+    let (send, recv) = tokio::sync::broadcast::channel(1024);
+    let mut send = CountingSender::new(send);
+    let stats = send.stats();
+    let (rescan_tx, mut rescan_rx) = tokio::sync::broadcast::channel::<RescanRequest>(1024);
+    let (snapshot_tx, mut snapshot_rx) = tokio::sync::broadcast::channel::<SnapshotRequest>(1024);
+    let (snapshots_tx, snapshots_rx) = tokio::sync::broadcast::channel::<MountSnapshot>(16);
 
     let mut state = State::new()?;
 
     let fut = async move {
         let mount_fut = tokio::spawn(mount_fut);
 
-        let mut should_run = true;
-        if !state.send_mountinfo(&mut send) {
-            should_run = false;
-        }
+        let should_run = send_changes(&mut send, state.initial_changes()).await?;
+
+        // Consumer may not be interested in rescans at all, and drop the sender.
+        let mut rescans_open = true;
+        let mut snapshots_open = true;
 
         'main: while should_run {
             tokio::select! {
                 _ = send.closed() => break 'main,
 
+                request = rescan_rx.recv(), if rescans_open => {
+                    match request {
+                        // Lagging behind on requests still means a rescan was requested.
+                        Ok(()) | Err(RecvError::Lagged(_)) => {
+                            let changes = state.update_mountinfo(ChangeSource::Rescan)?;
+                            if !send_changes(&mut send, changes).await? {
+                                break 'main;
+                            }
+                        }
+                        Err(RecvError::Closed) => rescans_open = false,
+                    }
+                }
+
+                request = snapshot_rx.recv(), if snapshots_open => {
+                    match request {
+                        // Several requests missed at once are answered with a single snapshot.
+                        Ok(()) | Err(RecvError::Lagged(_)) => {
+                            // Nobody listening for snapshots is not a reason to stop sending changes.
+                            let _ = snapshots_tx.send(state.mountinfo.clone());
+                        }
+                        Err(RecvError::Closed) => snapshots_open = false,
+                    }
+                }
+
                 result = mount_stream.recv() => {
                     let Ok(event) = result else {
                         break 'main;
                     };
                     let mount_file = event.path;
 
-                    if mount_file == PathBuf::from_str("/proc/self/mountinfo").unwrap() {
-                        if !state.update_mountinfo(&mut send)? {
+                    // Userspace events come from libmount's utab, kernel ones always from mountinfo.
+                    if event.kind == EventKind::Userspace || mount_file == PathBuf::from_str("/proc/self/mountinfo").unwrap() {
+                        let changes = state.update_mountinfo(event.kind.into())?;
+                        if !send_changes(&mut send, changes).await? {
                             break 'main;
                         }
                     } else {
@@ -376,5 +558,12 @@ pub fn monitor_mountinfo() -> Result<
         Ok(())
     };
 
-    Ok((recv, fut))
+    Ok(MountMonitor {
+        events: recv,
+        rescan: rescan_tx,
+        snapshot: snapshot_tx,
+        snapshots: snapshots_rx,
+        stats,
+        fut,
+    })
 }
@@ -1,23 +1,65 @@
 use std::{
     any::Any,
-    net::{Ipv4Addr, Ipv6Addr},
-    os::fd::AsRawFd,
-    path::PathBuf,
+    collections::{HashMap, HashSet, hash_map::Entry},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    os::{fd::AsRawFd, unix::fs::MetadataExt},
+    path::{Path, PathBuf},
+    ptr::null,
+    time::Duration,
 };
 
-use futures::TryStreamExt;
-use libc::CLONE_NEWNET;
-use rtnetlink::packet_route::link::LinkMessage;
+use bytes::BytesMut;
+use futures::{StreamExt, TryStreamExt};
+use libc::{CLONE_NEWNET, RTNLGRP_LINK};
+use netlink_proto::{
+    NetlinkCodec, NetlinkMessageCodec,
+    sys::{TokioSocket, protocols::NETLINK_ROUTE},
+};
+use rtnetlink::{
+    LinkDummy, LinkUnspec,
+    packet_core::{
+        NetlinkBuffer, NetlinkDeserializable, NetlinkMessage, NetlinkPayload, NetlinkSerializable,
+    },
+    packet_route::RouteNetlinkMessage,
+    packet_route::address::{AddressAttribute, AddressMessage},
+    packet_route::link::{
+        self, InfoData, InfoIpVlan, InfoIpVtap, InfoKind, InfoMacVlan, InfoMacVtap, InfoVlan,
+        InfoVxlan, LinkAttribute, LinkFlags, LinkInfo, LinkLayerType, LinkMessage, State, Stats,
+        Stats64,
+    },
+    packet_utils::nla::Nla,
+    sys::{AsyncSocket, SocketAddr},
+};
 use thiserror::Error;
-use tokio::task::LocalSet;
+use tokio::{task::LocalSet, time::Instant};
 
-use crate::netns::INode;
+use crate::{
+    netns::{INode, NetworkNamespace, NsId, Pid},
+    wireguard::{self, WgPeer},
+};
 
+/// Device another one is linked to, possibly in another network namespace.
+///
+/// A veth peer in another namespace is only known by its NETNSID, which is relative to the namespace of the veth.
+/// It is resolved against the namespace of the calling thread, so it is only resolved for devices of that namespace.
+/// For devices of other namespaces, such a peer has an empty name, and `netns` of the device itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PeerRef {
-    name: String,
-    netns: INode,
+    /// Empty, if the device could not be found.
+    pub name: String,
+    pub netns: INode,
 }
 
+/// With the `serde` feature, it is serialized with a `"kind"` tag next to the fields of the variant,
+/// named like the kernel kinds (`{"kind": "veth", "peer": ...}`). `Tun` and `Tap` details are in an `info`
+/// field, and name of an `Other` kind is in a `name` field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "kind", rename_all = "lowercase")
+)]
 pub enum Kind {
     Ethernet,
     Wifi,
@@ -25,34 +67,254 @@ pub enum Kind {
     Ppp,
     Slip,
     Loopback,
-    Veth { peer: PeerRef },
-    Bridge { ports: Vec<PeerRef> },
-    Bond { slaves: Vec<PeerRef> },
-    Vlan { id: u16, parent: PeerRef },
-    MacVlan { parent: PeerRef },
-    IpVlan { parent: PeerRef },
-    Vxlan { vni: u32 },
-    Tun,
-    Tap,
-    Gre,
-    Wireguard,
+    Veth {
+        peer: PeerRef,
+    },
+    Bridge {
+        ports: Vec<PeerRef>,
+    },
+    Bond {
+        slaves: Vec<PeerRef>,
+    },
+    Vlan {
+        id: u16,
+        parent: PeerRef,
+    },
+    MacVlan {
+        parent: PeerRef,
+        mode: MacVlanMode,
+    },
+    IpVlan {
+        parent: PeerRef,
+        mode: IpVlanMode,
+    },
+    Vxlan {
+        vni: u32,
+    },
+    /// `None` if the kernel did not report tun details.
+    #[cfg_attr(feature = "serde", serde(with = "serde_repr::tun_info"))]
+    Tun(Option<TunInfo>),
+    /// `None` if the kernel did not report tap details.
+    #[cfg_attr(feature = "serde", serde(with = "serde_repr::tun_info"))]
+    Tap(Option<TunInfo>),
+    /// `ip6gre` and `ip6gretap` tunnels have IPv6 endpoints, other GRE ones IPv4.
+    Gre {
+        /// `None` if the tunnel is not bound to a local address.
+        local: Option<IpAddr>,
+        /// `None` if the tunnel has no fixed remote end (point-to-multipoint).
+        remote: Option<IpAddr>,
+    },
+    /// Listen port and peers are only filled by full namespace queries ([`DeviceInfo::of_namespace`]),
+    /// and stay empty if the `wireguard` module is not loaded.
+    Wireguard {
+        listen_port: u16,
+        peers: Vec<WgPeer>,
+    },
 
+    #[cfg_attr(feature = "serde", serde(with = "serde_repr::other_name"))]
     Other(String),
 }
 
+/// Ownership and flags of a tun or tap device (`IFLA_TUN_*`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TunInfo {
+    /// Only this user may attach to the device. `None` if not restricted.
+    pub owner: Option<u32>,
+    /// Only members of this group may attach to the device. `None` if not restricted.
+    pub group: Option<u32>,
+    /// Packets are prefixed with a packet information header (`IFF_NO_PI` is not set).
+    pub packet_info: bool,
+    /// Packets are prefixed with a virtio-net header (`IFF_VNET_HDR`).
+    pub vnet_hdr: bool,
+    /// Device has multiple queues (`IFF_MULTI_QUEUE`).
+    pub multi_queue: bool,
+    /// Device outlives the process that created it (`TUNSETPERSIST`).
+    pub persistent: bool,
+}
+
+/// How a macvlan (or macvtap) device forwards traffic between itself and other macvlans of the same parent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MacVlanMode {
+    /// No traffic between macvlans of the parent.
+    Private,
+    /// Traffic between macvlans goes through the external switch. Kernel default.
+    #[default]
+    Vepa,
+    /// Traffic between macvlans is switched directly.
+    Bridge,
+    /// The only macvlan of the parent, taking over the parent completely.
+    Passthru,
+    /// Only traffic from listed source MAC addresses is accepted.
+    Source,
+}
+impl From<link::MacVlanMode> for MacVlanMode {
+    fn from(value: link::MacVlanMode) -> Self {
+        match value {
+            link::MacVlanMode::Private => MacVlanMode::Private,
+            link::MacVlanMode::Vepa => MacVlanMode::Vepa,
+            link::MacVlanMode::Bridge => MacVlanMode::Bridge,
+            link::MacVlanMode::Passthrough => MacVlanMode::Passthru,
+            link::MacVlanMode::Source => MacVlanMode::Source,
+            _ => MacVlanMode::default(),
+        }
+    }
+}
+
+/// Layer an ipvlan (or ipvtap) device switches traffic at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IpVlanMode {
+    L2,
+    /// Kernel default.
+    #[default]
+    L3,
+    /// L3, with traffic going through netfilter of the namespace of the device.
+    L3S,
+}
+impl From<link::IpVlanMode> for IpVlanMode {
+    fn from(value: link::IpVlanMode) -> Self {
+        match value {
+            link::IpVlanMode::L2 => IpVlanMode::L2,
+            link::IpVlanMode::L3 => IpVlanMode::L3,
+            link::IpVlanMode::L3S => IpVlanMode::L3S,
+            _ => IpVlanMode::default(),
+        }
+    }
+}
+
+/// Operational state of a device (`IFLA_OPERSTATE`, RFC 2863).
+///
+/// Unlike the administrative `is_up` flag, it also reflects the carrier state:
+/// an admin-up device with an unplugged cable is `LowerLayerDown` or `Down`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OperState {
+    Unknown,
+    NotPresent,
+    Down,
+    LowerLayerDown,
+    Testing,
+    Dormant,
+    Up,
+}
+impl From<State> for OperState {
+    fn from(value: State) -> Self {
+        match value {
+            State::Unknown => OperState::Unknown,
+            State::NotPresent => OperState::NotPresent,
+            State::Down => OperState::Down,
+            State::LowerLayerDown => OperState::LowerLayerDown,
+            State::Testing => OperState::Testing,
+            State::Dormant => OperState::Dormant,
+            State::Up => OperState::Up,
+            _ => OperState::Unknown,
+        }
+    }
+}
+
+/// Extracts operational state from link attributes. Defaults to `Unknown` when kernel did not report it.
+pub fn oper_state_from_attrs(attrs: &[LinkAttribute]) -> OperState {
+    attrs
+        .iter()
+        .find_map(|attr| match attr {
+            LinkAttribute::OperState(state) => Some(OperState::from(*state)),
+            _ => None,
+        })
+        .unwrap_or(OperState::Unknown)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Duplex {
+    Half,
+    Full,
+}
+
 pub type Mac = [u8; 6];
 pub type Ipv4Mask = [u8; 4];
 pub type Ipv6Mask = [u8; 16];
 
+/// Formats a hardware address the canonical way, as lowercase `aa:bb:cc:dd:ee:ff`.
+pub fn mac_to_string(mac: Mac) -> String {
+    mac.map(|byte| format!("{byte:02x}")).join(":")
+}
+
+/// Parses a hardware address formatted as `aa:bb:cc:dd:ee:ff` (in any case).
+pub fn mac_from_str(s: &str) -> Option<Mac> {
+    let mut mac = [0; 6];
+    let mut parts = s.split(':');
+    for byte in &mut mac {
+        let part = parts.next()?;
+        if part.len() != 2 {
+            return None;
+        }
+        *byte = u8::from_str_radix(part, 16).ok()?;
+    }
+    parts.next().is_none().then_some(mac)
+}
+
+/// Network address of the subnet an IPv4 address belongs to.
+pub fn ipv4_network(addr: Ipv4Addr, mask: Ipv4Mask) -> Ipv4Addr {
+    Ipv4Addr::from(u32::from(addr) & u32::from_be_bytes(mask))
+}
+
+/// Broadcast address of the subnet an IPv4 address belongs to.
+pub fn ipv4_broadcast(addr: Ipv4Addr, mask: Ipv4Mask) -> Ipv4Addr {
+    Ipv4Addr::from(u32::from(addr) | !u32::from_be_bytes(mask))
+}
+
+/// Network address (prefix) of the subnet an IPv6 address belongs to.
+pub fn ipv6_network(addr: Ipv6Addr, mask: Ipv6Mask) -> Ipv6Addr {
+    Ipv6Addr::from(u128::from(addr) & u128::from_be_bytes(mask))
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DeviceInfo {
     pub kind: Kind,
     pub name: String,
+    /// Interface index. Only unique within the network namespace.
+    pub index: u32,
+    /// Index of the device this one is enslaved to (bridge, bond, ...), in the same network namespace.
+    pub master_index: Option<u32>,
+    /// Serialized as a `aa:bb:cc:dd:ee:ff` string, with the `serde` feature.
+    #[cfg_attr(feature = "serde", serde(with = "serde_repr::mac"))]
     pub mac_addr: Option<Mac>,
     pub ipv4_addrs: Vec<(Ipv4Addr, Ipv4Mask)>,
     pub ipv6_addrs: Vec<(Ipv6Addr, Ipv6Mask)>,
     pub netns: INode,
+    /// Administrative state (`IFF_UP`).
     pub is_up: bool,
+    pub oper_state: OperState,
     pub is_virtual: bool,
+    /// Link speed in Mbit/s. Not known for virtual devices, and devices without carrier.
+    ///
+    /// Speed and duplex are only read when asked for, with [`DeviceQuery::with_link_modes`].
+    pub speed_mbps: Option<u32>,
+    pub duplex: Option<Duplex>,
+    pub mtu: Option<u32>,
+    /// Device group, for bulk changes with `ip link set group`. Devices are in group 0 (`default`) unless moved.
+    pub group: u32,
+    /// Name of the kernel driver (`e1000e`, `veth`, ...), from ethtool. `None` if the device does not support ethtool.
+    pub driver: Option<String>,
+    /// Bus address of the hardware (`0000:00:1f.6` for PCI), from ethtool. `None` for virtual devices.
+    pub bus_info: Option<String>,
+    /// Counters at the moment of the query. `None` if the kernel did not report any.
+    pub stats: Option<LinkStats>,
+}
+
+/// Traffic counters of a device, since it was created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LinkStats {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    pub rx_errors: u64,
+    pub tx_errors: u64,
 }
 
 type ThreadError = Box<dyn Any + Send + 'static>;
@@ -61,21 +323,910 @@ type ThreadError = Box<dyn Any + Send + 'static>;
 pub enum Error {
     #[error("io error - {0}")]
     Io(#[from] std::io::Error),
+    #[error("process {0} does not exist")]
+    NoSuchProcess(Pid),
+    #[error("failed to query network namespace - {0}")]
+    Query(#[from] QueryError),
+    #[error("rtnetlink error - {0}")]
+    Netlink(#[from] rtnetlink::Error),
+    #[error("device with index {0} does not exist")]
+    NoSuchDevice(u32),
+    #[error("address {0} is already assigned to device with index {1}")]
+    AddressExists(IpAddr, u32),
+    #[error("device {0} does not exist")]
+    NoSuchDeviceName(String),
+    #[error("kernel did not report statistics of device {0}")]
+    NoStats(String),
+    #[error("device {0} did not appear in {1:?}")]
+    DeviceTimeout(String, Duration),
+    #[error("statistics can not be sampled over a zero interval")]
+    ZeroInterval,
 }
 
+/// Devices of a single network namespace (identified by the file it was queried with),
+/// or the reason they could not be queried.
+pub type NamespaceDevices = (PathBuf, Result<Vec<DeviceInfo>, QueryError>);
+
 impl DeviceInfo {
+    /// Lists devices of all the given network namespaces.
+    ///
+    /// Namespaces that could not be queried (for example, ones destroyed since they were enumerated) are skipped.
+    /// Use [`DeviceInfo::all_per_namespace`] to know about them.
     pub async fn all(
-        _network_namespaces_files: impl IntoIterator<Item = PathBuf>,
-    ) -> Result<Vec<DeviceInfo>, Error> {
-        // Check devices in /sys/class/net
-        // For virtual devices, check /sys/devices/virtual/net
+        network_namespaces_files: impl IntoIterator<Item = PathBuf>,
+    ) -> Vec<DeviceInfo> {
+        Self::all_per_namespace(network_namespaces_files)
+            .await
+            .into_iter()
+            .filter_map(|(_netns_file, result)| result.ok())
+            .flatten()
+            .collect()
+    }
+
+    /// Lists devices of each given network namespace separately, so that failing to query
+    /// one of the namespaces does not affect the others.
+    pub async fn all_per_namespace(
+        network_namespaces_files: impl IntoIterator<Item = PathBuf>,
+    ) -> Vec<NamespaceDevices> {
+        let queries = network_namespaces_files
+            .into_iter()
+            .map(async |netns_file| (netns_file.clone(), Self::of_namespace(netns_file).await));
+
+        futures::future::join_all(queries).await
+    }
+
+    /// Same as `all_per_namespace`, but skips namespaces whose only device is loopback,
+    /// which is the case for every namespace nothing was set up in yet.
+    ///
+    /// Namespaces that could not be queried are kept, since it is not known what they contain.
+    pub async fn active_per_namespace(
+        network_namespaces_files: impl IntoIterator<Item = PathBuf>,
+    ) -> Vec<NamespaceDevices> {
+        let mut namespaces = Self::all_per_namespace(network_namespaces_files).await;
+        namespaces.retain(|(_netns_file, result)| match result {
+            Ok(devices) => devices
+                .iter()
+                .any(|device| !matches!(device.kind, Kind::Loopback)),
+            Err(_) => true,
+        });
+        namespaces
+    }
+
+    /// Lists devices of a single network namespace.
+    pub async fn of_namespace(netns_filepath: PathBuf) -> Result<Vec<DeviceInfo>, QueryError> {
+        let devices = Self::of_namespace_with_raw(netns_filepath).await?;
+        Ok(devices.into_iter().map(|(device, _link)| device).collect())
+    }
+
+    /// Same as `of_namespace`, but each device comes with the raw rtnetlink message it was parsed from,
+    /// for attributes that `DeviceInfo` does not have.
+    pub async fn of_namespace_with_raw(
+        netns_filepath: PathBuf,
+    ) -> Result<Vec<(DeviceInfo, LinkMessage)>, QueryError> {
+        Self::query_namespace(netns_filepath, false).await
+    }
+
+    /// Reading speed and duplex takes a private mount namespace and a fresh sysfs mount,
+    /// so it is only done when `link_modes` is set, and then failing to do so fails the namespace.
+    async fn query_namespace(
+        netns_filepath: PathBuf,
+        link_modes: bool,
+    ) -> Result<Vec<(DeviceInfo, LinkMessage)>, QueryError> {
+        let netns = tokio::fs::metadata(&netns_filepath)
+            .await
+            .map_err(QueryError::CoulndtOpenNetns)?
+            .ino();
+
+        // Partially received list of devices is still better than none.
+        let (links, addresses) = query_netns_links_and_addresses(netns_filepath.clone()).await?;
+        let (links, addresses) = (links.items, addresses.items);
+        let link_modes = if link_modes {
+            query_netns_link_modes(netns_filepath.clone()).await?
+        } else {
+            HashMap::new()
+        };
+
+        let mut devices: Vec<(DeviceInfo, LinkMessage)> = links
+            .into_iter()
+            .map(|link| (DeviceInfo::from_link(netns, &link), link))
+            .collect();
+
+        // Driver info is only nice to have, do not fail the whole namespace because of it.
+        let names = devices
+            .iter()
+            .map(|(device, _link)| device.name.clone())
+            .collect();
+        let mut driver_infos = query_netns_driver_info(netns_filepath.clone(), names)
+            .await
+            .unwrap_or_default();
+
+        for (device, _link) in &mut devices {
+            if let Some(&(speed_mbps, duplex)) = link_modes.get(&device.name) {
+                device.speed_mbps = speed_mbps;
+                device.duplex = duplex;
+            }
+            if let Some((driver, bus_info)) = driver_infos.remove(&device.name) {
+                device.driver = driver;
+                device.bus_info = bus_info;
+            }
+        }
 
-        todo!()
+        resolve_veth_peers(netns, &mut devices).await;
+        resolve_parents(&mut devices);
+        resolve_ports(&mut devices);
+        resolve_wireguard(netns_filepath, &mut devices).await;
 
-        // TODO: network device packet sniffer
+        for address in &addresses {
+            let Some((device, _link)) = devices
+                .iter_mut()
+                .find(|(device, _link)| device.index == address.header.index)
+            else {
+                continue;
+            };
+            device.add_address(address);
+        }
+
+        Ok(devices)
+    }
+
+    /// Gets a single device of a network namespace by its name. Returns `None` if there is no such device.
+    ///
+    /// Only the device itself is queried, so its peer, parent, ports or slaves have no names
+    /// (see [`kind_from_link`]), and speed, duplex and driver are not known. Use `of_namespace` for those.
+    pub async fn by_name(netns_filepath: PathBuf, name: &str) -> Result<Option<DeviceInfo>, Error> {
+        let netns = tokio::fs::metadata(&netns_filepath)
+            .await
+            .map_err(QueryError::CoulndtOpenNetns)?
+            .ino();
+
+        let name = name.to_owned();
+        let found = run_netlink_in_netns(netns_filepath, async move |handle| {
+            let link = match handle
+                .link()
+                .get()
+                .match_name(name)
+                .execute()
+                .try_next()
+                .await
+            {
+                Ok(Some(link)) => link,
+                Ok(None) => return Ok(None),
+                Err(err) if netlink_errno(&err) == Some(libc::ENODEV) => return Ok(None),
+                Err(err) => return Err(err.into()),
+            };
+            // Addresses are only nice to have, same as in `of_namespace`.
+            let addresses = collect_dump(
+                handle
+                    .address()
+                    .get()
+                    .set_link_index_filter(link.header.index)
+                    .execute(),
+            )
+            .await
+            .map(|dump| dump.items)
+            .unwrap_or_default();
+
+            Ok(Some((link, addresses)))
+        })
+        .await?;
+
+        Ok(found.map(|(link, addresses)| {
+            let mut device = DeviceInfo::from_link(netns, &link);
+            for address in &addresses {
+                device.add_address(address);
+            }
+            device
+        }))
+    }
+
+    fn add_address(&mut self, address: &AddressMessage) {
+        match address_from_message(address) {
+            Some(IpAddr::V4(addr)) => self
+                .ipv4_addrs
+                .push((addr, ipv4_mask(address.header.prefix_len))),
+            Some(IpAddr::V6(addr)) => self
+                .ipv6_addrs
+                .push((addr, ipv6_mask(address.header.prefix_len))),
+            None => {}
+        }
+    }
+
+    fn from_link(netns: INode, link: &LinkMessage) -> DeviceInfo {
+        let attrs = &link.attributes;
+
+        let name = attrs
+            .iter()
+            .find_map(|attr| match attr {
+                LinkAttribute::IfName(name) => Some(name.clone()),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let master_index = attrs.iter().find_map(|attr| match attr {
+            LinkAttribute::Controller(index) => Some(*index),
+            _ => None,
+        });
+        let mtu = attrs.iter().find_map(|attr| match attr {
+            LinkAttribute::Mtu(mtu) => Some(*mtu),
+            _ => None,
+        });
+        let info_kind = info_kind_from_attrs(attrs);
+
+        DeviceInfo {
+            kind: kind_from_link(netns, link),
+            name,
+            index: link.header.index,
+            master_index,
+            mac_addr: mac_from_attrs(attrs),
+            ipv4_addrs: vec![],
+            ipv6_addrs: vec![],
+            netns,
+            is_up: link.header.flags.contains(LinkFlags::Up),
+            oper_state: oper_state_from_attrs(attrs),
+            // Physical devices are not created with a kind, so kernel does not report one for them.
+            is_virtual: info_kind.is_some()
+                || link.header.link_layer_type == LinkLayerType::Loopback,
+            speed_mbps: None,
+            duplex: None,
+            mtu,
+            group: group_from_attrs(attrs),
+            driver: None,
+            bus_info: None,
+            stats: link_stats_from_attrs(attrs),
+        }
     }
 }
 
+/// Fills listen port and peers of WireGuard devices, which are only known over generic netlink.
+///
+/// Devices that could not be queried keep their empty placeholders.
+async fn resolve_wireguard(netns_filepath: PathBuf, devices: &mut [(DeviceInfo, LinkMessage)]) {
+    let indices: Vec<u32> = devices
+        .iter()
+        .filter(|(device, _link)| matches!(device.kind, Kind::Wireguard { .. }))
+        .map(|(device, _link)| device.index)
+        .collect();
+    if indices.is_empty() {
+        return;
+    }
+
+    let queried = in_namespace(netns_filepath, move || async move {
+        wireguard::query_devices(&indices)
+            .await
+            .map_err(QueryError::Wireguard)
+    })
+    .await;
+    let queried = match queried {
+        Ok(Some(queried)) => queried,
+        // Module is not loaded, nothing to fill.
+        Ok(None) => return,
+        Err(err) => {
+            eprintln!("[net_device] WARN could not query wireguard devices - {err}");
+            return;
+        }
+    };
+
+    for (index, result) in queried {
+        let wg = match result {
+            Ok(wg) => wg,
+            Err(err) => {
+                eprintln!("[net_device] WARN could not query wireguard device {index} - {err}");
+                continue;
+            }
+        };
+        if let Some((device, _link)) = devices
+            .iter_mut()
+            .find(|(device, _link)| device.index == index)
+        {
+            device.kind = Kind::Wireguard {
+                listen_port: wg.listen_port,
+                peers: wg.peers,
+            };
+        }
+    }
+}
+
+/// Fills peers of veth devices (placeholders, as returned by `kind_from_link`).
+async fn resolve_veth_peers(netns: INode, devices: &mut [(DeviceInfo, LinkMessage)]) {
+    let mut peer_namespaces = None;
+
+    for i in 0..devices.len() {
+        let (device, link) = &devices[i];
+        if !matches!(device.kind, Kind::Veth { .. }) {
+            continue;
+        }
+
+        let peer_index = link.attributes.iter().find_map(|attr| match attr {
+            LinkAttribute::Link(index) => Some(*index),
+            _ => None,
+        });
+        let peer_nsid = link.attributes.iter().find_map(|attr| match attr {
+            LinkAttribute::LinkNetNsId(id) => u32::try_from(*id).ok(),
+            _ => None,
+        });
+
+        let peer = match (peer_index, peer_nsid) {
+            // Peer is in the same namespace.
+            (Some(index), None) => PeerRef {
+                name: devices
+                    .iter()
+                    .find(|(device, _link)| device.index == index)
+                    .map(|(device, _link)| device.name.clone())
+                    .unwrap_or_default(),
+                netns,
+            },
+            (Some(index), Some(id)) => {
+                // Ids are only looked up once, and only if some veth needs them.
+                if peer_namespaces.is_none() {
+                    peer_namespaces = Some(PeerNamespaces::new(netns).await);
+                }
+                let peer_namespaces = peer_namespaces.as_mut().unwrap();
+
+                // Peer is in a namespace that is not visible from here, which is normal for containers.
+                peer_namespaces.peer(id, index).await.unwrap_or(PeerRef {
+                    name: String::new(),
+                    netns,
+                })
+            }
+            (None, _) => PeerRef {
+                name: String::new(),
+                netns,
+            },
+        };
+
+        devices[i].0.kind = Kind::Veth { peer };
+    }
+}
+
+/// Namespaces of veth peers, by NETNSID, with names of their devices by index.
+///
+/// NETNSIDs are only meaningful relative to the namespace that assigned them, and they are resolved relative
+/// to the current one. So only peers of devices in the current namespace are resolved (see [`PeerRef`]).
+/// Each namespace has its devices queried once, on the first peer looked up in it.
+struct PeerNamespaces {
+    by_id: HashMap<NsId, NetworkNamespace>,
+    device_names: HashMap<NsId, HashMap<u32, String>>,
+}
+
+impl PeerNamespaces {
+    async fn new(netns: INode) -> Self {
+        let own_netns = tokio::fs::metadata("/proc/self/ns/net")
+            .await
+            .map(|meta| meta.ino());
+        let resolvable = own_netns.is_ok_and(|own_netns| own_netns == netns);
+
+        let mut by_id = HashMap::new();
+        if resolvable {
+            // A single scan (and rtnetlink connection) gives ids of all the namespaces at once.
+            match NetworkNamespace::all().await {
+                Ok(namespaces) => {
+                    by_id = namespaces
+                        .into_iter()
+                        .filter_map(|netns| Some((netns.id?, netns)))
+                        .collect();
+                }
+                Err(err) => {
+                    eprintln!("[net_device] WARN could not list namespaces of veth peers - {err}")
+                }
+            }
+        }
+
+        Self {
+            by_id,
+            device_names: HashMap::new(),
+        }
+    }
+
+    /// Finds a device by index in a namespace, given by its NETNSID.
+    async fn peer(&mut self, id: NsId, index: u32) -> Option<PeerRef> {
+        let peer_netns = self.by_id.get(&id)?;
+
+        if let Entry::Vacant(entry) = self.device_names.entry(id) {
+            let links = query_netns_links(peer_netns.any_file()?).await.ok()?;
+            let names = links
+                .items
+                .iter()
+                .filter_map(|link| {
+                    link.attributes.iter().find_map(|attr| match attr {
+                        LinkAttribute::IfName(name) => Some((link.header.index, name.clone())),
+                        _ => None,
+                    })
+                })
+                .collect();
+            entry.insert(names);
+        }
+
+        Some(PeerRef {
+            name: self.device_names[&id]
+                .get(&index)
+                .cloned()
+                .unwrap_or_default(),
+            netns: peer_netns.inode,
+        })
+    }
+}
+
+/// Fills parents of VLAN, MACVLAN and IPVLAN devices (placeholders, as returned by `kind_from_link`).
+///
+/// Parent is looked up in the same namespace. If it is not there (it is in another namespace, or was not enumerated),
+/// the device keeps its kind, with a parent without a name.
+fn resolve_parents(devices: &mut [(DeviceInfo, LinkMessage)]) {
+    let names: HashMap<u32, String> = devices
+        .iter()
+        .map(|(device, _link)| (device.index, device.name.clone()))
+        .collect();
+
+    for (device, link) in devices {
+        let (Kind::Vlan { parent, .. }
+        | Kind::MacVlan { parent, .. }
+        | Kind::IpVlan { parent, .. }) = &mut device.kind
+        else {
+            continue;
+        };
+        let in_same_netns = !link
+            .attributes
+            .iter()
+            .any(|attr| matches!(attr, LinkAttribute::LinkNetNsId(_)));
+        let parent_index = link.attributes.iter().find_map(|attr| match attr {
+            LinkAttribute::Link(index) => Some(*index),
+            _ => None,
+        });
+
+        if let Some(index) = parent_index.filter(|_| in_same_netns) {
+            parent.name = names.get(&index).cloned().unwrap_or_default();
+        }
+    }
+}
+
+/// Fills ports of bridges and slaves of bonds, from devices which have them as their master.
+///
+/// Bridges and bonds without members keep an empty list.
+fn resolve_ports(devices: &mut [(DeviceInfo, LinkMessage)]) {
+    let mut members_by_master: HashMap<u32, Vec<PeerRef>> = HashMap::new();
+    for (device, _link) in devices.iter() {
+        if let Some(master_index) = device.master_index {
+            members_by_master
+                .entry(master_index)
+                .or_default()
+                .push(PeerRef {
+                    name: device.name.clone(),
+                    netns: device.netns,
+                });
+        }
+    }
+
+    for (device, _link) in devices {
+        if let Kind::Bridge { ports: members } | Kind::Bond { slaves: members } = &mut device.kind {
+            *members = members_by_master.remove(&device.index).unwrap_or_default();
+        }
+    }
+}
+
+/// Address of the device itself. For point-to-point IPv4 links, `Address` is the one of the other end,
+/// and the own one is `Local`.
+fn address_from_message(address: &AddressMessage) -> Option<IpAddr> {
+    let find = |local: bool| {
+        address.attributes.iter().find_map(|attr| match attr {
+            AddressAttribute::Local(addr) if local => Some(*addr),
+            AddressAttribute::Address(addr) if !local => Some(*addr),
+            _ => None,
+        })
+    };
+
+    find(true).or_else(|| find(false))
+}
+
+/// Mask of an IPv4 subnet with a given prefix length (`24` is `255.255.255.0`).
+pub fn ipv4_mask(prefix_len: u8) -> Ipv4Mask {
+    let bits = u32::MAX
+        .checked_shl(32 - u32::from(prefix_len.min(32)))
+        .unwrap_or(0);
+    bits.to_be_bytes()
+}
+
+/// Mask of an IPv6 subnet with a given prefix length.
+pub fn ipv6_mask(prefix_len: u8) -> Ipv6Mask {
+    let bits = u128::MAX
+        .checked_shl(128 - u32::from(prefix_len.min(128)))
+        .unwrap_or(0);
+    bits.to_be_bytes()
+}
+
+/// Hardware address of a device. `None` for devices without one (loopback reports all zeros though),
+/// and for non-Ethernet addressing (InfiniBand addresses are 20 bytes long, for example).
+pub fn mac_from_attrs(attrs: &[LinkAttribute]) -> Option<Mac> {
+    attrs.iter().find_map(|attr| match attr {
+        LinkAttribute::Address(address) => Mac::try_from(address.as_slice()).ok(),
+        _ => None,
+    })
+}
+
+/// Kind of a device, as it was given on creation (`ip link add ... type <kind>`).
+fn info_kind_from_attrs(attrs: &[LinkAttribute]) -> Option<&InfoKind> {
+    attrs
+        .iter()
+        .filter_map(|attr| match attr {
+            LinkAttribute::LinkInfo(infos) => Some(infos),
+            _ => None,
+        })
+        .flatten()
+        .find_map(|info| match info {
+            LinkInfo::Kind(kind) => Some(kind),
+            _ => None,
+        })
+}
+
+/// Kind-specific data of a device (VLAN id, VXLAN vni, ...).
+fn info_data_from_attrs(attrs: &[LinkAttribute]) -> Option<&InfoData> {
+    attrs
+        .iter()
+        .filter_map(|attr| match attr {
+            LinkAttribute::LinkInfo(infos) => Some(infos),
+            _ => None,
+        })
+        .flatten()
+        .find_map(|info| match info {
+            LinkInfo::Data(data) => Some(data),
+            _ => None,
+        })
+}
+
+/// Local and remote endpoints of a GRE tunnel (`IFLA_GRE_LOCAL`, `IFLA_GRE_REMOTE`). Unset (wildcard) ones are `None`.
+///
+/// Endpoints are IPv6 for `ip6gre` and `ip6gretap` devices, and IPv4 for `gre` and `gretap` ones.
+pub fn gre_endpoints_from_attrs(attrs: &[LinkAttribute]) -> (Option<IpAddr>, Option<IpAddr>) {
+    let (infos, ipv6): (Vec<&dyn Nla>, bool) = match info_data_from_attrs(attrs) {
+        Some(InfoData::GreTun(infos)) => (infos.iter().map(|nla| nla as &dyn Nla).collect(), false),
+        Some(InfoData::GreTap(infos)) => (infos.iter().map(|nla| nla as &dyn Nla).collect(), false),
+        Some(InfoData::GreTun6(infos)) => (infos.iter().map(|nla| nla as &dyn Nla).collect(), true),
+        Some(InfoData::GreTap6(infos)) => (infos.iter().map(|nla| nla as &dyn Nla).collect(), true),
+        _ => return (None, None),
+    };
+
+    let address = |nla: &dyn Nla| {
+        let mut value = vec![0; nla.value_len()];
+        nla.emit_value(&mut value);
+        let address = if ipv6 {
+            IpAddr::from(<[u8; 16]>::try_from(value.as_slice()).ok()?)
+        } else {
+            IpAddr::from(<[u8; 4]>::try_from(value.as_slice()).ok()?)
+        };
+        Some(address).filter(|address| !address.is_unspecified())
+    };
+
+    let (mut local, mut remote) = (None, None);
+    for nla in infos {
+        match nla.kind() {
+            IFLA_GRE_LOCAL => local = address(nla),
+            IFLA_GRE_REMOTE => remote = address(nla),
+            _ => {}
+        }
+    }
+
+    (local, remote)
+}
+
+const IFLA_GRE_LOCAL: u16 = 6;
+const IFLA_GRE_REMOTE: u16 = 7;
+
+const IFLA_TUN_OWNER: u16 = 1;
+const IFLA_TUN_GROUP: u16 = 2;
+const IFLA_TUN_PI: u16 = 4;
+const IFLA_TUN_VNET_HDR: u16 = 5;
+const IFLA_TUN_PERSIST: u16 = 6;
+const IFLA_TUN_MULTI_QUEUE: u16 = 7;
+
+/// Parses tun details, which `netlink-packet-route` only has as raw attributes.
+/// Returns `None` if there are none (kernel too old, or not a tun device).
+pub fn tun_info_from_attrs(attrs: &[LinkAttribute]) -> Option<TunInfo> {
+    let Some(InfoData::Tun(infos)) = info_data_from_attrs(attrs) else {
+        return None;
+    };
+
+    // Owner and group are `-1` when not set.
+    let id = |value: &[u8]| {
+        Some(u32::from_ne_bytes(value.try_into().ok()?)).filter(|&id| id != u32::MAX)
+    };
+    let flag = |value: &[u8]| value.first().is_some_and(|&flag| flag != 0);
+
+    let mut info = TunInfo::default();
+    for nla in infos {
+        let mut value = vec![0; nla.value_len()];
+        nla.emit_value(&mut value);
+        match nla.kind() {
+            IFLA_TUN_OWNER => info.owner = id(&value),
+            IFLA_TUN_GROUP => info.group = id(&value),
+            IFLA_TUN_PI => info.packet_info = flag(&value),
+            IFLA_TUN_VNET_HDR => info.vnet_hdr = flag(&value),
+            IFLA_TUN_PERSIST => info.persistent = flag(&value),
+            IFLA_TUN_MULTI_QUEUE => info.multi_queue = flag(&value),
+            _ => {}
+        }
+    }
+
+    Some(info)
+}
+
+fn vxlan_infos_from_attrs(attrs: &[LinkAttribute]) -> &[InfoVxlan] {
+    match info_data_from_attrs(attrs) {
+        Some(InfoData::Vxlan(infos)) => infos,
+        _ => &[],
+    }
+}
+
+/// Addresses a VXLAN device tunnels between, kept out of [`Kind::Vxlan`] to not change its fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VxlanEndpoints {
+    /// Remote unicast address or multicast group.
+    pub group: Option<IpAddr>,
+    /// Source address of the tunnel.
+    pub local: Option<IpAddr>,
+}
+
+/// Tunnel endpoints of a VXLAN device. Both are `None` for other devices, or if not configured.
+pub fn vxlan_endpoints_from_attrs(attrs: &[LinkAttribute]) -> VxlanEndpoints {
+    let mut endpoints = VxlanEndpoints::default();
+    for info in vxlan_infos_from_attrs(attrs) {
+        match info {
+            InfoVxlan::Group(addr) => endpoints.group = Some(IpAddr::V4(*addr)),
+            InfoVxlan::Group6(addr) => endpoints.group = Some(IpAddr::V6(*addr)),
+            InfoVxlan::Local(addr) => endpoints.local = Some(IpAddr::V4(*addr)),
+            InfoVxlan::Local6(addr) => endpoints.local = Some(IpAddr::V6(*addr)),
+            _ => {}
+        }
+    }
+    endpoints
+}
+
+/// Kind of a device, from the kind it was created with (`LinkInfo::Kind`).
+/// Devices created without one (physical devices, loopback) are told apart by their link layer (`ARPHRD_*`) type.
+///
+/// Peers, parents and ports are only known by index here, so the returned ones are placeholders
+/// (with an empty name, in namespace `netns`), filled once all devices of the namespace are known.
+pub fn kind_from_link(netns: INode, link: &LinkMessage) -> Kind {
+    let placeholder = || PeerRef {
+        name: String::new(),
+        netns,
+    };
+
+    let Some(info_kind) = info_kind_from_attrs(&link.attributes) else {
+        return match link.header.link_layer_type {
+            LinkLayerType::Loopback => Kind::Loopback,
+            LinkLayerType::Ppp => Kind::Ppp,
+            LinkLayerType::Slip
+            | LinkLayerType::Cslip
+            | LinkLayerType::Slip6
+            | LinkLayerType::Cslip6 => Kind::Slip,
+            _ => Kind::Ethernet,
+        };
+    };
+
+    match info_kind {
+        InfoKind::Veth => Kind::Veth {
+            peer: placeholder(),
+        },
+        InfoKind::Bridge => Kind::Bridge { ports: vec![] },
+        InfoKind::Bond => Kind::Bond { slaves: vec![] },
+        InfoKind::Vlan => Kind::Vlan {
+            id: info_data_from_attrs(&link.attributes)
+                .and_then(|data| match data {
+                    InfoData::Vlan(infos) => infos.iter().find_map(|info| match info {
+                        InfoVlan::Id(id) => Some(*id),
+                        _ => None,
+                    }),
+                    _ => None,
+                })
+                .unwrap_or(0),
+            parent: placeholder(),
+        },
+        InfoKind::MacVlan | InfoKind::MacVtap => Kind::MacVlan {
+            parent: placeholder(),
+            mode: info_data_from_attrs(&link.attributes)
+                .and_then(|data| match data {
+                    InfoData::MacVlan(infos) => infos.iter().find_map(|info| match info {
+                        InfoMacVlan::Mode(mode) => Some(MacVlanMode::from(*mode)),
+                        _ => None,
+                    }),
+                    InfoData::MacVtap(infos) => infos.iter().find_map(|info| match info {
+                        InfoMacVtap::Mode(mode) => Some(MacVlanMode::from(*mode)),
+                        _ => None,
+                    }),
+                    _ => None,
+                })
+                .unwrap_or_default(),
+        },
+        InfoKind::IpVlan | InfoKind::IpVtap => Kind::IpVlan {
+            parent: placeholder(),
+            mode: info_data_from_attrs(&link.attributes)
+                .and_then(|data| match data {
+                    InfoData::IpVlan(infos) => infos.iter().find_map(|info| match info {
+                        InfoIpVlan::Mode(mode) => Some(IpVlanMode::from(*mode)),
+                        _ => None,
+                    }),
+                    InfoData::IpVtap(infos) => infos.iter().find_map(|info| match info {
+                        InfoIpVtap::Mode(mode) => Some(IpVlanMode::from(*mode)),
+                        _ => None,
+                    }),
+                    _ => None,
+                })
+                .unwrap_or_default(),
+        },
+        InfoKind::Vxlan => Kind::Vxlan {
+            vni: vxlan_infos_from_attrs(&link.attributes)
+                .iter()
+                .find_map(|info| match info {
+                    InfoVxlan::Id(vni) => Some(*vni),
+                    _ => None,
+                })
+                .unwrap_or(0),
+        },
+        // Tun and tap devices have the same kind, tap ones are Ethernet-like.
+        InfoKind::Tun if link.header.link_layer_type == LinkLayerType::Ether => {
+            Kind::Tap(tun_info_from_attrs(&link.attributes))
+        }
+        InfoKind::Tun => Kind::Tun(tun_info_from_attrs(&link.attributes)),
+        InfoKind::GreTun | InfoKind::GreTun6 | InfoKind::GreTap | InfoKind::GreTap6 => {
+            let (local, remote) = gre_endpoints_from_attrs(&link.attributes);
+            Kind::Gre { local, remote }
+        }
+        InfoKind::Wireguard => Kind::Wireguard {
+            listen_port: 0,
+            peers: vec![],
+        },
+        other => Kind::Other(other.to_string()),
+    }
+}
+
+impl DeviceInfo {
+    pub fn is_bridge(&self) -> bool {
+        matches!(self.kind, Kind::Bridge { .. })
+    }
+
+    /// Whether the device is a port of a bridge. `devices` should contain devices of the same namespace,
+    /// since only the index of the master device is known.
+    pub fn is_bridge_port(&self, devices: &[DeviceInfo]) -> bool {
+        let Some(master_index) = self.master_index else {
+            return false;
+        };
+
+        devices
+            .iter()
+            .find(|device| device.netns == self.netns && device.index == master_index)
+            .is_some_and(DeviceInfo::is_bridge)
+    }
+
+    /// Whether the device is actually able to pass packets.
+    ///
+    /// Devices without a notion of carrier (loopback, some virtual ones) report `Unknown` operational state,
+    /// so for them the administrative state is used.
+    pub fn is_operational(&self) -> bool {
+        match self.oper_state {
+            OperState::Up => true,
+            OperState::Unknown => self.is_up,
+            _ => false,
+        }
+    }
+}
+
+/// Builder for device queries, to only get devices of interest.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceQuery {
+    operational_only: bool,
+    skip_loopback: bool,
+    group: Option<u32>,
+    link_modes: bool,
+}
+
+impl DeviceQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only include devices that are operationally up (see [`DeviceInfo::is_operational`]),
+    /// excluding the ones that are down, dormant, or have their lower layer down.
+    pub fn operational_only(mut self, operational_only: bool) -> Self {
+        self.operational_only = operational_only;
+        self
+    }
+
+    /// Exclude loopback devices (`lo`, which every namespace has).
+    pub fn skip_loopback(mut self, skip_loopback: bool) -> Self {
+        self.skip_loopback = skip_loopback;
+        self
+    }
+
+    /// Only include devices of the given device group.
+    pub fn in_group(mut self, group: u32) -> Self {
+        self.group = Some(group);
+        self
+    }
+
+    /// Also read speed and duplex of devices (see [`DeviceInfo::speed_mbps`]).
+    ///
+    /// This mounts a fresh sysfs in a private mount namespace, once per queried network namespace.
+    /// Namespaces where that fails are reported with [`QueryError::CouldntMountSysfs`].
+    pub fn with_link_modes(mut self, link_modes: bool) -> Self {
+        self.link_modes = link_modes;
+        self
+    }
+
+    /// Checks whether the device passes all the filters of this query.
+    pub fn matches(&self, device: &DeviceInfo) -> bool {
+        if self.operational_only && !device.is_operational() {
+            return false;
+        }
+        if self.skip_loopback && matches!(device.kind, Kind::Loopback) {
+            return false;
+        }
+        if self.group.is_some_and(|group| group != device.group) {
+            return false;
+        }
+        true
+    }
+
+    /// Lists matching devices of all the given network namespaces.
+    ///
+    /// Same as [`DeviceInfo::all`], namespaces that could not be queried are skipped.
+    /// Use [`DeviceQuery::execute_per_namespace`] to know about them.
+    pub async fn execute(
+        &self,
+        network_namespaces_files: impl IntoIterator<Item = PathBuf>,
+    ) -> Vec<DeviceInfo> {
+        self.execute_per_namespace(network_namespaces_files)
+            .await
+            .into_iter()
+            .filter_map(|(_netns_file, result)| result.ok())
+            .flatten()
+            .collect()
+    }
+
+    /// Lists matching devices of each given network namespace separately, like [`DeviceInfo::all_per_namespace`].
+    pub async fn execute_per_namespace(
+        &self,
+        network_namespaces_files: impl IntoIterator<Item = PathBuf>,
+    ) -> Vec<NamespaceDevices> {
+        let queries = network_namespaces_files
+            .into_iter()
+            .map(async |netns_file| {
+                let result = DeviceInfo::query_namespace(netns_file.clone(), self.link_modes)
+                    .await
+                    .map(|devices| {
+                        devices
+                            .into_iter()
+                            .map(|(device, _link)| device)
+                            .filter(|device| self.matches(device))
+                            .collect()
+                    });
+                (netns_file, result)
+            });
+
+        futures::future::join_all(queries).await
+    }
+}
+
+/// Lists network devices visible to a process, i.e. devices of the network namespace it is running in.
+pub async fn devices_for_pid(pid: Pid) -> Result<Vec<DeviceInfo>, Error> {
+    let netns_filepath = Path::new("/proc")
+        .join(pid.to_string())
+        .join("ns")
+        .join("net");
+
+    // Keep the namespace file open for the whole query: even if the process exits meanwhile,
+    // the namespace stays alive, and we keep querying the same one.
+    let netns_file = match std::fs::File::open(&netns_filepath) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Err(Error::NoSuchProcess(pid));
+        }
+        Err(err) => return Err(Error::Io(err)),
+    };
+    let fd_filepath = Path::new("/proc/self/fd").join(netns_file.as_raw_fd().to_string());
+
+    let devices = DeviceInfo::of_namespace(fd_filepath).await;
+    drop(netns_file);
+
+    Ok(devices?)
+}
+
 #[derive(Debug, Error)]
 pub enum QueryError {
     #[error("could not open network namespace file - {0}")]
@@ -92,12 +1243,1003 @@ pub enum QueryError {
 
     #[error("rtnetlink receiving error - {0}")]
     RtnetnlinkRecvErrror(#[from] rtnetlink::Error),
+
+    #[error("failed to mount sysfs of network namespace - {0}")]
+    CouldntMountSysfs(std::io::Error),
+
+    #[error("'{0}' is not a network sysctl key")]
+    InvalidSysctlKey(String),
+
+    #[error("failed to read sysctl {0} - {1}")]
+    CouldntReadSysctl(String, std::io::Error),
+
+    #[error("failed to open ethtool socket - {0}")]
+    CouldntOpenSocket(std::io::Error),
+
+    #[error("wireguard generic netlink error - {0}")]
+    Wireguard(wireguard::Error),
+}
+
+/// Sets MTU of a device. Device index is relative to the network namespace `handle` was opened in.
+pub async fn set_device_mtu(
+    handle: &mut rtnetlink::Handle,
+    index: u32,
+    mtu: u32,
+) -> Result<(), Error> {
+    let message = LinkUnspec::new_with_index(index).mtu(mtu).build();
+
+    handle
+        .link()
+        .set(message)
+        .execute()
+        .await
+        .map_err(|err| device_error(index, err))
+}
+
+/// Creates a `dummy` device, in the network namespace `handle` was opened in. Device is created down.
+pub async fn create_dummy(handle: &mut rtnetlink::Handle, name: &str) -> Result<(), Error> {
+    handle
+        .link()
+        .add(LinkDummy::new(name).build())
+        .execute()
+        .await?;
+
+    Ok(())
+}
+
+/// Deletes a device. Device index is relative to the network namespace `handle` was opened in.
+///
+/// Deleting one end of a veth pair deletes the other one too.
+pub async fn delete_device(handle: &mut rtnetlink::Handle, index: u32) -> Result<(), Error> {
+    handle
+        .link()
+        .del(index)
+        .execute()
+        .await
+        .map_err(|err| device_error(index, err))
+}
+
+/// Assigns an IPv4 or IPv6 address with a prefix length to a device.
+/// Device index is relative to the network namespace `handle` was opened in.
+pub async fn add_address(
+    handle: &mut rtnetlink::Handle,
+    index: u32,
+    addr: IpAddr,
+    prefix: u8,
+) -> Result<(), Error> {
+    handle
+        .address()
+        .add(index, addr, prefix)
+        .execute()
+        .await
+        .map_err(|err| match netlink_errno(&err) {
+            Some(libc::EEXIST) => Error::AddressExists(addr, index),
+            _ => device_error(index, err),
+        })
+}
+
+/// Maps `ENODEV` to `Error::NoSuchDevice`, since device could have vanished in the meantime.
+fn device_error(index: u32, err: rtnetlink::Error) -> Error {
+    if netlink_errno(&err) == Some(libc::ENODEV) {
+        Error::NoSuchDevice(index)
+    } else {
+        Error::Netlink(err)
+    }
 }
 
-/// Moves to a certain network namespace, then uses rtnetlink to get all network devices
-pub async fn query_netns_links(netns_filepath: PathBuf) -> Result<Vec<LinkMessage>, QueryError> {
+/// Returns (positive) errno of a netlink error message.
+fn netlink_errno(err: &rtnetlink::Error) -> Option<i32> {
+    match err {
+        rtnetlink::Error::NetlinkError(message) => message.code.map(|code| -code.get()),
+        _ => None,
+    }
+}
+
+/// Result of an rtnetlink dump request.
+///
+/// A dump can be interrupted midway by an error. In that case, `items` holds everything received before it,
+/// and `interrupted` holds the error.
+#[derive(Debug)]
+pub struct NetlinkDump<T> {
+    pub items: Vec<T>,
+    pub interrupted: Option<rtnetlink::Error>,
+}
+
+/// Moves to a certain network namespace, then uses rtnetlink to get all network devices.
+///
+/// Fails only if no devices were received at all.
+pub async fn query_netns_links(
+    netns_filepath: PathBuf,
+) -> Result<NetlinkDump<LinkMessage>, QueryError> {
+    run_netlink_in_netns(netns_filepath, async |handle| {
+        Ok(collect_dump(handle.link().get().execute()).await?)
+    })
+    .await
+}
+
+/// Same as `query_netns_links`, but also gets all addresses of the devices, while in the same namespace.
+///
+/// Addresses are only nice to have: if querying them fails, the dump of addresses is empty and `interrupted`.
+pub async fn query_netns_links_and_addresses(
+    netns_filepath: PathBuf,
+) -> Result<(NetlinkDump<LinkMessage>, NetlinkDump<AddressMessage>), QueryError> {
+    run_netlink_in_netns(netns_filepath, async |handle| {
+        let links = collect_dump(handle.link().get().execute()).await?;
+        let addresses = collect_dump(handle.address().get().execute())
+            .await
+            .unwrap_or_else(|err| NetlinkDump {
+                items: vec![],
+                interrupted: Some(err),
+            });
+
+        Ok((links, addresses))
+    })
+    .await
+}
+
+/// Moves to a certain network namespace, then runs `f` with an rtnetlink connection opened there.
+async fn run_netlink_in_netns<T, F>(netns_filepath: PathBuf, f: F) -> Result<T, QueryError>
+where
+    T: Send + 'static,
+    F: AsyncFnOnce(&rtnetlink::Handle) -> Result<T, QueryError> + Send + 'static,
+{
+    in_namespace(netns_filepath, move || async move {
+        // Socket is bound to the namespace of the thread.
+        let (conn, handle, _) =
+            rtnetlink::new_connection().map_err(QueryError::NetlinkConnection)?;
+        let conn_handle = tokio::task::spawn_local(conn);
+
+        let result = f(&handle).await;
+
+        drop(handle);
+        conn_handle.abort();
+
+        result
+    })
+    .await
+}
+
+/// Pool of threads for querying devices of many network namespaces.
+///
+/// Same as `query_netns_links`, but threads, and their tokio runtimes, are reused between queries.
+/// Only an rtnetlink connection is opened per query, since its socket is bound to the namespace it is opened in.
+/// Workers are stopped when the querier is dropped.
+#[derive(Debug)]
+pub struct LinkQuerier {
+    jobs: Option<std::sync::mpsc::Sender<LinkJob>>,
+    workers: Vec<std::thread::JoinHandle<()>>,
+}
+
+type LinkJob = (
+    std::fs::File,
+    tokio::sync::oneshot::Sender<Result<NetlinkDump<LinkMessage>, QueryError>>,
+);
+
+impl LinkQuerier {
+    /// Starts `workers` threads (at least one). At most that many namespaces are queried at once.
+    pub fn new(workers: usize) -> Self {
+        let (jobs, job_rx) = std::sync::mpsc::channel::<LinkJob>();
+        let job_rx = std::sync::Arc::new(std::sync::Mutex::new(job_rx));
+
+        let workers = (0..workers.max(1))
+            .map(|_| {
+                let job_rx = job_rx.clone();
+                std::thread::spawn(move || link_querier_worker(&job_rx))
+            })
+            .collect();
+
+        Self {
+            jobs: Some(jobs),
+            workers,
+        }
+    }
+
+    /// Gets all network devices of a network namespace. Fails only if no devices were received at all.
+    pub async fn query_links(
+        &self,
+        netns_filepath: PathBuf,
+    ) -> Result<NetlinkDump<LinkMessage>, QueryError> {
+        let netns_file = tokio::fs::File::open(netns_filepath)
+            .await
+            .map_err(QueryError::CoulndtOpenNetns)?
+            .into_std()
+            .await;
+
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+        let sent = self
+            .jobs
+            .as_ref()
+            .is_some_and(|jobs| jobs.send((netns_file, response_tx)).is_ok());
+        if !sent {
+            return Err(link_querier_stopped());
+        }
+
+        response_rx
+            .await
+            .map_err(|_closed| link_querier_stopped())?
+    }
+}
+
+impl Drop for LinkQuerier {
+    fn drop(&mut self) {
+        // Workers stop once there are no more jobs to take.
+        drop(self.jobs.take());
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn link_querier_stopped() -> QueryError {
+    QueryError::ThreadDied(Box::new("link querier worker stopped"))
+}
+
+fn link_querier_worker(jobs: &std::sync::Mutex<std::sync::mpsc::Receiver<LinkJob>>) {
+    // Thread returns here after every job, so it does not keep the queried namespace alive,
+    // nor shows up among its processes.
+    let own_netns = match std::fs::File::open("/proc/thread-self/ns/net") {
+        Ok(file) => file,
+        Err(err) => {
+            eprintln!(
+                "[net_device] WARN link querier worker could not open its own namespace - {err}"
+            );
+            return;
+        }
+    };
+    let mut runtime = None;
+
+    loop {
+        let job = jobs.lock().map(|jobs| jobs.recv());
+        let Ok(Ok((netns_file, response_tx))) = job else {
+            return;
+        };
+
+        let result = (|| {
+            set_netns(&netns_file).map_err(QueryError::CoulndtOpenNetns)?;
+
+            let runtime = match &mut runtime {
+                Some(runtime) => runtime,
+                None => runtime.insert(
+                    tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                        .map_err(QueryError::TokioRuntime)?,
+                ),
+            };
+
+            runtime.block_on(async {
+                // Socket is bound to the namespace the thread is in now.
+                let (conn, handle, _) =
+                    rtnetlink::new_connection().map_err(QueryError::NetlinkConnection)?;
+                let conn_handle = tokio::spawn(conn);
+
+                let result = collect_dump(handle.link().get().execute()).await;
+
+                drop(handle);
+                conn_handle.abort();
+                Ok(result?)
+            })
+        })();
+
+        // Also after a failed job: `setns` could have succeeded before it failed.
+        let restored = set_netns(&own_netns);
+
+        // Requester could have given up waiting.
+        let _ = response_tx.send(result);
+
+        if let Err(err) = restored {
+            eprintln!(
+                "[net_device] WARN link querier worker could not return to its own namespace - {err}"
+            );
+            // Exiting is the only other way to leave the namespace.
+            return;
+        }
+    }
+}
+
+/// Receives all messages of a dump. Keeps what was received, if dump fails midway.
+///
+/// Fails only if nothing was received at all.
+pub async fn collect_dump<T>(
+    stream: impl futures::TryStream<Ok = T, Error = rtnetlink::Error>,
+) -> Result<NetlinkDump<T>, rtnetlink::Error> {
+    let mut stream = std::pin::pin!(stream.into_stream());
+    let mut items = Vec::new();
+    let mut interrupted = None;
+
+    loop {
+        match TryStreamExt::try_next(&mut stream).await {
+            Ok(Some(item)) => items.push(item),
+            Ok(None) => break,
+            Err(err) if items.is_empty() => return Err(err),
+            Err(err) => {
+                interrupted = Some(err);
+                break;
+            }
+        }
+    }
+
+    Ok(NetlinkDump { items, interrupted })
+}
+
+/// Moves to a certain network namespace, mounts its own view of sysfs, then reads speed and duplex of all network devices.
+pub async fn query_netns_link_modes(
+    netns_filepath: PathBuf,
+) -> Result<HashMap<String, (Option<u32>, Option<Duplex>)>, QueryError> {
+    run_in_netns(netns_filepath, || {
+        mount_netns_sysfs().map_err(QueryError::CouldntMountSysfs)?;
+
+        let sysfs_net = Path::new(SYSFS_NET_PATH);
+        let modes = std::fs::read_dir(sysfs_net)
+            .map_err(QueryError::CouldntMountSysfs)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .map(|name| {
+                let speed = read_link_speed(sysfs_net, &name);
+                let duplex = read_link_duplex(sysfs_net, &name);
+                (name, (speed, duplex))
+            })
+            .collect();
+
+        Ok(modes)
+    })
+    .await
+}
+
+/// Moves to a certain network namespace, then reads driver name and bus address of the named devices with ethtool.
+///
+/// Devices that do not support ethtool, or do not exist (anymore), get `None` for both.
+pub async fn query_netns_driver_info(
+    netns_filepath: PathBuf,
+    names: Vec<String>,
+) -> Result<HashMap<String, (Option<String>, Option<String>)>, QueryError> {
+    run_in_netns(netns_filepath, move || {
+        let socket = socket2::Socket::new(socket2::Domain::IPV4, socket2::Type::DGRAM, None)
+            .map_err(QueryError::CouldntOpenSocket)?;
+
+        let infos = names
+            .into_iter()
+            .map(|name| {
+                let info = match read_driver_info(&socket, &name) {
+                    Ok(info) => info,
+                    Err(err)
+                        if matches!(err.raw_os_error(), Some(libc::EOPNOTSUPP | libc::ENODEV)) =>
+                    {
+                        (None, None)
+                    }
+                    Err(err) => {
+                        eprintln!("[net_device] WARN could not query driver of {name} - {err}");
+                        (None, None)
+                    }
+                };
+                (name, info)
+            })
+            .collect();
+
+        Ok(infos)
+    })
+    .await
+}
+
+/// `ETHTOOL_GDRVINFO` command of the `SIOCETHTOOL` ioctl.
+const ETHTOOL_GDRVINFO: u32 = 0x00000003;
+
+/// `struct ethtool_drvinfo` from `linux/ethtool.h`.
+#[repr(C)]
+struct EthtoolDrvInfo {
+    cmd: u32,
+    driver: [u8; 32],
+    version: [u8; 32],
+    fw_version: [u8; 32],
+    bus_info: [u8; 32],
+    erom_version: [u8; 32],
+    reserved2: [u8; 12],
+    n_priv_flags: u32,
+    n_stats: u32,
+    testinfo_len: u32,
+    eedump_len: u32,
+    regdump_len: u32,
+}
+
+/// Driver name and bus address of a device, in the network namespace of `socket`.
+fn read_driver_info(
+    socket: &socket2::Socket,
+    name: &str,
+) -> std::io::Result<(Option<String>, Option<String>)> {
+    // Name must fit together with the terminating nul.
+    if name.len() >= libc::IFNAMSIZ {
+        return Err(std::io::Error::from_raw_os_error(libc::ENODEV));
+    }
+
+    let mut info: EthtoolDrvInfo = unsafe { std::mem::zeroed() };
+    info.cmd = ETHTOOL_GDRVINFO;
+    let mut request: libc::ifreq = unsafe { std::mem::zeroed() };
+    for (dst, src) in request.ifr_name.iter_mut().zip(name.bytes()) {
+        *dst = src as libc::c_char;
+    }
+    request.ifr_ifru.ifru_data = (&raw mut info).cast();
+
+    let code = unsafe { libc::ioctl(socket.as_raw_fd(), libc::SIOCETHTOOL, &mut request) };
+    if code < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok((drvinfo_string(&info.driver), drvinfo_string(&info.bus_info)))
+}
+
+/// Reads a nul-terminated field of `struct ethtool_drvinfo`. Empty fields are `None`,
+/// and so is `N/A`, which some virtual devices (bridge, bond) report as their bus address.
+pub fn drvinfo_string(field: &[u8]) -> Option<String> {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    match String::from_utf8_lossy(&field[..len]).trim() {
+        "" | "N/A" => None,
+        value => Some(value.to_owned()),
+    }
+}
+
+/// Moves to a certain network namespace, then reads 64-bit traffic counters of the device named `ifname`.
+pub async fn query_device_stats(netns_filepath: PathBuf, ifname: &str) -> Result<Stats64, Error> {
+    let links = query_netns_links(netns_filepath).await?.items;
+
+    let link = links
+        .iter()
+        .find(|link| {
+            link.attributes
+                .iter()
+                .any(|attr| matches!(attr, LinkAttribute::IfName(name) if name == ifname))
+        })
+        .ok_or_else(|| Error::NoSuchDeviceName(ifname.to_owned()))?;
+
+    stats64_from_attrs(&link.attributes).ok_or_else(|| Error::NoStats(ifname.to_owned()))
+}
+
+/// Device group from `IFLA_GROUP`. Kernel always reports it, but a missing one means the default group 0.
+pub fn group_from_attrs(attrs: &[LinkAttribute]) -> u32 {
+    attrs
+        .iter()
+        .find_map(|attr| match attr {
+            LinkAttribute::Group(group) => Some(*group),
+            _ => None,
+        })
+        .unwrap_or(0)
+}
+
+/// Counters of a device, from `IFLA_STATS64`, or the 32-bit `IFLA_STATS` (which wrap around) if there are no 64-bit ones.
+pub fn link_stats_from_attrs(attrs: &[LinkAttribute]) -> Option<LinkStats> {
+    stats64_from_attrs(attrs).map(|stats| LinkStats {
+        rx_bytes: stats.rx_bytes,
+        tx_bytes: stats.tx_bytes,
+        rx_packets: stats.rx_packets,
+        tx_packets: stats.tx_packets,
+        rx_errors: stats.rx_errors,
+        tx_errors: stats.tx_errors,
+    })
+}
+
+/// All counters of a device, from `IFLA_STATS64`, or widened from the 32-bit `IFLA_STATS` if there are no 64-bit ones.
+pub fn stats64_from_attrs(attrs: &[LinkAttribute]) -> Option<Stats64> {
+    let stats64 = attrs.iter().find_map(|attr| match attr {
+        LinkAttribute::Stats64(stats) => Some(*stats),
+        _ => None,
+    });
+
+    stats64.or_else(|| {
+        attrs.iter().find_map(|attr| match attr {
+            LinkAttribute::Stats(stats) => Some(widen_stats(stats)),
+            _ => None,
+        })
+    })
+}
+
+fn widen_stats(stats: &Stats) -> Stats64 {
+    // `Stats64` is non-exhaustive, so it can not be built with a struct expression.
+    let mut stats64 = Stats64::default();
+    stats64.rx_packets = stats.rx_packets.into();
+    stats64.tx_packets = stats.tx_packets.into();
+    stats64.rx_bytes = stats.rx_bytes.into();
+    stats64.tx_bytes = stats.tx_bytes.into();
+    stats64.rx_errors = stats.rx_errors.into();
+    stats64.tx_errors = stats.tx_errors.into();
+    stats64.rx_dropped = stats.rx_dropped.into();
+    stats64.tx_dropped = stats.tx_dropped.into();
+    stats64.multicast = stats.multicast.into();
+    stats64.collisions = stats.collisions.into();
+    stats64.rx_length_errors = stats.rx_length_errors.into();
+    stats64.rx_over_errors = stats.rx_over_errors.into();
+    stats64.rx_crc_errors = stats.rx_crc_errors.into();
+    stats64.rx_frame_errors = stats.rx_frame_errors.into();
+    stats64.rx_fifo_errors = stats.rx_fifo_errors.into();
+    stats64.rx_missed_errors = stats.rx_missed_errors.into();
+    stats64.tx_aborted_errors = stats.tx_aborted_errors.into();
+    stats64.tx_carrier_errors = stats.tx_carrier_errors.into();
+    stats64.tx_fifo_errors = stats.tx_fifo_errors.into();
+    stats64.tx_heartbeat_errors = stats.tx_heartbeat_errors.into();
+    stats64.tx_window_errors = stats.tx_window_errors.into();
+    stats64.rx_compressed = stats.rx_compressed.into();
+    stats64.tx_compressed = stats.tx_compressed.into();
+    stats64.rx_nohandler = stats.rx_nohandler.into();
+    stats64
+}
+
+/// Traffic rates of a device, per second.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatsDelta {
+    pub rx_bytes: f64,
+    pub tx_bytes: f64,
+    pub rx_packets: f64,
+    pub tx_packets: f64,
+    pub rx_errors: f64,
+    pub tx_errors: f64,
+    pub rx_dropped: f64,
+    pub tx_dropped: f64,
+}
+
+impl StatsDelta {
+    /// Rates between two readings of counters, taken `elapsed` apart.
+    ///
+    /// Counters are allowed to wrap around between the readings. Readings taken no time apart give zero rates.
+    pub fn between(before: &Stats64, after: &Stats64, elapsed: Duration) -> Self {
+        let secs = elapsed.as_secs_f64();
+        let rate = |before: u64, after: u64| {
+            if secs == 0.0 {
+                return 0.0;
+            }
+            after.wrapping_sub(before) as f64 / secs
+        };
+
+        Self {
+            rx_bytes: rate(before.rx_bytes, after.rx_bytes),
+            tx_bytes: rate(before.tx_bytes, after.tx_bytes),
+            rx_packets: rate(before.rx_packets, after.rx_packets),
+            tx_packets: rate(before.tx_packets, after.tx_packets),
+            rx_errors: rate(before.rx_errors, after.rx_errors),
+            tx_errors: rate(before.tx_errors, after.tx_errors),
+            rx_dropped: rate(before.rx_dropped, after.rx_dropped),
+            tx_dropped: rate(before.tx_dropped, after.tx_dropped),
+        }
+    }
+}
+
+/// Reads counters of a device twice, `interval` apart, and returns the rates in between.
+///
+/// Interval must not be zero, since there would be no rates to measure.
+pub async fn sample_device_stats(
+    netns_filepath: PathBuf,
+    ifname: &str,
+    interval: Duration,
+) -> Result<StatsDelta, Error> {
+    if interval.is_zero() {
+        return Err(Error::ZeroInterval);
+    }
+
+    let before = query_device_stats(netns_filepath.clone(), ifname).await?;
+    let started = Instant::now();
+
+    tokio::time::sleep(interval).await;
+
+    let after = query_device_stats(netns_filepath, ifname).await?;
+
+    Ok(StatsDelta::between(&before, &after, started.elapsed()))
+}
+
+/// Waits until a device named `ifname` appears in a network namespace (created or moved there), then returns it.
+///
+/// Returns immediately if the device is already there.
+pub async fn wait_for_device(
+    netns_filepath: PathBuf,
+    ifname: &str,
+    timeout: Duration,
+) -> Result<DeviceInfo, Error> {
+    let netns = tokio::fs::metadata(&netns_filepath)
+        .await
+        .map_err(QueryError::CoulndtOpenNetns)?
+        .ino();
+
+    let name = ifname.to_owned();
+    let link = in_namespace(netns_filepath.clone(), move || async move {
+        let (mut conn, handle, mut messages) =
+            rtnetlink::new_connection().map_err(QueryError::NetlinkConnection)?;
+
+        // Subscribe before listing existing devices, so that a device appearing in between is not missed.
+        {
+            let socket = conn.socket_mut().socket_mut();
+            socket
+                .bind(&SocketAddr::new(0, 0))
+                .map_err(QueryError::NetlinkConnection)?;
+            socket
+                .add_membership(RTNLGRP_LINK)
+                .map_err(QueryError::NetlinkConnection)?;
+        }
+        let conn_handle = tokio::spawn(conn);
+
+        let has_name = |link: &LinkMessage| {
+            link.attributes
+                .iter()
+                .any(|attr| matches!(attr, LinkAttribute::IfName(n) if *n == name))
+        };
+
+        let wait = async {
+            let existing = collect_dump(handle.link().get().execute()).await?;
+            if let Some(link) = existing.items.into_iter().find(has_name) {
+                return Ok(Some(link));
+            }
+
+            while let Some((message, _addr)) = messages.next().await {
+                if let NetlinkPayload::InnerMessage(RouteNetlinkMessage::NewLink(link)) =
+                    message.payload
+                    && has_name(&link)
+                {
+                    return Ok(Some(link));
+                }
+            }
+            Ok::<_, QueryError>(None)
+        };
+        let result = tokio::time::timeout(timeout, wait).await;
+
+        drop(handle);
+        conn_handle.abort();
+
+        match result {
+            Ok(link) => link,
+            Err(_elapsed) => Ok(None),
+        }
+    })
+    .await?
+    .ok_or_else(|| Error::DeviceTimeout(ifname.to_owned(), timeout))?;
+
+    // Full query gives addresses and peers too. Device may be gone again by then though.
+    let devices = DeviceInfo::of_namespace(netns_filepath).await?;
+    Ok(devices
+        .into_iter()
+        .find(|device| device.name == ifname)
+        .unwrap_or_else(|| DeviceInfo::from_link(netns, &link)))
+}
+
+/// Change of a device in a network namespace, as reported by [`monitor_links`].
+#[derive(Debug, Clone)]
+pub enum LinkChange {
+    /// Device was created in the namespace, or moved into it.
+    Added(DeviceInfo),
+    /// Device was deleted, or moved out of the namespace.
+    Removed(DeviceInfo),
+    /// Device state changed: up/down, carrier, name, master, ...
+    Changed(DeviceInfo),
+    /// Both ends of a new veth pair appeared in the namespace, in the order they were reported.
+    /// Peers of both are filled in. Neither end is reported as `Added` on its own.
+    VethPairCreated(Box<(DeviceInfo, DeviceInfo)>),
+}
+
+/// How long a new veth waits for its peer to show up, before it is reported as `LinkChange::Added` alone.
+const VETH_PAIR_WINDOW: Duration = Duration::from_millis(100);
+
+/// New veth whose peer has not been reported yet.
+struct PendingVeth {
+    device: DeviceInfo,
+    deadline: Instant,
+}
+
+/// Index of the peer of a veth, if the peer is in the same namespace.
+fn same_netns_peer(device: &DeviceInfo, link: &LinkMessage) -> Option<u32> {
+    if !matches!(device.kind, Kind::Veth { .. })
+        || link
+            .attributes
+            .iter()
+            .any(|attr| matches!(attr, LinkAttribute::LinkNetNsId(_)))
+    {
+        return None;
+    }
+
+    link.attributes.iter().find_map(|attr| match attr {
+        LinkAttribute::Link(index) => Some(*index),
+        _ => None,
+    })
+}
+
+fn veth_pair_created(mut first: DeviceInfo, mut second: DeviceInfo) -> LinkChange {
+    if let Kind::Veth { peer } = &mut first.kind {
+        peer.name = second.name.clone();
+    }
+    if let Kind::Veth { peer } = &mut second.kind {
+        peer.name = first.name.clone();
+    }
+    LinkChange::VethPairCreated(Box::new((first, second)))
+}
+
+/// Returns a Receiver for changes of devices in a network namespace, and a Future that drives the monitor loop.
+///
+/// Devices are built from the link notification alone, so addresses, peers and ports are not filled in.
+/// Use [`DeviceInfo::by_name`] for the full picture.
+///
+/// Ends of a veth pair may be reported in either order. So a new veth whose peer is not known yet is held back
+/// for a short while, and if the peer appears meanwhile, both come as a single `LinkChange::VethPairCreated`.
+/// A veth whose peer is in another namespace is `Added` right away.
+pub async fn monitor_links(
+    netns_filepath: PathBuf,
+) -> Result<
+    (
+        tokio::sync::broadcast::Receiver<LinkChange>,
+        impl Send + Future<Output = Result<(), rtnetlink::Error>>,
+    ),
+    Error,
+> {
+    let netns = tokio::fs::metadata(&netns_filepath)
+        .await
+        .map_err(QueryError::CoulndtOpenNetns)?
+        .ino();
+
+    // A netlink socket belongs to the network namespace of the thread that created it, for its whole life.
+    // So the socket is created on a thread moved into the namespace, but registered with the caller's runtime:
+    // once the thread is gone, the socket is still in that namespace and gets driven by the caller.
+    let runtime = tokio::runtime::Handle::current();
+    let (conn, mut messages) = run_in_netns(netns_filepath.clone(), move || {
+        let _guard = runtime.enter();
+
+        let (mut conn, handle, messages) = netlink_proto::new_connection_with_codec::<
+            RouteNetlinkMessage,
+            TokioSocket,
+            LinkEventCodec,
+        >(NETLINK_ROUTE)
+        .map_err(QueryError::NetlinkConnection)?;
+        drop(handle);
+        {
+            let socket = conn.socket_mut().socket_mut();
+            socket
+                .bind(&SocketAddr::new(0, 0))
+                .map_err(QueryError::NetlinkConnection)?;
+            socket
+                .add_membership(RTNLGRP_LINK)
+                .map_err(QueryError::NetlinkConnection)?;
+        }
+        Ok((conn, messages))
+    })
+    .await?;
+    let conn_handle = tokio::spawn(conn);
+
+    // Kernel sends RTM_NEWLINK both for new and changed devices. Known indices tell these apart.
+    // Subscribed before the dump, so a device created in between is reported as changed, not missed.
+    let existing = match query_netns_links(netns_filepath).await {
+        Ok(existing) => existing.items,
+        Err(err) => {
+            conn_handle.abort();
+            return Err(err.into());
+        }
+    };
+    let mut known: HashSet<u32> = existing.iter().map(|link| link.header.index).collect();
+
+    let (send, recv) = tokio::sync::broadcast::channel(1024);
+
+    let monitor_fut = async move {
+        let mut pending: HashMap<u32, PendingVeth> = HashMap::new();
+
+        'main: loop {
+            let next_deadline = pending.values().map(|veth| veth.deadline).min();
+
+            tokio::select! {
+                message = messages.next() => {
+                    let Some((message, _addr)) = message else {
+                        break 'main;
+                    };
+
+                    let changes = match message.payload {
+                        NetlinkPayload::InnerMessage(RouteNetlinkMessage::NewLink(link)) => {
+                            let index = link.header.index;
+                            let device = DeviceInfo::from_link(netns, &link);
+
+                            if known.insert(index) {
+                                match same_netns_peer(&device, &link) {
+                                    Some(peer) => match pending.remove(&peer) {
+                                        Some(first) => vec![veth_pair_created(first.device, device)],
+                                        // Peer was there before, this end was moved in (or renamed over).
+                                        None if known.contains(&peer) => vec![LinkChange::Added(device)],
+                                        None => {
+                                            let deadline = Instant::now() + VETH_PAIR_WINDOW;
+                                            pending.insert(index, PendingVeth { device, deadline });
+                                            vec![]
+                                        }
+                                    },
+                                    None => vec![LinkChange::Added(device)],
+                                }
+                            } else if let Some(veth) = pending.get_mut(&index) {
+                                // Not reported yet, so it will be with the latest state.
+                                veth.device = device;
+                                vec![]
+                            } else {
+                                vec![LinkChange::Changed(device)]
+                            }
+                        }
+                        NetlinkPayload::InnerMessage(RouteNetlinkMessage::DelLink(link)) => {
+                            known.remove(&link.header.index);
+                            let mut changes = vec![];
+                            if let Some(veth) = pending.remove(&link.header.index) {
+                                changes.push(LinkChange::Added(veth.device));
+                            }
+                            changes.push(LinkChange::Removed(DeviceInfo::from_link(netns, &link)));
+                            changes
+                        }
+                        _ => continue,
+                    };
+
+                    for change in changes {
+                        if send.send(change).is_err() {
+                            break 'main;
+                        }
+                    }
+                }
+
+                _ = tokio::time::sleep_until(next_deadline.unwrap_or_else(Instant::now)), if next_deadline.is_some() => {
+                    let now = Instant::now();
+                    for (_index, veth) in pending.extract_if(|_index, veth| veth.deadline <= now) {
+                        if send.send(LinkChange::Added(veth.device)).is_err() {
+                            break 'main;
+                        }
+                    }
+                }
+
+                _ = send.closed() => break 'main,
+            }
+        }
+        drop(messages);
+        conn_handle.abort();
+        Ok(())
+    };
+
+    Ok((recv, monitor_fut))
+}
+
+/// Decodes like `NetlinkCodec`, but does not drop `RTM_DELLINK` messages netlink-packet-route fails to parse.
+///
+/// Newer kernels put per-family data into `IFLA_AF_SPEC` of deleted devices which is not understood,
+/// so such messages are decoded once more without that attribute.
+struct LinkEventCodec;
+
+impl NetlinkMessageCodec for LinkEventCodec {
+    fn decode<T>(src: &mut BytesMut) -> std::io::Result<Option<NetlinkMessage<T>>>
+    where
+        T: NetlinkDeserializable + std::fmt::Debug,
+    {
+        loop {
+            if src.is_empty() {
+                return Ok(None);
+            }
+            let len = match NetlinkBuffer::new_checked(src.as_ref()) {
+                Ok(buffer) => buffer.length() as usize,
+                // Truncated or malformed datagram, the start of the next message is unknown.
+                Err(_) => {
+                    src.clear();
+                    return Ok(None);
+                }
+            };
+            let bytes = src.split_to(len);
+
+            if let Ok(message) = NetlinkMessage::deserialize(&bytes) {
+                return Ok(Some(message));
+            }
+            if let Some(stripped) = without_link_attribute(&bytes, libc::IFLA_AF_SPEC)
+                && let Ok(message) = NetlinkMessage::deserialize(&stripped)
+            {
+                return Ok(Some(message));
+            }
+            eprintln!("[net_device] WARN skipping netlink message which could not be decoded");
+        }
+    }
+
+    fn encode<T>(msg: NetlinkMessage<T>, buf: &mut BytesMut) -> std::io::Result<()>
+    where
+        T: NetlinkSerializable + std::fmt::Debug,
+    {
+        NetlinkCodec::encode(msg, buf)
+    }
+}
+
+/// Copy of a `RTM_DELLINK` message without attributes of the given kind. `None` for other messages.
+fn without_link_attribute(message: &[u8], kind: u16) -> Option<Vec<u8>> {
+    // nlmsghdr, then ifinfomsg, then attributes.
+    const HEADERS_LEN: usize = 16 + 16;
+
+    if message.len() < HEADERS_LEN
+        || u16::from_ne_bytes([message[4], message[5]]) != libc::RTM_DELLINK
+    {
+        return None;
+    }
+
+    let mut stripped = message[..HEADERS_LEN].to_vec();
+    let mut rest = &message[HEADERS_LEN..];
+    while rest.len() >= 4 {
+        let len = usize::from(u16::from_ne_bytes([rest[0], rest[1]]));
+        let attr_kind = u16::from_ne_bytes([rest[2], rest[3]]) & libc::NLA_TYPE_MASK as u16;
+        if len < 4 || len > rest.len() {
+            return None;
+        }
+
+        let padded = len.next_multiple_of(4).min(rest.len());
+        if attr_kind != kind {
+            stripped.extend_from_slice(&rest[..padded]);
+        }
+        rest = &rest[padded..];
+    }
+
+    let len = u32::try_from(stripped.len()).ok()?;
+    stripped[..4].copy_from_slice(&len.to_ne_bytes());
+    Some(stripped)
+}
+
+/// Moves to a certain network namespace, then reads the given sysctls (`net.ipv4.ip_forward`, ...).
+///
+/// Only `net.*` keys are allowed, since other sysctls are not specific to a network namespace.
+pub async fn query_netns_sysctls(
+    netns_filepath: PathBuf,
+    keys: &[&str],
+) -> Result<HashMap<String, String>, Error> {
+    let mut paths = Vec::with_capacity(keys.len());
+    for &key in keys {
+        paths.push((key.to_owned(), sysctl_path(key)?));
+    }
+
+    let values = run_in_netns(netns_filepath, || {
+        // `/proc/sys/net` shows settings of the namespace of the thread reading it.
+        paths
+            .into_iter()
+            .map(|(key, path)| match std::fs::read_to_string(path) {
+                Ok(value) => Ok((key, value.trim().to_owned())),
+                Err(err) => Err(QueryError::CouldntReadSysctl(key, err)),
+            })
+            .collect()
+    })
+    .await?;
+
+    Ok(values)
+}
+
+/// Maps `net.ipv4.ip_forward` to `/proc/sys/net/ipv4/ip_forward`.
+fn sysctl_path(key: &str) -> Result<PathBuf, QueryError> {
+    let invalid = || QueryError::InvalidSysctlKey(key.to_owned());
+
+    let mut components = key.split('.');
+    if components.next() != Some("net") {
+        return Err(invalid());
+    }
+
+    let mut path = PathBuf::from("/proc/sys/net");
+    for component in components {
+        if component.is_empty() || component.contains('/') {
+            return Err(invalid());
+        }
+        path.push(component);
+    }
+
+    Ok(path)
+}
+
+/// Runs the future returned by `f` on a dedicated thread, which is moved to the given network namespace beforehand.
+///
+/// Thread gets its own current-thread tokio runtime, inside of a `LocalSet`, so the future does not have to be `Send`
+/// and may `spawn_local`. Sockets opened by it belong to the namespace. Thread exits once the future completes,
+/// so no other code ever runs in the namespace, and the caller's thread is not moved at all.
+pub async fn in_namespace<T, F, Fut>(netns_filepath: PathBuf, f: F) -> Result<T, QueryError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<T, QueryError>> + 'static,
+{
+    run_in_netns(netns_filepath, move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(QueryError::TokioRuntime)?;
+
+        LocalSet::new().block_on(&runtime, f())
+    })
+    .await
+}
+
+/// Runs `f` on a dedicated thread, which is moved to the given network namespace beforehand.
+pub(crate) async fn run_in_netns<T, F>(netns_filepath: PathBuf, f: F) -> Result<T, QueryError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, QueryError> + Send + 'static,
+{
     // 1. Open network namespace file (we need file descriptor)
-    let handle = async_thread::spawn(move || -> Result<Vec<LinkMessage>, QueryError> {
+    let handle = async_thread::spawn(move || -> Result<T, QueryError> {
         {
             let netns_file =
                 std::fs::File::open(netns_filepath).map_err(QueryError::CoulndtOpenNetns)?;
@@ -107,40 +2249,71 @@ pub async fn query_netns_links(netns_filepath: PathBuf) -> Result<Vec<LinkMessag
             let _ = netns_file; // we can close the file now 
         }
 
-        // 3. Create async context from current thread.
-        let runtime = tokio::runtime::Builder::new_current_thread()
-            .enable_all()
-            .build()
-            .map_err(QueryError::TokioRuntime)?;
-        let local_set = LocalSet::new();
+        f()
+    });
 
-        let local_set_ref = &local_set;
-        let binding = async move || -> Result<Vec<LinkMessage>, QueryError> {
-            // 4. Open rtnetlink socket
-            let (conn, handle, _) =
-                rtnetlink::new_connection().map_err(QueryError::NetlinkConnection)?;
+    handle.join().await.map_err(QueryError::ThreadDied)?
+}
 
-            let conn_handle = local_set_ref.spawn_local(conn);
+const SYSFS_NET_PATH: &str = "/sys/class/net";
 
-            let mut stream = handle.link().get().execute();
-            let mut links = Vec::new();
+/// Reads link speed in Mbit/s from `<sysfs_net>/<name>/speed`.
+///
+/// Returns `None` when speed is unknown, which is the case for virtual devices and devices without carrier.
+pub fn read_link_speed(sysfs_net: &Path, name: &str) -> Option<u32> {
+    let speed = std::fs::read_to_string(sysfs_net.join(name).join("speed")).ok()?;
 
-            // 5. Receive all the messages
-            while let Some(item) = TryStreamExt::try_next(&mut stream).await? {
-                links.push(item);
-            }
+    // Unknown speed is `-1` (or `4294967295` on older kernels).
+    match speed.trim().parse::<i64>().ok()? {
+        speed if (0..u32::MAX as i64).contains(&speed) => Some(speed as u32),
+        _ => None,
+    }
+}
 
-            let _ = handle;
-            conn_handle.abort();
+/// Reads link duplex from `<sysfs_net>/<name>/duplex`. Returns `None` when duplex is unknown.
+pub fn read_link_duplex(sysfs_net: &Path, name: &str) -> Option<Duplex> {
+    let duplex = std::fs::read_to_string(sysfs_net.join(name).join("duplex")).ok()?;
 
-            Ok(links)
-        };
-        let links = local_set.block_on(&runtime, binding())?;
+    match duplex.trim() {
+        "full" => Some(Duplex::Full),
+        "half" => Some(Duplex::Half),
+        _ => None,
+    }
+}
 
-        Ok(links)
-    });
+/// sysfs shows network devices of the namespace it was mounted from, not of the reader's one.
+/// So current thread is moved to a private mount namespace, where a fresh sysfs is mounted over `/sys`.
+fn mount_netns_sysfs() -> std::io::Result<()> {
+    unsafe {
+        if libc::unshare(libc::CLONE_NEWNS) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
 
-    handle.join().await.map_err(QueryError::ThreadDied)?
+        // Make sure our mounts do not propagate back to the original mount namespace.
+        let code = libc::mount(
+            null(),
+            c"/".as_ptr(),
+            null(),
+            libc::MS_SLAVE | libc::MS_REC,
+            null(),
+        );
+        if code != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let code = libc::mount(
+            c"sysfs".as_ptr(),
+            c"/sys".as_ptr(),
+            c"sysfs".as_ptr(),
+            0,
+            null(),
+        );
+        if code != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
 }
 
 fn set_netns(fd: &std::fs::File) -> std::io::Result<()> {
@@ -152,3 +2325,81 @@ fn set_netns(fd: &std::fs::File) -> std::io::Result<()> {
         }
     }
 }
+
+/// Serde representations of what does not fit the derived ones.
+#[cfg(feature = "serde")]
+mod serde_repr {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{Mac, TunInfo, mac_from_str, mac_to_string};
+
+    /// Variants of an internally tagged enum can only hold maps, so details of a tun or tap go into a field.
+    pub mod tun_info {
+        use super::*;
+
+        #[derive(Serialize, Deserialize)]
+        struct Repr {
+            info: Option<TunInfo>,
+        }
+
+        pub fn serialize<S: Serializer>(
+            info: &Option<TunInfo>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            Repr { info: *info }.serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<TunInfo>, D::Error> {
+            Ok(Repr::deserialize(deserializer)?.info)
+        }
+    }
+
+    /// Same as `tun_info`, for the name of an unknown kind.
+    pub mod other_name {
+        use super::*;
+
+        #[derive(Serialize, Deserialize)]
+        struct Repr {
+            name: String,
+        }
+
+        pub fn serialize<S: Serializer>(name: &str, serializer: S) -> Result<S::Ok, S::Error> {
+            Repr {
+                name: name.to_owned(),
+            }
+            .serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+            Ok(Repr::deserialize(deserializer)?.name)
+        }
+    }
+
+    pub mod mac {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(
+            mac: &Option<Mac>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            mac.map(mac_to_string).serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<Mac>, D::Error> {
+            Option::<String>::deserialize(deserializer)?
+                .map(|mac| {
+                    mac_from_str(&mac).ok_or_else(|| {
+                        serde::de::Error::invalid_value(
+                            serde::de::Unexpected::Str(&mac),
+                            &"a hardware address like aa:bb:cc:dd:ee:ff",
+                        )
+                    })
+                })
+                .transpose()
+        }
+    }
+}
@@ -1,18 +1,21 @@
 use std::{
     collections::{HashMap, HashSet},
-    ffi::OsString,
+    ffi::{CString, OsString},
     fs::File,
     num::ParseIntError,
     os::{
         fd::{AsRawFd, RawFd},
-        unix::fs::MetadataExt,
+        unix::{ffi::OsStrExt, fs::MetadataExt},
     },
     path::{Component, Path, PathBuf},
     str::FromStr,
+    time::Duration,
 };
 
+use futures::{StreamExt, channel::mpsc::UnboundedReceiver};
 use glob::glob;
-use mountinfo::{FsType, MountInfo};
+use itertools::Itertools;
+use mountinfo::MountInfo;
 use rtnetlink::{
     new_connection,
     packet_core::{NLM_F_REQUEST, NetlinkMessage, NetlinkPayload},
@@ -20,15 +23,33 @@ use rtnetlink::{
         AddressFamily, RouteNetlinkMessage,
         nsid::{NsidAttribute, NsidMessage},
     },
+    sys::SocketAddr,
 };
 use thiserror::Error;
 use tokio::fs::metadata;
+use uuid::Uuid;
+
+use crate::mount_monitor::MountPoint;
 
 pub type INode = u64;
 pub type Pid = u32;
 pub type NsId = u32;
 
+/// `/proc` entry which was skipped during a scan, with the reason it could not be read.
+pub type Unreadable = (PathBuf, std::io::Error);
+
+/// Namespaces found by [`NetworkNamespace::all_with_unreadable`], with the entries the scan had to skip.
+/// If `unreadable` is not empty, processes (or descriptors) of the namespaces may be missing.
+#[derive(Debug)]
+pub struct NamespaceScan {
+    pub namespaces: Vec<NetworkNamespace>,
+    pub unreadable: Vec<Unreadable>,
+}
+
+/// With the `serde` feature, it can be serialized. Paths use the standard serde handling:
+/// they are strings, and serializing a path which is not valid UTF-8 fails.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NetworkNamespace {
     /// The way to differentiate namespaces on the system.
     /// Different namespaces will have different inodes, and same namespace will always have same inode.
@@ -42,8 +63,24 @@ pub struct NetworkNamespace {
     /// For example, `ip netns add <name>` creates a network namespace and binds it to `/run/netns/<name>` file.
     pub fs_path: HashSet<PathBuf>,
 
-    /// List of all processes that are running in that namespace
+    /// List of all processes that are running in that namespace.
+    ///
+    /// These are thread ids (`/proc/<pid>/task/<tid>`), since every thread has a namespace of its own
+    /// (`unshare(CLONE_NEWNET)` only moves the calling thread). For a single-threaded process it is its pid,
+    /// a multi-threaded one has an entry per thread, possibly in several namespaces.
     pub pids: Vec<Pid>,
+
+    /// Open file descriptors referring to the namespace (`/proc/<pid>/fd/<fd>`), of processes which are not
+    /// necessarily in it. Such a descriptor alone keeps a namespace alive. Only filled by [`NetworkNamespace::all`].
+    pub fds: Vec<PathBuf>,
+
+    /// Stable identity, which survives tracker restarts. Only assigned by `netns_tracker`,
+    /// see [`crate::netns_identity::IdentityStore`].
+    pub uuid: Option<Uuid>,
+
+    /// Name of the systemd-machined machine (nspawn container, VM) whose leader process runs in this namespace.
+    /// Only filled by [`crate::machined::label_machines`], with the `machined` feature.
+    pub machine: Option<String>,
 }
 
 #[derive(Debug, Error)]
@@ -56,21 +93,66 @@ pub enum Error {
     IoError(#[from] std::io::Error),
     #[error("failed to query netns id - {0}")]
     IdQueryFailed(#[from] IdError),
+    #[error("rtnetlink connection task failed - {0}")]
+    ConnectionTaskFailed(tokio::task::JoinError),
+    #[error("process {0} does not exist")]
+    NoSuchProcess(Pid),
+    #[error("network namespace of {0} kept changing while being read")]
+    NamespaceUnsettled(PathBuf),
+    #[error("there is no network namespace named {0:?}")]
+    NoSuchNamedNamespace(String),
 }
 
 impl NetworkNamespace {
+    /// Lists all network namespaces that are used by any process, bound to a file, or held by an open file descriptor.
+    ///
+    /// Scanning `/proc` is best-effort: processes that can not be inspected are skipped,
+    /// and if `/proc` is not accessible at all, only namespaces bound to files (nsfs mounts) are returned.
+    /// A process that switches namespace during the scan is listed in whichever one it was seen in,
+    /// see [`pid_netns_inode`] for a read that waits for it to settle.
     pub async fn all() -> Result<Vec<NetworkNamespace>, Error> {
+        Ok(Self::all_with_unreadable().await?.namespaces)
+    }
+
+    /// Same as [`NetworkNamespace::all`], but ids are queried over an existing connection,
+    /// instead of opening one for each call.
+    pub async fn all_with_handle(
+        handle: &mut rtnetlink::Handle,
+    ) -> Result<Vec<NetworkNamespace>, Error> {
+        Ok(Self::scan(Some(handle), Path::new(PROCFS))
+            .await?
+            .namespaces)
+    }
+
+    /// Same as [`NetworkNamespace::all`], but also returns the `/proc` entries which were skipped because they
+    /// could not be read (`hidepid`, processes of other users), so that callers know the result is partial.
+    ///
+    /// Processes which exited during the scan are not reported, there is nothing left to miss.
+    pub async fn all_with_unreadable() -> Result<NamespaceScan, Error> {
+        Self::scan(None, Path::new(PROCFS)).await
+    }
+
+    /// Same as [`NetworkNamespace::all_with_unreadable`], but processes are looked up in procfs mounted at `procfs`,
+    /// for example the one of the host, bind-mounted into a container.
+    ///
+    /// Namespaces bound to files still come from the mounts of the current process.
+    pub async fn all_in_procfs(procfs: &Path) -> Result<NamespaceScan, Error> {
+        Self::scan(None, procfs).await
+    }
+
+    /// Scan behind [`NetworkNamespace::all`]. Opens a connection for id queries, unless `handle` is given.
+    async fn scan(
+        handle: Option<&mut rtnetlink::Handle>,
+        procfs: &Path,
+    ) -> Result<NamespaceScan, Error> {
         // Map from netns inode, to list of PIDs in that inode.
         let mut inodes: HashMap<INode, NetworkNamespace> = HashMap::new();
 
         // Get all (possibly unnamed) network namespaces from processes list
-        let mut pids = PidsIterator::new();
-        loop {
-            let (_filepath, pid, inode) = match pids.next().await {
-                Ok(Some(x)) => x,
-                Ok(None) => break,
-                Err(_) => continue,
-            };
+        let (pids, mut unreadable) = PidsIterator::in_procfs(procfs)
+            .scan_concurrent(METADATA_CONCURRENCY)
+            .await;
+        for (_filepath, pid, inode) in pids {
             inodes
                 .entry(inode)
                 .and_modify(|netns| netns.pids.push(pid))
@@ -79,13 +161,21 @@ impl NetworkNamespace {
                     id: None,
                     fs_path: HashSet::new(),
                     pids: vec![pid],
+                    fds: vec![],
+                    uuid: None,
+                    machine: None,
                 });
         }
-        drop(pids);
 
         // Get all named namespaces from `/proc/self/mountinfo`.
-        let mut mounts = MountsIterator::new()?;
-        while let Some((path, inode)) = mounts.next().await? {
+        let (mounts, unreadable_mounts) = nsfs_mount_map_with_unreadable().await?;
+        // Same as for processes, a mount removed during the scan is not missed.
+        unreadable.extend(
+            unreadable_mounts
+                .into_iter()
+                .filter(|(_path, err)| err.kind() != std::io::ErrorKind::NotFound),
+        );
+        for (path, inode) in mounts {
             inodes
                 .entry(inode)
                 .and_modify(|netns| {
@@ -96,43 +186,85 @@ impl NetworkNamespace {
                     id: None,
                     fs_path: [path].into_iter().collect(),
                     pids: vec![],
+                    fds: vec![],
+                    uuid: None,
+                    machine: None,
+                });
+        }
+
+        // Namespaces which have neither processes nor files, and are only kept alive by an open descriptor.
+        let (fds, unreadable_fds) = scan_fd_namespaces(procfs).await;
+        unreadable.extend(unreadable_fds);
+        for (path, inode) in fds {
+            inodes
+                .entry(inode)
+                .and_modify(|netns| netns.fds.push(path.clone()))
+                .or_insert(NetworkNamespace {
+                    inode,
+                    id: None,
+                    fs_path: HashSet::new(),
+                    pids: vec![],
+                    fds: vec![path],
+                    uuid: None,
+                    machine: None,
                 });
         }
-        drop(mounts);
 
         // Try to query ids for each namespace
-        let (conn, mut handle, messages) = new_connection()?;
-        let task = tokio::spawn(conn);
+        match handle {
+            Some(handle) => Self::query_ids(handle, inodes.values_mut(), procfs).await?,
+            None => {
+                let mut resolver = NsIdResolver::new()?;
+                let result = Self::query_ids(resolver.handle(), inodes.values_mut(), procfs).await;
+                // Tear the connection down even if querying failed, so that it does not outlive this call.
+                resolver
+                    .close()
+                    .await
+                    .map_err(Error::ConnectionTaskFailed)?;
+                result?;
+            }
+        }
 
-        for (_, netns) in &mut inodes {
-            let Some(file) = netns.any_file() else {
+        Ok(NamespaceScan {
+            namespaces: inodes.into_values().collect(),
+            unreadable,
+        })
+    }
+
+    async fn query_ids(
+        handle: &mut rtnetlink::Handle,
+        namespaces: impl Iterator<Item = &mut NetworkNamespace>,
+        procfs: &Path,
+    ) -> Result<(), IdError> {
+        for netns in namespaces {
+            let Some(file) = netns.files_in_procfs(procfs).next() else {
                 continue;
             };
-            let Some(netnsid) = NetworkNamespace::id_by_path(&mut handle, file.as_path()).await?
-            else {
+            let Some(netnsid) = NetworkNamespace::id_by_path(handle, file.as_path()).await? else {
                 continue;
             };
             netns.id = Some(netnsid as u32);
         }
 
-        drop(handle);
-        drop(messages);
-        task.await.unwrap();
-
-        Ok(inodes.into_values().collect())
+        Ok(())
     }
 
     /// Returns an iterator of all all files that can be used to get a file descriptor of the inode.
     pub fn files(&self) -> impl Iterator<Item = PathBuf> {
+        self.files_in_procfs(Path::new(PROCFS))
+    }
+
+    /// Same as [`NetworkNamespace::files`], for processes found in procfs mounted at `procfs`.
+    fn files_in_procfs(&self, procfs: &Path) -> impl Iterator<Item = PathBuf> {
         self.fs_path
             .iter()
             .cloned()
-            .chain(self.pids.iter().map(|&pid| {
-                Path::new("/proc")
-                    .join(pid.to_string())
-                    .join("ns")
-                    .join("net")
-            }))
+            .chain(
+                self.pids
+                    .iter()
+                    .map(move |&pid| procfs.join(pid.to_string()).join("ns").join("net")),
+            )
+            .chain(self.fds.iter().cloned())
     }
 
     /// Returns any file that can be used to get a file descriptor for that network namespace.
@@ -140,7 +272,144 @@ impl NetworkNamespace {
         self.files().next()
     }
 
+    /// Name given with `ip netns`, i.e. file name of a path the namespace is bound to in `/run/netns`.
+    /// If there are several, the alphabetically first one.
+    pub fn name(&self) -> Option<&str> {
+        self.fs_path
+            .iter()
+            .filter(|path| path.parent() == Some(Path::new(NETNS_RUN_DIR)))
+            .filter_map(|path| path.file_name()?.to_str())
+            .min()
+    }
+
+    /// Looks up a namespace by the name given with `ip netns` (bound to `/run/netns/<name>`).
+    /// Returns `None` if there is no namespace with that name.
+    pub async fn by_name(
+        handle: &mut rtnetlink::Handle,
+        name: &str,
+    ) -> Result<Option<NetworkNamespace>, Error> {
+        match named_namespace_inode(name).await? {
+            Some(inode) => Self::by_inode(handle, inode).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Same as `LookupContext::by_inode`, with a fresh context.
+    pub async fn by_inode(
+        handle: &mut rtnetlink::Handle,
+        target_inode: INode,
+    ) -> Result<Option<NetworkNamespace>, Error> {
+        LookupContext::new()?.by_inode(handle, target_inode).await
+    }
+
+    /// Same as `by_inode`, but opens an rtnetlink connection of its own.
+    pub async fn by_inode_own_connection(
+        target_inode: INode,
+    ) -> Result<Option<NetworkNamespace>, Error> {
+        let mut resolver = NsIdResolver::new()?;
+        let result = Self::by_inode(resolver.handle(), target_inode).await;
+        resolver
+            .close()
+            .await
+            .map_err(Error::ConnectionTaskFailed)?;
+        result
+    }
+
+    pub async fn by_path(
+        handle: &mut rtnetlink::Handle,
+        path: &PathBuf,
+    ) -> Result<Option<NetworkNamespace>, Error> {
+        let metadata = metadata(path)
+            .await
+            .map_err(|err| Error::CouldntGetMetadata(path.clone(), err))?;
+
+        Self::by_inode(handle, metadata.ino()).await
+    }
+
+    pub async fn by_file(
+        handle: &mut rtnetlink::Handle,
+        file: &File,
+    ) -> Result<Option<NetworkNamespace>, Error> {
+        let metadata = file.metadata()?;
+
+        Self::by_inode(handle, metadata.ino()).await
+    }
+
+    /// Same as `LookupContext::by_id`, with a fresh context.
+    pub async fn by_id(
+        handle: &mut rtnetlink::Handle,
+        id: NsId,
+    ) -> Result<Option<NetworkNamespace>, Error> {
+        LookupContext::new()?.by_id(handle, id).await
+    }
+
+    /// Same as `by_id`, but opens an rtnetlink connection of its own.
+    /// It is still a single connection, shared by the id queries of all candidate namespaces.
+    pub async fn by_id_own_connection(id: NsId) -> Result<Option<NetworkNamespace>, Error> {
+        let mut resolver = NsIdResolver::new()?;
+        let result = Self::by_id(resolver.handle(), id).await;
+        resolver
+            .close()
+            .await
+            .map_err(Error::ConnectionTaskFailed)?;
+        result
+    }
+}
+
+/// rtnetlink connection for id queries, opened once and shared by all the queries made through it.
+struct NsIdResolver {
+    handle: rtnetlink::Handle,
+    messages: UnboundedReceiver<(NetlinkMessage<RouteNetlinkMessage>, SocketAddr)>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl NsIdResolver {
+    fn new() -> std::io::Result<Self> {
+        let (conn, handle, messages) = new_connection()?;
+
+        Ok(Self {
+            handle,
+            messages,
+            task: tokio::spawn(conn),
+        })
+    }
+
+    fn handle(&mut self) -> &mut rtnetlink::Handle {
+        &mut self.handle
+    }
+
+    /// Connection task only finishes once all the handles are dropped, so this waits for it.
+    async fn close(self) -> Result<(), tokio::task::JoinError> {
+        drop(self.handle);
+        drop(self.messages);
+        self.task.await
+    }
+}
+
+/// Snapshot of nsfs mounts, shared between several lookups, so that `/proc/self/mountinfo` is only parsed once.
+///
+/// Namespaces bound to files after the context was created are not visible through it,
+/// so it should only be kept for a series of lookups done in one go.
+#[derive(Debug, Clone)]
+pub struct LookupContext {
+    nsfs_mounts: Vec<PathBuf>,
+}
+
+impl LookupContext {
+    pub fn new() -> Result<Self, Error> {
+        Ok(Self {
+            nsfs_mounts: MountsIterator::new()?.mounts.collect(),
+        })
+    }
+
+    fn mounts(&self) -> MountsIterator {
+        MountsIterator {
+            mounts: Box::new(self.nsfs_mounts.clone().into_iter()),
+        }
+    }
+
     pub async fn by_inode(
+        &self,
         handle: &mut rtnetlink::Handle,
         target_inode: INode,
     ) -> Result<Option<NetworkNamespace>, Error> {
@@ -148,7 +417,7 @@ impl NetworkNamespace {
 
         // Get all (possibly unnamed) network namespaces from processes list
         let mut pids_iter = PidsIterator::new();
-        while let Some((_netns_link, pid, inode)) = pids_iter.next().await? {
+        while let Some((_netns_link, pid, inode)) = pids_iter.next_readable().await {
             if inode == target_inode {
                 pids.push(pid);
             }
@@ -156,7 +425,7 @@ impl NetworkNamespace {
 
         // Check if it is bound to a path
         let mut fs_path = HashSet::new();
-        let mut mounts = MountsIterator::new()?;
+        let mut mounts = self.mounts();
         while let Some((path, inode)) = mounts.next().await? {
             if inode == target_inode {
                 fs_path.insert(path);
@@ -173,51 +442,36 @@ impl NetworkNamespace {
             id: None,
             fs_path,
             pids,
+            fds: vec![],
+            uuid: None,
+            machine: None,
         };
 
         let path = netns.any_file().unwrap();
-        netns.id = Self::id_by_path(handle, &path).await?;
+        netns.id = NetworkNamespace::id_by_path(handle, &path).await?;
 
         Ok(Some(netns))
     }
 
-    pub async fn by_path(
-        handle: &mut rtnetlink::Handle,
-        path: &PathBuf,
-    ) -> Result<Option<NetworkNamespace>, Error> {
-        let metadata = metadata(path)
-            .await
-            .map_err(|err| Error::CouldntGetMetadata(path.clone(), err))?;
-
-        Self::by_inode(handle, metadata.ino()).await
-    }
-
-    pub async fn by_file(
-        handle: &mut rtnetlink::Handle,
-        file: &File,
-    ) -> Result<Option<NetworkNamespace>, Error> {
-        let metadata = file.metadata()?;
-
-        Self::by_inode(handle, metadata.ino()).await
-    }
-
     pub async fn by_id(
+        &self,
         handle: &mut rtnetlink::Handle,
         id: NsId,
     ) -> Result<Option<NetworkNamespace>, Error> {
         let mut all_files: HashMap<INode, PathBuf> = HashMap::new();
 
-        let mut mounts = MountsIterator::new()?;
+        let mut mounts = self.mounts();
         while let Some((path, inode)) = mounts.next().await? {
             all_files.entry(inode).or_insert(path);
         }
 
         for (inode, filepath) in all_files {
-            if Some(id) == Self::id_by_path(handle, filepath.as_path()).await? {
+            if Some(id) == NetworkNamespace::id_by_path(handle, filepath.as_path()).await? {
                 let mut pids = Vec::new();
 
                 let mut pids_iter = PidsIterator::new();
-                while let Some((_netns_link, pid, current_inode)) = pids_iter.next().await? {
+                while let Some((_netns_link, pid, current_inode)) = pids_iter.next_readable().await
+                {
                     if inode == current_inode {
                         pids.push(pid);
                     }
@@ -228,6 +482,9 @@ impl NetworkNamespace {
                     id: Some(id),
                     fs_path: [filepath].into_iter().collect(),
                     pids,
+                    fds: vec![],
+                    uuid: None,
+                    machine: None,
                 }));
             }
         }
@@ -242,18 +499,20 @@ pub enum IdError {
     CouldntOpenNetns(#[from] std::io::Error),
     #[error("failed to do rtnetlink request - {0}")]
     Rtnetlink(#[from] rtnetlink::Error),
+    #[error("rtnetlink connection task failed - {0}")]
+    ConnectionTaskFailed(tokio::task::JoinError),
 }
 
 impl NetworkNamespace {
     pub async fn id_by_path_own_connection(filepath: &Path) -> Result<Option<NsId>, IdError> {
-        let (conn, mut handle, messages) = rtnetlink::new_connection()?;
-        let task = tokio::spawn(conn);
+        let mut resolver = NsIdResolver::new()?;
 
-        let result = Self::id_by_path(&mut handle, filepath).await;
+        let result = Self::id_by_path(resolver.handle(), filepath).await;
 
-        drop(handle);
-        drop(messages);
-        task.await.unwrap();
+        resolver
+            .close()
+            .await
+            .map_err(IdError::ConnectionTaskFailed)?;
 
         result
     }
@@ -288,8 +547,6 @@ impl NetworkNamespace {
 
         let mut responses = handle.request(request)?;
 
-        use futures::StreamExt;
-
         while let Some(msg) = responses.next().await {
             match msg.payload {
                 NetlinkPayload::InnerMessage(RouteNetlinkMessage::NewNsId(NsidMessage {
@@ -313,15 +570,440 @@ impl NetworkNamespace {
     }
 }
 
+/// `NS_GET_NSTYPE` ioctl request (`_IO(0xb7, 0x3)`) - returns `CLONE_NEW*` type of the namespace.
+const NS_GET_NSTYPE: libc::c_ulong = 0xb703;
+
+/// Whether a mount of the given filesystem type is nsfs, i.e. a namespace bound to a file.
+pub fn is_nsfs(fstype: &crate::mount_monitor::FsType) -> bool {
+    matches!(fstype, crate::mount_monitor::FsType::Other(name) if name == "nsfs")
+}
+
+/// Whether the file at `path` (following symlinks, like `/proc/<pid>/ns/net`) is on nsfs.
+pub fn is_nsfs_path(path: &Path) -> std::io::Result<bool> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stat) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(stat.f_type == libc::NSFS_MAGIC as _)
+}
+
+/// Returns inode of the network namespace an nsfs mount refers to.
+///
+/// Returns `None` for non-nsfs mounts, and for nsfs mounts of other namespace types (mount, pid, ...).
+pub async fn namespace_for_mount(mount: &MountPoint) -> std::io::Result<Option<INode>> {
+    if !is_nsfs(&mount.fstype) {
+        return Ok(None);
+    }
+
+    let path = mount.path.clone();
+    tokio::task::spawn_blocking(move || netns_inode_of_nsfs_file(&path))
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+/// Blocking part of `namespace_for_mount`, for a file already known to be on nsfs.
+pub(crate) fn netns_inode_of_nsfs_file(path: &Path) -> std::io::Result<Option<INode>> {
+    let file = File::open(path)?;
+    let ns_type = unsafe { libc::ioctl(file.as_raw_fd(), NS_GET_NSTYPE) };
+    if ns_type < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    if ns_type != libc::CLONE_NEWNET {
+        return Ok(None);
+    }
+
+    Ok(Some(file.metadata()?.ino()))
+}
+
+/// Returns distinct network namespaces the given processes are running in.
+///
+/// Processes that do not exist (anymore) are skipped.
+pub async fn namespaces_for_pids(
+    handle: &mut rtnetlink::Handle,
+    pids: &[Pid],
+) -> Result<Vec<NetworkNamespace>, Error> {
+    let mut inodes = Vec::new();
+    for &pid in pids {
+        let path = Path::new("/proc")
+            .join(pid.to_string())
+            .join("ns")
+            .join("net");
+
+        match metadata(&path).await {
+            Ok(meta) if !inodes.contains(&meta.ino()) => inodes.push(meta.ino()),
+            Ok(_) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(Error::CouldntGetMetadata(path, err)),
+        }
+    }
+
+    let context = LookupContext::new()?;
+    let mut namespaces = Vec::with_capacity(inodes.len());
+    for inode in inodes {
+        // Namespace could have vanished in the meantime, together with its processes.
+        if let Some(netns) = context.by_inode(handle, inode).await? {
+            namespaces.push(netns);
+        }
+    }
+
+    Ok(namespaces)
+}
+
+/// Maps every process (thread, as in `NetworkNamespace::pids`) to the inode of its network namespace.
+///
+/// Same `/proc` scan as [`NetworkNamespace::all`], without grouping into namespaces,
+/// mounts scanning or id queries. Processes that can not be inspected are skipped.
+pub async fn pid_namespace_table() -> Result<HashMap<Pid, INode>, Error> {
+    collect_pid_namespace_table(PidsIterator::new()).await
+}
+
+/// Same as [`pid_namespace_table`], but namespace of each process is read again after `recheck`,
+/// until it settles (see [`pid_netns_inode`]). Processes that are moving between namespaces
+/// for longer than that are skipped. Takes at least `recheck` longer.
+pub async fn settled_pid_namespace_table(recheck: Duration) -> Result<HashMap<Pid, INode>, Error> {
+    collect_pid_namespace_table(PidsIterator::new().recheck_after(recheck)).await
+}
+
+async fn collect_pid_namespace_table(pids: PidsIterator) -> Result<HashMap<Pid, INode>, Error> {
+    let pids = pids.readable_concurrent(METADATA_CONCURRENCY).await;
+
+    Ok(pids
+        .into_iter()
+        .map(|(_filepath, pid, inode)| (pid, inode))
+        .collect())
+}
+
+/// Maps every nsfs mount of a network namespace (file it is bound to, like `/run/netns/<name>`) to its inode.
+///
+/// `/proc/self/mountinfo` is parsed once, and files are inspected concurrently. Mounts of other namespace types
+/// (mount, pid, ...) are left out. Mounts that can not be inspected, for example ones removed since mountinfo
+/// was read, are skipped.
+pub async fn nsfs_mount_map() -> Result<HashMap<PathBuf, INode>, Error> {
+    Ok(nsfs_mount_map_with_unreadable().await?.0)
+}
+
+/// Same as [`nsfs_mount_map`], but also returns the mounts which were skipped, with the reason.
+pub async fn nsfs_mount_map_with_unreadable()
+-> Result<(HashMap<PathBuf, INode>, Vec<Unreadable>), Error> {
+    let (mounts, unreadable) = MountsIterator::new()?
+        .scan_concurrent(METADATA_CONCURRENCY)
+        .await;
+
+    Ok((mounts.into_iter().collect(), unreadable))
+}
+
+/// Maps every open file descriptor that refers to a network namespace (`/proc/<pid>/fd/<fd>`) to the inode of it.
+///
+/// Best-effort, same as the process scan: processes whose descriptors can not be read are skipped.
+pub async fn fd_namespace_map() -> Result<HashMap<PathBuf, INode>, Error> {
+    let (fds, _unreadable) = scan_fd_namespaces(Path::new(PROCFS)).await;
+    Ok(fds)
+}
+
+/// Scan behind [`fd_namespace_map`], which also returns descriptors (or whole `fd` directories) that could not be read.
+async fn scan_fd_namespaces(procfs: &Path) -> (HashMap<PathBuf, INode>, Vec<Unreadable>) {
+    let mut unreadable = vec![];
+    let files = glob(&procfs_pattern(procfs, PROCFS_FD_GLOB_PATTERN))
+        .expect("Pattern should be correct")
+        .filter_map(|file| match file {
+            Ok(file) => Some(file),
+            // `fd` directory of a process of another user.
+            Err(err) => {
+                if err.error().kind() != std::io::ErrorKind::NotFound {
+                    unreadable.push((err.path().to_owned(), err.into()));
+                }
+                None
+            }
+        })
+        // `/proc/self` and `/proc/thread-self` would list own descriptors once more.
+        .filter(|file| {
+            file.strip_prefix(procfs)
+                .ok()
+                .and_then(|file| file.iter().next())
+                .and_then(parse_pid)
+                .is_some()
+        })
+        .collect::<Vec<_>>();
+
+    let mut fds = HashMap::new();
+    let mut entries = futures::stream::iter(files)
+        .map(async |file| {
+            let target = tokio::fs::read_link(&file).await;
+            (file, target)
+        })
+        .buffer_unordered(METADATA_CONCURRENCY);
+    while let Some((file, target)) = entries.next().await {
+        match target {
+            Ok(target) => {
+                if let Some(inode) = target.to_str().and_then(parse_netns_link_target) {
+                    fds.insert(file, inode);
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => unreadable.push((file, err)),
+        }
+    }
+
+    (fds, unreadable)
+}
+
+/// Directory where `ip netns` binds named network namespaces.
+const NETNS_RUN_DIR: &str = "/run/netns";
+
+/// Lists namespaces named with `ip netns` (bound in `/run/netns`), with their inodes and ids.
+///
+/// Much cheaper than [`NetworkNamespace::all`], since neither `/proc` nor mountinfo is scanned,
+/// and all ids are queried over a single rtnetlink connection.
+pub async fn named_namespaces() -> Result<Vec<(String, INode, Option<NsId>)>, Error> {
+    let run_dir = match metadata(NETNS_RUN_DIR).await {
+        Ok(meta) => meta,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(Error::CouldntGetMetadata(NETNS_RUN_DIR.into(), err)),
+    };
+
+    let mut named = Vec::new();
+    let mut entries = tokio::fs::read_dir(NETNS_RUN_DIR).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        let Ok(meta) = metadata(entry.path()).await else {
+            continue;
+        };
+        // A file which is not bound to a namespace (anymore) lives on the same filesystem as the directory.
+        if meta.dev() == run_dir.dev() {
+            continue;
+        }
+        let netns = NetworkNamespace {
+            inode: meta.ino(),
+            id: None,
+            fs_path: [entry.path()].into_iter().collect(),
+            pids: vec![],
+            fds: vec![],
+            uuid: None,
+            machine: None,
+        };
+        named.push((name, netns));
+    }
+
+    let mut resolver = NsIdResolver::new()?;
+    let result = NetworkNamespace::query_ids(
+        resolver.handle(),
+        named.iter_mut().map(|(_, netns)| netns),
+        Path::new(PROCFS),
+    )
+    .await;
+    resolver
+        .close()
+        .await
+        .map_err(Error::ConnectionTaskFailed)?;
+    result?;
+
+    Ok(named
+        .into_iter()
+        .map(|(name, netns)| (name, netns.inode, netns.id))
+        .collect())
+}
+
+/// Processes (threads, as in `NetworkNamespace::pids`) running in the namespace named `name` with `ip netns`.
+///
+/// Name is resolved the same way as in [`named_namespaces`]: `/run/netns/<name>` has to be bound to a namespace.
+/// Then `/proc` is scanned as in [`pid_namespace_table`]. Pids are sorted.
+pub async fn pids_in_named_namespace(name: &str) -> Result<Vec<Pid>, Error> {
+    let Some(target) = named_namespace_inode(name).await? else {
+        return Err(Error::NoSuchNamedNamespace(name.to_owned()));
+    };
+
+    let mut pids: Vec<Pid> = pid_namespace_table()
+        .await?
+        .into_iter()
+        .filter(|&(_pid, inode)| inode == target)
+        .map(|(pid, _inode)| pid)
+        .collect();
+    pids.sort();
+
+    Ok(pids)
+}
+
+/// Inode of the namespace bound to `/run/netns/<name>`. `None` if there is no such file,
+/// or it is not bound to a namespace (anymore).
+async fn named_namespace_inode(name: &str) -> Result<Option<INode>, Error> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Ok(None);
+    }
+
+    let run_dir = match metadata(NETNS_RUN_DIR).await {
+        Ok(meta) => meta,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(Error::CouldntGetMetadata(NETNS_RUN_DIR.into(), err)),
+    };
+    let path = Path::new(NETNS_RUN_DIR).join(name);
+    let meta = match metadata(&path).await {
+        Ok(meta) => meta,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(Error::CouldntGetMetadata(path, err)),
+    };
+    // A file which is not bound to a namespace lives on the same filesystem as the directory.
+    if meta.dev() == run_dir.dev() {
+        return Ok(None);
+    }
+
+    Ok(Some(meta.ino()))
+}
+
+/// NETNSIDs assigned to namespace `target_inode`, by each namespace that has assigned one.
+///
+/// Ids are relative to the namespace that assigned them, so the same target can have different ids in different namespaces.
+/// `handle` is used for the current namespace, other ones are queried from threads moved into them.
+/// Namespaces that can not be entered are skipped.
+pub async fn nsid_views(
+    handle: &mut rtnetlink::Handle,
+    target_inode: INode,
+) -> Result<HashMap<INode, NsId>, Error> {
+    let namespaces = NetworkNamespace::all().await?;
+    let Some(target_file) = namespaces
+        .iter()
+        .find(|netns| netns.inode == target_inode)
+        .and_then(NetworkNamespace::any_file)
+    else {
+        return Ok(HashMap::new());
+    };
+    let own_inode = metadata("/proc/self/ns/net").await?.ino();
+
+    let mut views = HashMap::new();
+    for netns in &namespaces {
+        let id = if netns.inode == own_inode {
+            NetworkNamespace::id_by_path(handle, &target_file).await?
+        } else {
+            let Some(file) = netns.any_file() else {
+                continue;
+            };
+            let target_file = target_file.clone();
+            let id = crate::net_device::in_namespace(file, move || async move {
+                // Socket belongs to the namespace of the thread it was opened in.
+                let Ok((conn, mut handle, _)) = new_connection() else {
+                    return Ok(None);
+                };
+                let conn_task = tokio::spawn(conn);
+                let id = NetworkNamespace::id_by_path(&mut handle, &target_file).await;
+                conn_task.abort();
+                Ok(id.ok().flatten())
+            })
+            .await;
+
+            id.ok().flatten()
+        };
+
+        if let Some(id) = id {
+            views.insert(netns.inode, id);
+        }
+    }
+
+    Ok(views)
+}
+
+/// Returns network namespace of a container, given PID of its init process (as reported by container runtime),
+/// with all the processes sharing it.
+pub async fn container_netns(init_pid: Pid) -> Result<NetworkNamespace, Error> {
+    let path = Path::new("/proc")
+        .join(init_pid.to_string())
+        .join("ns")
+        .join("net");
+
+    let inode = match metadata(&path).await {
+        Ok(meta) => meta.ino(),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Err(Error::NoSuchProcess(init_pid));
+        }
+        Err(err) => return Err(Error::CouldntGetMetadata(path, err)),
+    };
+
+    let result = NetworkNamespace::by_inode_own_connection(inode).await;
+
+    // Namespace is gone only if the whole container exited meanwhile.
+    result?.ok_or(Error::NoSuchProcess(init_pid))
+}
+
+/// Finds network namespace of a socket (inode as in `/proc/net/tcp` and such),
+/// by looking for a process holding it in `/proc/*/fd`.
+///
+/// Returns `None` if no (inspectable) process holds the socket.
+pub fn netns_for_socket(socket_inode: u64) -> std::io::Result<Option<INode>> {
+    let target = format!("socket:[{socket_inode}]");
+
+    for process in std::fs::read_dir("/proc")?.filter_map(|entry| entry.ok()) {
+        if parse_pid(&process.file_name()).is_none() {
+            continue;
+        }
+        // Processes can exit, and fds of other users' processes are not readable.
+        let Ok(fds) = std::fs::read_dir(process.path().join("fd")) else {
+            continue;
+        };
+
+        let holds_socket = fds
+            .filter_map(|fd| fd.ok())
+            .filter_map(|fd| std::fs::read_link(fd.path()).ok())
+            .any(|link| link.as_os_str() == target.as_str());
+
+        if holds_socket {
+            match std::fs::metadata(process.path().join("ns").join("net")) {
+                Ok(meta) => return Ok(Some(meta.ino())),
+                Err(_) => continue,
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+fn parse_pid(name: &std::ffi::OsStr) -> Option<Pid> {
+    name.to_str()?.parse().ok()
+}
+
+/// Formats namespaces as CSV: a header, and one row per namespace.
+///
+/// Columns are `inode,id,path,pid_count`. Missing id is an empty field.
+/// Namespaces bound to several files have them joined with `;` in a single `path` field.
+pub fn namespaces_to_csv(namespaces: &[NetworkNamespace]) -> String {
+    let mut csv = String::from("inode,id,path,pid_count\n");
+
+    for netns in namespaces {
+        let id = netns.id.map(|id| id.to_string()).unwrap_or_default();
+        let path = netns
+            .fs_path
+            .iter()
+            .map(|path| path.to_string_lossy())
+            .sorted()
+            .join(";");
+
+        csv += &format!(
+            "{},{},{},{}\n",
+            netns.inode,
+            id,
+            escape_csv_field(&path),
+            netns.pids.len()
+        );
+    }
+
+    csv
+}
+
+/// Quotes a CSV field (RFC 4180) if it contains separators, quotes or line breaks.
+fn escape_csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
 // ==== Utilities ====
 
 #[derive(Debug, Error)]
 enum ParseProcfsError {
-    #[error("path is not absolute")]
-    NotAbsolute,
-    #[error("path does not start with root")]
-    DoesntStartWithRoot,
-    #[error("path does not start with `/proc/`")]
+    #[error("path is not in procfs")]
     NonProc,
     #[error("path does not contain a TGID")]
     NoTgid,
@@ -339,22 +1021,12 @@ enum ParseProcfsError {
 }
 
 /// Parses `/proc/<tgid>/task/<pid>/`. Returns `pid`.
-fn parse_procfs_path_start(path: &PathBuf) -> Result<u64, ParseProcfsError> {
-    if !path.is_absolute() {
-        return Err(ParseProcfsError::NotAbsolute);
-    }
-    let mut components = path.components();
-
-    // `/`
-    if !matches!(components.next(), Some(std::path::Component::RootDir)) {
-        return Err(ParseProcfsError::DoesntStartWithRoot);
-    }
-
-    // `proc/`
-    let proc: OsString = OsString::from_str("proc").unwrap();
-    if !matches!(components.next(), Some(std::path::Component::Normal(x)) if x == proc) {
-        return Err(ParseProcfsError::NonProc);
-    }
+fn parse_procfs_path_start(path: &Path, procfs: &Path) -> Result<u64, ParseProcfsError> {
+    // `/proc/`, or wherever procfs is mounted
+    let mut components = path
+        .strip_prefix(procfs)
+        .map_err(|_| ParseProcfsError::NonProc)?
+        .components();
 
     // TGID
     let Some(Component::Normal(tgid)) = components.next() else {
@@ -390,36 +1062,199 @@ fn parse_procfs_path_start(path: &PathBuf) -> Result<u64, ParseProcfsError> {
     return Ok(pid);
 }
 
+/// Parses target of a `/proc/<pid>/ns/net` symlink, which looks like `net:[<inode>]`.
+pub fn parse_netns_link_target(target: &str) -> Option<INode> {
+    target
+        .strip_prefix("net:[")?
+        .strip_suffix(']')?
+        .parse()
+        .ok()
+}
+
+/// Gets inode of a `/proc/<pid>/ns/net` symlink from its target, which saves a `stat` call.
+/// Falls back to metadata if the link can not be read, or target is not in the expected format.
+async fn netns_link_inode(path: &Path) -> std::io::Result<INode> {
+    let target = tokio::fs::read_link(path).await.ok();
+    if let Some(inode) = target
+        .as_deref()
+        .and_then(Path::to_str)
+        .and_then(parse_netns_link_target)
+    {
+        return Ok(inode);
+    }
+
+    Ok(metadata(path).await?.ino())
+}
+
+/// How many times a namespace of a process is read at most, while waiting for it to settle.
+const RECHECK_ATTEMPTS: usize = 3;
+
+/// Reads namespace of a process (`/proc/<pid>/ns/net`) until two consecutive reads, `recheck` apart, agree.
+/// Without `recheck`, the first read is returned.
+async fn settled_netns_link_inode(path: &Path, recheck: Option<Duration>) -> Result<INode, Error> {
+    let read = async |path: &Path| {
+        netns_link_inode(path)
+            .await
+            .map_err(|err| Error::CouldntGetMetadata(path.to_owned(), err))
+    };
+
+    let mut inode = read(path).await?;
+    let Some(delay) = recheck else {
+        return Ok(inode);
+    };
+
+    for _ in 1..RECHECK_ATTEMPTS {
+        tokio::time::sleep(delay).await;
+        let again = read(path).await?;
+        if again == inode {
+            return Ok(inode);
+        }
+        inode = again;
+    }
+
+    Err(Error::NamespaceUnsettled(path.to_owned()))
+}
+
+/// Returns inode of the network namespace process `pid` is in.
+///
+/// A process can switch namespaces (`setns`, `unshare`) at any moment, so the result may be stale
+/// as soon as it is returned. With `recheck`, the namespace is read again after that delay,
+/// until two reads agree, which filters out processes that are in the middle of moving
+/// (e.g. just spawned by `ip netns exec`). Fails with [`Error::NamespaceUnsettled`] if they never do.
+pub async fn pid_netns_inode(pid: Pid, recheck: Option<Duration>) -> Result<INode, Error> {
+    let path = Path::new("/proc")
+        .join(pid.to_string())
+        .join("ns")
+        .join("net");
+
+    match settled_netns_link_inode(&path, recheck).await {
+        Err(Error::CouldntGetMetadata(_, err)) if err.kind() == std::io::ErrorKind::NotFound => {
+            Err(Error::NoSuchProcess(pid))
+        }
+        result => result,
+    }
+}
+
+/// Iterates over all processes and their network namespaces.
+///
+/// Namespace of each process is read once, at the moment its entry is reached. Processes which change
+/// namespace during the scan are reported in the namespace they were in at that moment, unless
+/// [`PidsIterator::recheck_after`] is used.
 pub(crate) struct PidsIterator {
-    files: Box<dyn Send + Iterator<Item = (PathBuf, u64)>>,
+    files: Box<dyn Send + Iterator<Item = Result<(PathBuf, u64), Error>>>,
+    recheck: Option<Duration>,
 }
 
-const PROCFS_GLOB_PATTERN: &'static str = "/proc/*/task/*/ns/net";
+const PROCFS: &str = "/proc";
+/// Patterns of files to scan, relative to procfs.
+const PROCFS_GLOB_PATTERN: &str = "*/task/*/ns/net";
+const PROCFS_FD_GLOB_PATTERN: &str = "*/fd/*";
+
+fn procfs_pattern(procfs: &Path, pattern: &str) -> String {
+    format!(
+        "{}/{pattern}",
+        glob::Pattern::escape(&procfs.to_string_lossy())
+    )
+}
+
+/// How many `metadata()` calls are allowed to be in flight at once while scanning.
+const METADATA_CONCURRENCY: usize = 64;
 
 impl PidsIterator {
     pub fn new() -> Self {
-        let files = glob(PROCFS_GLOB_PATTERN)
+        Self::in_procfs(Path::new(PROCFS))
+    }
+
+    /// Scans processes of procfs mounted at `procfs`. If it can not be listed, there are no processes.
+    pub fn in_procfs(procfs: &Path) -> Self {
+        let procfs = procfs.to_owned();
+        let files = glob(&procfs_pattern(&procfs, PROCFS_GLOB_PATTERN))
             .expect("Pattern should be correct")
-            .filter_map(|file| file.ok())
-            .filter_map(|file| parse_procfs_path_start(&file).map(|pid| (file, pid)).ok());
+            .filter_map(move |file| match file {
+                Ok(file) => parse_procfs_path_start(&file, &procfs)
+                    .map(|pid| Ok((file, pid)))
+                    .ok(),
+                // Directory of a process that can not be listed (`hidepid`, another user).
+                Err(err) => Some(Err(Error::CouldntGetMetadata(
+                    err.path().to_owned(),
+                    err.into(),
+                ))),
+            });
 
         Self {
             files: Box::new(files),
+            recheck: None,
         }
     }
 
+    /// Re-reads namespace of each process after `delay`, until it settles (see [`pid_netns_inode`]).
+    /// Processes whose namespace does not settle are reported as unreadable.
+    pub fn recheck_after(mut self, delay: Duration) -> Self {
+        self.recheck = Some(delay);
+        self
+    }
+
     pub async fn next(&mut self) -> Result<Option<(PathBuf, Pid, INode)>, Error> {
         match self.files.next() {
-            Some((file, pid)) => {
-                let metadata = metadata(&file)
-                    .await
-                    .map_err(|err| Error::CouldntGetMetadata(file.clone(), err))?;
+            Some(Ok((file, pid))) => {
+                let inode = settled_netns_link_inode(&file, self.recheck).await?;
 
-                Ok(Some((file, pid as Pid, metadata.ino())))
+                Ok(Some((file, pid as Pid, inode)))
             }
+            Some(Err(err)) => Err(err),
             None => Ok(None),
         }
     }
+
+    /// Same as `next`, but skips entries that can not be read (process exited, or access to it is restricted).
+    pub async fn next_readable(&mut self) -> Option<(PathBuf, Pid, INode)> {
+        loop {
+            match self.next().await {
+                Ok(Some(x)) => return Some(x),
+                Ok(None) => return None,
+                Err(_) => continue,
+            }
+        }
+    }
+
+    /// Reads all the remaining entries, with up to `concurrency` metadata reads in flight at once.
+    /// Entries that can not be read are skipped, same as in `next_readable`.
+    pub async fn readable_concurrent(self, concurrency: usize) -> Vec<(PathBuf, Pid, INode)> {
+        let (entries, _unreadable) = self.scan_concurrent(concurrency).await;
+        entries
+    }
+
+    /// Same as `readable_concurrent`, but also returns entries which were skipped because access to them was denied.
+    /// Entries of processes that exited during the scan, and ones that did not settle, are just skipped.
+    pub async fn scan_concurrent(
+        self,
+        concurrency: usize,
+    ) -> (Vec<(PathBuf, Pid, INode)>, Vec<Unreadable>) {
+        let recheck = self.recheck;
+        let mut results = futures::stream::iter(self.files)
+            .map(async |entry| {
+                let (file, pid) = entry?;
+                let inode = settled_netns_link_inode(&file, recheck).await?;
+                Ok((file, pid as Pid, inode))
+            })
+            .buffer_unordered(concurrency);
+
+        let mut entries = vec![];
+        let mut unreadable = vec![];
+        while let Some(result) = results.next().await {
+            match result {
+                Ok(entry) => entries.push(entry),
+                Err(Error::CouldntGetMetadata(path, err))
+                    if err.kind() != std::io::ErrorKind::NotFound =>
+                {
+                    unreadable.push((path, err))
+                }
+                Err(_) => {}
+            }
+        }
+
+        (entries, unreadable)
+    }
 }
 
 struct MountsIterator {
@@ -432,8 +1267,7 @@ impl MountsIterator {
         let mounts = mounts
             .mounting_points
             .into_iter()
-            .filter(|x| x.fstype == FsType::Other("nsfs".to_owned()))
-            .map(|x| x.path);
+            .filter_map(|x| is_nsfs(&x.fstype.into()).then_some(x.path));
 
         Ok(Self {
             mounts: Box::new(mounts),
@@ -452,4 +1286,35 @@ impl MountsIterator {
             }
         }
     }
+
+    /// Reads all the remaining mounts, with up to `concurrency` files inspected at once.
+    /// Mounts of other namespace types are skipped, and ones that can not be inspected are returned separately.
+    pub async fn scan_concurrent(
+        self,
+        concurrency: usize,
+    ) -> (Vec<(PathBuf, INode)>, Vec<Unreadable>) {
+        let mut results = futures::stream::iter(self.mounts)
+            .map(async |mount| {
+                let path = mount.clone();
+                let inode = async move {
+                    tokio::task::spawn_blocking(move || netns_inode_of_nsfs_file(&path))
+                        .await
+                        .map_err(std::io::Error::other)?
+                };
+                (mount, inode.await)
+            })
+            .buffer_unordered(concurrency);
+
+        let mut entries = vec![];
+        let mut unreadable = vec![];
+        while let Some((mount, inode)) = results.next().await {
+            match inode {
+                Ok(Some(inode)) => entries.push((mount, inode)),
+                Ok(None) => {}
+                Err(err) => unreadable.push((mount, err)),
+            }
+        }
+
+        (entries, unreadable)
+    }
 }
@@ -0,0 +1,170 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use uuid::Uuid;
+
+use crate::netns::INode;
+
+/// Stable identifier of a network namespace, assigned when it was first seen.
+///
+/// Inodes of anonymous namespaces are meaningless to humans, and get recycled once namespace is gone.
+/// UUID survives restarts of the tracker, as long as the namespace is still alive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Identity {
+    pub uuid: Uuid,
+    pub inode: INode,
+    pub first_seen: SystemTime,
+}
+
+/// Mapping from namespace inodes to their identities, optionally persisted to a file.
+///
+/// File starts with a `boot_id <boot id>` line, followed by one identity per line:
+/// `<uuid> <inode> <first seen, seconds since UNIX epoch>`.
+#[derive(Debug, Clone, Default)]
+pub struct IdentityStore {
+    path: Option<PathBuf>,
+    boot_id: String,
+    identities: HashMap<INode, Identity>,
+    dirty: bool,
+}
+
+const BOOT_ID_PATH: &str = "/proc/sys/kernel/random/boot_id";
+
+/// Random id of the current boot. Changes on each reboot, unlike namespace inodes which are reused.
+pub fn boot_id() -> std::io::Result<String> {
+    Ok(std::fs::read_to_string(BOOT_ID_PATH)?.trim().to_owned())
+}
+
+impl IdentityStore {
+    /// Store that is never persisted.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Loads identities from a file. Missing file is treated as empty, and will be created on `save`.
+    ///
+    /// Identities saved during another boot are discarded, since inodes do not survive reboots.
+    /// Malformed lines are skipped.
+    pub fn load(path: PathBuf) -> std::io::Result<Self> {
+        Self::load_with_boot_id(path, boot_id()?)
+    }
+
+    /// Same as `load`, but with an explicitly given id of the current boot.
+    pub fn load_with_boot_id(path: PathBuf, boot_id: String) -> std::io::Result<Self> {
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err),
+        };
+
+        let mut lines = contents.lines();
+        let saved_boot_id = lines.next().and_then(|line| line.strip_prefix("boot_id "));
+
+        let identities: HashMap<INode, Identity> = if saved_boot_id == Some(boot_id.as_str()) {
+            lines
+                .filter_map(parse_identity)
+                .map(|identity| (identity.inode, identity))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            path: Some(path),
+            // Stale file should be overwritten even if nothing changes afterwards.
+            dirty: saved_boot_id.is_some_and(|saved| saved != boot_id),
+            boot_id,
+            identities,
+        })
+    }
+
+    pub fn path(&self) -> Option<&PathBuf> {
+        self.path.as_ref()
+    }
+
+    pub fn get(&self, inode: INode) -> Option<&Identity> {
+        self.identities.get(&inode)
+    }
+
+    pub fn identities(&self) -> impl Iterator<Item = &Identity> {
+        self.identities.values()
+    }
+
+    /// Returns identity of the namespace, assigning a new one if it was not seen before.
+    pub fn get_or_assign(&mut self, inode: INode) -> &Identity {
+        self.identities.entry(inode).or_insert_with(|| {
+            self.dirty = true;
+            Identity {
+                uuid: Uuid::new_v4(),
+                inode,
+                first_seen: SystemTime::now(),
+            }
+        })
+    }
+
+    /// Forgets identity of a namespace that is gone, since its inode may be reused by another namespace.
+    pub fn forget(&mut self, inode: INode) -> Option<Identity> {
+        let removed = self.identities.remove(&inode);
+        self.dirty |= removed.is_some();
+        removed
+    }
+
+    /// Only keeps identities of namespaces for which `is_alive` returns `true`.
+    pub fn retain(&mut self, mut is_alive: impl FnMut(INode) -> bool) {
+        let count = self.identities.len();
+        self.identities.retain(|&inode, _| is_alive(inode));
+        self.dirty |= count != self.identities.len();
+    }
+
+    /// Whether there are changes that were not saved yet.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Writes identities to the file (if store has one). File is replaced atomically.
+    pub fn save(&mut self) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            self.dirty = false;
+            return Ok(());
+        };
+
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut file = std::io::BufWriter::new(std::fs::File::create(&tmp_path)?);
+            writeln!(file, "boot_id {}", self.boot_id)?;
+            for identity in self.identities.values() {
+                let first_seen = identity
+                    .first_seen
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                writeln!(file, "{} {} {}", identity.uuid, identity.inode, first_seen)?;
+            }
+            // Contents should be on disk before the rename makes them visible, or a crash could leave an empty file.
+            file.into_inner()
+                .map_err(std::io::IntoInnerError::into_error)?
+                .sync_all()?;
+        }
+        std::fs::rename(tmp_path, path)?;
+
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+fn parse_identity(line: &str) -> Option<Identity> {
+    let mut parts = line.split_whitespace();
+    let uuid = parts.next()?.parse().ok()?;
+    let inode = parts.next()?.parse().ok()?;
+    let first_seen = UNIX_EPOCH + Duration::from_secs(parts.next()?.parse().ok()?);
+
+    Some(Identity {
+        uuid,
+        inode,
+        first_seen,
+    })
+}
@@ -8,26 +8,53 @@ use std::{
     str::FromStr,
 };
 
-use futures::StreamExt;
+use futures::{StreamExt, future::BoxFuture};
 use itertools::Itertools;
-use mountinfo::MountInfo;
 use thiserror::Error;
 use tokio::{
     fs::metadata,
-    sync::broadcast::{Receiver, Sender},
+    sync::{
+        broadcast::{Receiver, Sender},
+        watch,
+    },
 };
 use tokio_stream::wrappers::BroadcastStream;
 use uuid::Uuid;
 
 use crate::{
-    mount_monitor::{FsType, MountChange, MountPoint},
-    netns::{INode, NetworkNamespace, NsId, Pid, PidsIterator},
+    mount_monitor::{MountChange, MountPoint},
+    netns::{
+        INode, NetworkNamespace, NsId, Pid, PidsIterator, is_nsfs, nsfs_mount_map_with_unreadable,
+    },
+    netns_identity::IdentityStore,
     nsid_monitor::NetnsIdEvent,
     syscall_monitor::EbpfEvent,
 };
 
-pub type StateRequest = ();
-pub type StateResponse = Vec<NetworkNamespace>;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateRequest {
+    /// Full list of known namespaces.
+    Snapshot,
+    /// Only the number of processes in each namespace. Much cheaper than a full snapshot.
+    PidCounts,
+}
+
+#[derive(Debug, Clone)]
+pub enum StateResponse {
+    Snapshot(Vec<NetworkNamespace>),
+    /// Namespaces without processes (bound to a file only) have a count of zero.
+    PidCounts(HashMap<INode, usize>),
+    /// Sent without a request, whenever a namespace gains or loses its NETNSID.
+    IdChanged(NamespaceIdChanged),
+}
+
+/// NETNSID of a namespace changed. `new` is `None` if namespace lost its id (and is gone).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NamespaceIdChanged {
+    pub inode: INode,
+    pub old: Option<NsId>,
+    pub new: Option<NsId>,
+}
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -35,6 +62,12 @@ pub enum Error {
     Io(#[from] std::io::Error),
     #[error("netns error - {0}")]
     Netns(#[from] crate::netns::Error),
+    #[error("syscall monitor error - {0}")]
+    SyscallMonitor(#[from] crate::syscall_monitor::Error),
+    #[error("nsid monitor error - {0}")]
+    NsidMonitor(#[from] crate::nsid_monitor::MonitorError),
+    #[error("mount monitor error - {0}")]
+    MountMonitor(Box<crate::mount_monitor::Error>),
 }
 
 #[derive(Debug, Clone)]
@@ -45,27 +78,262 @@ enum Event {
     StateRequested(StateRequest),
 }
 
+/// Sender for state requests, Receiver for the responses, and a Future that drives the tracker.
+pub type Tracker<F> = (Sender<StateRequest>, Receiver<StateResponse>, F);
+
+/// Receiver of the latest snapshot, and a Future that drives the tracker. See [`NetnsTrackerBuilder::build_watch`].
+pub type WatchTracker<F> = (watch::Receiver<Vec<NetworkNamespace>>, F);
+
 pub fn monitor_network_namespaces(
     nsid_events: Receiver<NetnsIdEvent>,
     mount_events: Receiver<MountChange>,
     syscalls: Receiver<EbpfEvent>,
-) -> Result<
-    (
-        Sender<StateRequest>,
-        Receiver<StateResponse>,
-        impl Send + Future<Output = Result<(), Error>>,
-    ),
-    Error,
-> {
+) -> Result<Tracker<impl Send + Future<Output = Result<(), Error>>>, Error> {
+    monitor_network_namespaces_with_options(
+        nsid_events,
+        mount_events,
+        syscalls,
+        TrackerOptions::default(),
+    )
+}
+
+/// Ways to find out which namespace a newly assigned NETNSID belongs to. Listed from the cheapest to the most expensive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdSearchTier {
+    /// Query ids of the already known namespaces.
+    ScanExisting,
+    /// Query ids of all nsfs mounts (`/run/netns/*` and such).
+    ScanMounts,
+    /// Query ids of namespaces of all processes. Can take a while on hosts with lots of processes.
+    ScanProcs,
+}
+
+impl IdSearchTier {
+    pub const ALL: [IdSearchTier; 3] = [
+        IdSearchTier::ScanExisting,
+        IdSearchTier::ScanMounts,
+        IdSearchTier::ScanProcs,
+    ];
+}
+
+/// Options of `monitor_network_namespaces_with_options`.
+#[derive(Debug, Clone)]
+pub struct TrackerOptions {
+    identities: IdentityStore,
+    id_search: Vec<IdSearchTier>,
+    capacity: usize,
+    id_change_events: bool,
+}
+
+impl Default for TrackerOptions {
+    fn default() -> Self {
+        Self {
+            identities: IdentityStore::in_memory(),
+            id_search: IdSearchTier::ALL.to_vec(),
+            capacity: 1024,
+            id_change_events: true,
+        }
+    }
+}
+
+impl TrackerOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Namespace identities (UUIDs) are taken from `identities`, and saved back to it whenever they change.
+    /// Use `IdentityStore::load` to keep them across restarts.
+    ///
+    /// Identities of namespaces that are not alive anymore are dropped on startup:
+    /// their inodes may already belong to other namespaces.
+    pub fn identities(mut self, identities: IdentityStore) -> Self {
+        self.identities = identities;
+        self
+    }
+
+    /// Which tiers are used (in the given order) to find a namespace a new NETNSID was assigned to.
+    /// Id is left unknown if none of them finds it.
+    pub fn id_search(mut self, tiers: &[IdSearchTier]) -> Self {
+        self.id_search = tiers.to_vec();
+        self
+    }
+
+    /// Capacity of both request and response channels.
+    /// Slow consumers of responses will lag (and miss some of them) once it is exceeded.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Whether `StateResponse::IdChanged` is sent, without being requested, whenever a namespace gains or loses its id.
+    pub fn id_change_events(mut self, enabled: bool) -> Self {
+        self.id_change_events = enabled;
+        self
+    }
+}
+
+/// Sets up the whole tracking pipeline in one call: monitors that were not provided explicitly are created internally.
+///
+/// ```no_run
+/// # async fn example() -> Result<(), net_device_mapping::netns_tracker::Error> {
+/// use net_device_mapping::netns_tracker::{NetnsTrackerBuilder, StateRequest};
+///
+/// let (requests, mut responses, fut) = NetnsTrackerBuilder::new().build()?;
+/// tokio::spawn(fut);
+/// requests.send(StateRequest::Snapshot).unwrap();
+/// let snapshot = responses.recv().await.unwrap();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct NetnsTrackerBuilder {
+    options: TrackerOptions,
+    nsid_events: Option<Receiver<NetnsIdEvent>>,
+    mount_events: Option<Receiver<MountChange>>,
+    syscalls: Option<Receiver<EbpfEvent>>,
+}
+
+impl NetnsTrackerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn options(mut self, options: TrackerOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// See [`TrackerOptions::capacity`].
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.options = self.options.capacity(capacity);
+        self
+    }
+
+    /// See [`TrackerOptions::id_change_events`].
+    pub fn id_change_events(mut self, enabled: bool) -> Self {
+        self.options = self.options.id_change_events(enabled);
+        self
+    }
+
+    /// Use an already running nsid monitor, instead of creating one.
+    pub fn nsid_events(mut self, nsid_events: Receiver<NetnsIdEvent>) -> Self {
+        self.nsid_events = Some(nsid_events);
+        self
+    }
+
+    /// Use an already running mount monitor, instead of creating one.
+    pub fn mount_events(mut self, mount_events: Receiver<MountChange>) -> Self {
+        self.mount_events = Some(mount_events);
+        self
+    }
+
+    /// Use an already running syscall monitor, instead of creating one.
+    pub fn syscalls(mut self, syscalls: Receiver<EbpfEvent>) -> Self {
+        self.syscalls = Some(syscalls);
+        self
+    }
+
+    /// Creates missing monitors and the tracker itself.
+    ///
+    /// Returned future drives the tracker along with all the monitors it created,
+    /// and finishes once all of them have stopped.
+    pub fn build(self) -> Result<Tracker<impl Send + Future<Output = Result<(), Error>>>, Error> {
+        self.start(None)
+    }
+
+    /// Same as `build`, but instead of answering requests, the tracker keeps the latest snapshot in a watch channel.
+    /// It is replaced whenever the tracked state changes, so a slow receiver skips intermediate states instead of lagging.
+    ///
+    /// Receiver holds an empty list until the startup scan is done, and `changed()` fires for the first time after it.
+    /// Id changes are not reported in this mode. The tracker stops once the receiver is dropped.
+    pub fn build_watch(
+        self,
+    ) -> Result<WatchTracker<impl Send + Future<Output = Result<(), Error>>>, Error> {
+        let (snapshots_tx, snapshots) = watch::channel(Vec::new());
+        let (_requests, _responses, fut) = self.start(Some(snapshots_tx))?;
+        Ok((snapshots, fut))
+    }
+
+    fn start(
+        self,
+        snapshots: Option<watch::Sender<Vec<NetworkNamespace>>>,
+    ) -> Result<Tracker<impl Send + Future<Output = Result<(), Error>>>, Error> {
+        let mut monitors: Vec<BoxFuture<'static, Result<(), Error>>> = Vec::new();
+
+        let nsid_events = match self.nsid_events {
+            Some(nsid_events) => nsid_events,
+            None => {
+                let monitor = crate::nsid_monitor::monitor_netns_ids()?;
+                let fut = monitor.fut;
+                monitors.push(Box::pin(async move {
+                    Ok(fut.await.map_err(crate::nsid_monitor::MonitorError::from)?)
+                }));
+                monitor.events
+            }
+        };
+        let mount_events = match self.mount_events {
+            Some(mount_events) => mount_events,
+            None => {
+                // Tracker never asks for rescans or snapshots, so the request senders are dropped.
+                let monitor = crate::mount_monitor::monitor_mountinfo()
+                    .map_err(|err| Error::MountMonitor(Box::new(err)))?;
+                let fut = monitor.fut;
+                monitors.push(Box::pin(async move {
+                    fut.await.map_err(|err| Error::MountMonitor(Box::new(err)))
+                }));
+                monitor.events
+            }
+        };
+        let syscalls = match self.syscalls {
+            Some(syscalls) => syscalls,
+            None => {
+                let (syscalls, _stop, fut) = crate::syscall_monitor::monitor_syscalls()?;
+                monitors.push(Box::pin(async move { Ok(fut.await?) }));
+                syscalls
+            }
+        };
+
+        let (requests, responses, tracker_fut) =
+            track_namespaces(nsid_events, mount_events, syscalls, self.options, snapshots)?;
+        monitors.push(Box::pin(tracker_fut));
+
+        let fut = async move {
+            futures::future::try_join_all(monitors).await?;
+            Ok(())
+        };
+
+        Ok((requests, responses, fut))
+    }
+}
+
+/// Same as `monitor_network_namespaces`, but with non-default options.
+pub fn monitor_network_namespaces_with_options(
+    nsid_events: Receiver<NetnsIdEvent>,
+    mount_events: Receiver<MountChange>,
+    syscalls: Receiver<EbpfEvent>,
+    options: TrackerOptions,
+) -> Result<Tracker<impl Send + Future<Output = Result<(), Error>>>, Error> {
+    track_namespaces(nsid_events, mount_events, syscalls, options, None)
+}
+
+/// Tracker behind all the entry points. If `snapshots` is given, the latest snapshot is kept in it,
+/// and the tracker runs until its receivers are gone instead of the ones of responses.
+fn track_namespaces(
+    nsid_events: Receiver<NetnsIdEvent>,
+    mount_events: Receiver<MountChange>,
+    syscalls: Receiver<EbpfEvent>,
+    options: TrackerOptions,
+    snapshots: Option<watch::Sender<Vec<NetworkNamespace>>>,
+) -> Result<Tracker<impl Send + Future<Output = Result<(), Error>>>, Error> {
     // Create a channel for receiving data from here
-    let (state_request_tx, state_request_rx) = tokio::sync::broadcast::channel(1024);
-    let (state_response_tx, state_response_rx) = tokio::sync::broadcast::channel(1024);
+    let (state_request_tx, state_request_rx) = tokio::sync::broadcast::channel(options.capacity);
+    let (state_response_tx, state_response_rx) = tokio::sync::broadcast::channel(options.capacity);
 
     let events = {
         // Combine all streams into a single one
         let state_requests = BroadcastStream::new(state_request_rx)
             .filter_map(async |x| x.ok())
-            .map(|()| Event::StateRequested(()));
+            .map(Event::StateRequested);
 
         let nsid_events = BroadcastStream::new(nsid_events)
             .filter_map(async |x| x.ok())
@@ -74,13 +342,10 @@ pub fn monitor_network_namespaces(
         let mount_events = BroadcastStream::new(mount_events)
             .filter_map(async |x| x.ok())
             .filter(|mount_change| {
-                let target_fstype = FsType::Other("nsfs".to_owned());
                 let result = match mount_change {
-                    MountChange::Added(_uuid, mount_point) => mount_point.fstype == target_fstype,
-                    MountChange::Removed(_uuid) => true,
-                    MountChange::Modified(_uuid, mount_point) => {
-                        mount_point.fstype == target_fstype
-                    }
+                    MountChange::Added(_uuid, mount_point, _, _) => is_nsfs(&mount_point.fstype),
+                    MountChange::Removed(_uuid, _) => true,
+                    MountChange::Modified(_uuid, mount_point, _, _) => is_nsfs(&mount_point.fstype),
                 };
                 async move { result }
             })
@@ -106,16 +371,35 @@ pub fn monitor_network_namespaces(
     let fut = async move {
         let mut ev = std::pin::pin!(events);
 
-        let mut state = State::new().await?;
+        let TrackerOptions {
+            identities,
+            id_search,
+            capacity: _,
+            id_change_events,
+        } = options;
+        let mut state = State::new(identities).await?;
+        save_identities(&mut state.identities).await;
         let mut mount_state = MountState::default();
+        if let Some(snapshots) = &snapshots {
+            publish_snapshot(snapshots, &state);
+        }
 
         'main: loop {
             tokio::select! {
-                _ = state_response_tx.closed() => break 'main,
+                _ = receivers_closed(&state_response_tx, snapshots.as_ref()) => break 'main,
 
                 event = ev.next() => {
                     if let Some(event) = event {
-                        let should_quit = process_event(&mut state, &mut mount_state, &mut handle, &state_response_tx, event).await?;
+                        let changes_state = !matches!(event, Event::StateRequested(_));
+                        let should_quit = process_event(&mut state, &mut mount_state, &mut handle, &state_response_tx, &id_search, id_change_events, event).await?;
+                        if state.identities.is_dirty() {
+                            save_identities(&mut state.identities).await;
+                        }
+                        if let Some(snapshots) = &snapshots
+                            && changes_state
+                        {
+                            publish_snapshot(snapshots, &state);
+                        }
                         if should_quit {
                             break 'main;
                         }
@@ -133,11 +417,53 @@ pub fn monitor_network_namespaces(
     Ok((state_request_tx, state_response_rx, fut))
 }
 
+/// Resolves once nobody is left to receive what the tracker produces.
+async fn receivers_closed(
+    responses: &Sender<StateResponse>,
+    snapshots: Option<&watch::Sender<Vec<NetworkNamespace>>>,
+) {
+    match snapshots {
+        Some(snapshots) => snapshots.closed().await,
+        None => responses.closed().await,
+    }
+}
+
+/// Replaces the snapshot in the watch channel, only notifying receivers if it is different from the previous one.
+fn publish_snapshot(snapshots: &watch::Sender<Vec<NetworkNamespace>>, state: &State) {
+    let mut current = state.current_state();
+    current.sort_by_key(|netns| netns.inode);
+
+    snapshots.send_if_modified(|snapshot| {
+        if *snapshot == current {
+            return false;
+        }
+        *snapshot = current;
+        true
+    });
+}
+
+/// Saves identities on a blocking thread. Failure does not stop the tracker: it is logged,
+/// and the store stays dirty, so that saving is retried after the next change.
+async fn save_identities(identities: &mut IdentityStore) {
+    let mut saved = identities.clone();
+    let result = tokio::task::spawn_blocking(move || saved.save().map(|()| saved)).await;
+
+    match result {
+        Ok(Ok(saved)) => *identities = saved,
+        Ok(Err(err)) => {
+            eprintln!("[netns_tracker] WARN failed to save namespace identities - {err}")
+        }
+        Err(err) => eprintln!("[netns_tracker] WARN failed to save namespace identities - {err}"),
+    }
+}
+
 async fn process_event(
     state: &mut State,
     mount_state: &mut MountState,
     handle: &mut rtnetlink::Handle,
     state_response_tx: &Sender<StateResponse>,
+    id_search: &[IdSearchTier],
+    id_change_events: bool,
 
     event: Event,
 ) -> Result<bool, Error> {
@@ -147,14 +473,23 @@ async fn process_event(
         // ==== Network namespace id change ====
         Event::NetnsIdEvent(netns_id_event) => match netns_id_event {
             NetnsIdEvent::Added(id) => {
-                if let Some(inode) = find_netns_id_addition(&state, handle, id).await? {
-                    state.ensure_namespace_mut(inode).id = Some(id);
+                match find_netns_id_addition(state, handle, id, id_search).await? {
+                    Ok(inode) => {
+                        let old = state.ensure_namespace_mut(inode).id.replace(id);
+                        if id_change_events && old != Some(id) {
+                            notify_id_changed(state_response_tx, inode, old, Some(id));
+                        }
+                    }
+                    Err(failure) => eprintln!("[netns_tracker] WARN {failure}"),
                 }
             }
             NetnsIdEvent::Removed(id) => {
                 // Losing an ID means that namespace is removed.
                 if let Some((inode, _)) = state.namespace_by_id(id) {
                     state.remove_namespace(inode);
+                    if id_change_events {
+                        notify_id_changed(state_response_tx, inode, Some(id), None);
+                    }
                 }
             }
         },
@@ -162,16 +497,16 @@ async fn process_event(
         // ==== NSFS partition was mounted, unmounted, or remounted ====
         Event::MountChange(mount_change) => {
             match &mount_change {
-                MountChange::Added(_uuid, mount_point) => {
+                MountChange::Added(_uuid, mount_point, _, netns_inode) => {
                     // Add the bound path
-                    if let Ok(metadata) = metadata(&mount_point.path).await {
+                    if let Some(inode) = *netns_inode {
                         state
-                            .ensure_namespace_mut(metadata.ino())
+                            .ensure_namespace_mut(inode)
                             .fs_path
                             .insert(mount_point.path.clone());
                     }
                 }
-                MountChange::Removed(uuid) => {
+                MountChange::Removed(uuid, _) => {
                     let removed = mount_state
                         .get_path(*uuid)
                         .map(|path| (path, state.namespace_by_path(path)));
@@ -186,7 +521,7 @@ async fn process_event(
                         }
                     }
                 }
-                MountChange::Modified(uuid, mount_point) => {
+                MountChange::Modified(uuid, mount_point, _, _) => {
                     // Update the filepath it is bound to.
                     let removed = mount_state
                         .get_path(*uuid)
@@ -204,25 +539,49 @@ async fn process_event(
         // ==== Some process did one of syscalls we are interested in ====
         Event::Syscall(ebpf_event) => {
             match ebpf_event.kind {
-                crate::syscall_monitor::EventType::Fork | // Check process netns, and add PID to correct namespace.
-                crate::syscall_monitor::EventType::Clone |
-                crate::syscall_monitor::EventType::Unshare | // Check process netns, it may have changed (unshare with `CLONE_NEWNET` or setns with specific fd).
-                crate::syscall_monitor::EventType::Setns => {
-                    if let Ok(meta) = metadata(process_netns_path(ebpf_event.pid)).await {
-                        state.ensure_namespace_mut(meta.ino());
-                        state.pids.insert(ebpf_event.pid, meta.ino());
+                // Processes are tracked per thread (`tid`), the same as the startup scan does.
+                // Fork does not change namespace of the caller, and the new task starts in the same one.
+                // A task cloned with `CLONE_NEWNET` is not told apart here, it is corrected by its next `setns` or `unshare`.
+                crate::syscall_monitor::EventType::Fork => {
+                    if let Some(inode) = ebpf_event.netns()
+                        && ebpf_event.child_tid != 0
+                    {
+                        state.ensure_namespace_mut(inode);
+                        state.pids.insert(ebpf_event.child_tid, inode);
                     }
-                },
+                }
+                // The new task is reported by the `Fork` that follows.
+                crate::syscall_monitor::EventType::Clone => {}
+                // Namespace of the thread may have changed (unshare with `CLONE_NEWNET` or setns with specific fd).
+                // Events come after the syscall, with the namespace it ended up in, so procfs is only a fallback.
+                crate::syscall_monitor::EventType::Unshare
+                | crate::syscall_monitor::EventType::Setns => {
+                    let inode = match ebpf_event.netns() {
+                        Some(inode) => Some(inode),
+                        None => metadata(thread_netns_path(ebpf_event.tid))
+                            .await
+                            .ok()
+                            .map(|meta| meta.ino()),
+                    };
+                    if let Some(inode) = inode {
+                        state.ensure_namespace_mut(inode);
+                        state.pids.insert(ebpf_event.tid, inode);
+                    }
+                }
                 crate::syscall_monitor::EventType::Exit => {
-                    state.pids.remove(&ebpf_event.pid);
-                },
-                crate::syscall_monitor::EventType::Exec => {}, // Does not do anything with namespaces
+                    state.pids.remove(&ebpf_event.tid);
+                }
+                crate::syscall_monitor::EventType::Exec => {} // Does not do anything with namespaces
             }
         }
 
         // ==== User requested current state ====
-        Event::StateRequested(()) => {
-            if state_response_tx.send(state.current_state()).is_err() {
+        Event::StateRequested(request) => {
+            let response = match request {
+                StateRequest::Snapshot => StateResponse::Snapshot(state.current_state()),
+                StateRequest::PidCounts => StateResponse::PidCounts(state.pid_counts()),
+            };
+            if state_response_tx.send(response).is_err() {
                 return Ok(true);
             }
         }
@@ -230,69 +589,154 @@ async fn process_event(
     Ok(false)
 }
 
+fn notify_id_changed(
+    state_response_tx: &Sender<StateResponse>,
+    inode: INode,
+    old: Option<NsId>,
+    new: Option<NsId>,
+) {
+    // Nobody listening is not an error here: the main loop stops once all receivers are gone.
+    let _ = state_response_tx.send(StateResponse::IdChanged(NamespaceIdChanged {
+        inode,
+        old,
+        new,
+    }));
+}
+
+/// What a single tier of NETNSID search went through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TierOutcome {
+    pub tier: IdSearchTier,
+    /// Namespace files (or processes, for `ScanProcs`) whose id was checked.
+    pub scanned: usize,
+    /// Candidates that could not be checked: file could not be opened, or id query failed.
+    pub failed: usize,
+}
+
+/// None of the tiers found a namespace a new NETNSID was assigned to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdSearchFailure {
+    pub id: NsId,
+    /// Tiers attempted, in order.
+    pub tiers: Vec<TierOutcome>,
+}
+
+impl IdSearchFailure {
+    /// Most likely reason the namespace was not found.
+    pub fn reason(&self) -> &'static str {
+        if self.tiers.is_empty() {
+            "no search tiers are enabled"
+        } else if self.tiers.iter().any(|outcome| outcome.failed > 0) {
+            "some candidates could not be queried, namespace may be among them"
+        } else {
+            "no visible namespace has this id, it may be gone already or only reachable from another namespace"
+        }
+    }
+}
+
+/// `key=value` pairs, so that logs can be grepped and parsed.
+impl std::fmt::Display for IdSearchFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "netnsid={} resolved=false", self.id)?;
+        for outcome in &self.tiers {
+            write!(
+                f,
+                " tier={:?} scanned={} failed={}",
+                outcome.tier, outcome.scanned, outcome.failed
+            )?;
+        }
+        write!(f, " reason=\"{}\"", self.reason())
+    }
+}
+
 async fn find_netns_id_addition(
     state: &State,
     handle: &mut rtnetlink::Handle,
     id: NsId,
-) -> std::io::Result<Option<INode>> {
-    // 1. Happy path: rescan existing network namespaces
-    for (inode, filepath) in state.namespace_files() {
-        let Ok(file) = tokio::fs::File::open(filepath).await else {
-            continue;
+    tiers: &[IdSearchTier],
+) -> Result<Result<INode, IdSearchFailure>, Error> {
+    let mut outcomes = Vec::with_capacity(tiers.len());
+    for &tier in tiers {
+        let mut outcome = TierOutcome {
+            tier,
+            scanned: 0,
+            failed: 0,
         };
-        let netns_id_result = unsafe {
-            NetworkNamespace::id_by_file_descriptor(handle, file.as_fd().as_raw_fd()).await
-        };
-        let Ok(Some(current_netns_id)) = netns_id_result else {
-            continue;
+        let found = match tier {
+            IdSearchTier::ScanExisting => {
+                find_id_in_existing(state, handle, id, &mut outcome).await
+            }
+            IdSearchTier::ScanMounts => find_id_in_mounts(handle, id, &mut outcome).await?,
+            IdSearchTier::ScanProcs => find_id_in_procs(handle, id, &mut outcome).await,
         };
-
-        if current_netns_id == id {
-            return Ok(Some(inode));
+        if let Some(inode) = found {
+            return Ok(Ok(inode));
         }
+        outcomes.push(outcome);
     }
 
-    // 2. Less happy path: rescan all `/run/netns/` entries.
-    let mounts = MountInfo::new()?
-        .mounting_points
-        .into_iter()
-        .filter(|mount| matches!(&mount.fstype, mountinfo::FsType::Other(other) if other == "nsfs"))
-        .map(|mount| mount.path)
-        .sorted()
-        .dedup();
+    Ok(Err(IdSearchFailure {
+        id,
+        tiers: outcomes,
+    }))
+}
 
-    for filepath in mounts {
-        let Ok(file) = tokio::fs::File::open(filepath).await else {
-            continue;
-        };
-        let Ok(meta) = file.metadata().await else {
-            continue;
-        };
-        let netns_id_result = unsafe {
-            NetworkNamespace::id_by_file_descriptor(handle, file.as_fd().as_raw_fd()).await
-        };
-        let Ok(Some(current_netns_id)) = netns_id_result else {
-            continue;
-        };
+/// Checks whether namespace file `file` has id `id`, counting the check in `outcome`.
+async fn file_has_id(
+    handle: &mut rtnetlink::Handle,
+    file: std::io::Result<tokio::fs::File>,
+    id: NsId,
+    outcome: &mut TierOutcome,
+) -> bool {
+    outcome.scanned += 1;
+
+    let Ok(file) = file else {
+        outcome.failed += 1;
+        return false;
+    };
+    let netns_id_result =
+        unsafe { NetworkNamespace::id_by_file_descriptor(handle, file.as_fd().as_raw_fd()).await };
+
+    match netns_id_result {
+        Ok(current_netns_id) => current_netns_id == Some(id),
+        Err(_) => {
+            outcome.failed += 1;
+            false
+        }
+    }
+}
 
-        if current_netns_id == id {
-            return Ok(Some(meta.ino()));
+/// Happy path: rescan existing network namespaces.
+async fn find_id_in_existing(
+    state: &State,
+    handle: &mut rtnetlink::Handle,
+    id: NsId,
+    outcome: &mut TierOutcome,
+) -> Option<INode> {
+    for (inode, filepath) in state.namespace_files() {
+        let file = tokio::fs::File::open(filepath).await;
+        if file_has_id(handle, file, id, outcome).await {
+            return Some(inode);
         }
     }
 
-    // 3. Really unhappy path: rescan all processes.
-    let mut pids = PidsIterator::new();
-    loop {
-        let (filepath, _pid, inode) = match pids.next().await {
-            Ok(Some(x)) => x,
-            Ok(None) => break,
-            Err(_) => continue,
-        };
-        let Ok(Some(current_netns_id)) = NetworkNamespace::id_by_path(handle, &filepath).await
-        else {
-            continue;
-        };
-        if current_netns_id == id {
+    None
+}
+
+/// Less happy path: rescan all `/run/netns/` entries.
+async fn find_id_in_mounts(
+    handle: &mut rtnetlink::Handle,
+    id: NsId,
+    outcome: &mut TierOutcome,
+) -> Result<Option<INode>, Error> {
+    let (mounts, unreadable) = nsfs_mount_map_with_unreadable().await?;
+    // Mounts that could not be inspected, for example ones removed since mountinfo was read.
+    outcome.scanned += unreadable.len();
+    outcome.failed += unreadable.len();
+
+    for (filepath, inode) in mounts.into_iter().sorted() {
+        let file = tokio::fs::File::open(filepath).await;
+        if file_has_id(handle, file, id, outcome).await {
             return Ok(Some(inode));
         }
     }
@@ -300,10 +744,28 @@ async fn find_netns_id_addition(
     Ok(None)
 }
 
-fn process_netns_path(pid: Pid) -> PathBuf {
+/// Really unhappy path: rescan all processes.
+async fn find_id_in_procs(
+    handle: &mut rtnetlink::Handle,
+    id: NsId,
+    outcome: &mut TierOutcome,
+) -> Option<INode> {
+    let mut pids = PidsIterator::new();
+    while let Some((filepath, _pid, inode)) = pids.next_readable().await {
+        let file = tokio::fs::File::open(filepath).await;
+        if file_has_id(handle, file, id, outcome).await {
+            return Some(inode);
+        }
+    }
+
+    None
+}
+
+/// Threads are not listed in `/proc`, but `/proc/<tid>` works for them the same as for processes.
+fn thread_netns_path(tid: Pid) -> PathBuf {
     PathBuf::from_str("/proc")
         .unwrap()
-        .join(pid.to_string())
+        .join(tid.to_string())
         .join("ns")
         .join("net")
 }
@@ -326,10 +788,13 @@ struct State {
 
     /// Each process (`/proc/*/task/*/`, not group) is in exactly one network namespace.
     pub pids: HashMap<Pid, INode>,
+
+    /// Stable UUIDs of the namespaces. Kept in sync with `namespaces`.
+    pub identities: IdentityStore,
 }
 
 impl State {
-    pub async fn new() -> Result<Self, Error> {
+    pub async fn new(mut identities: IdentityStore) -> Result<Self, Error> {
         let iter = NetworkNamespace::all().await?.into_iter().map(|netns| {
             (
                 netns.inode,
@@ -352,7 +817,17 @@ impl State {
             }
         }
 
-        Ok(Self { namespaces, pids })
+        // Namespaces that are gone by now can not be told apart from new ones with a recycled inode.
+        identities.retain(|inode| namespaces.contains_key(&inode));
+        for &inode in namespaces.keys() {
+            identities.get_or_assign(inode);
+        }
+
+        Ok(Self {
+            namespaces,
+            pids,
+            identities,
+        })
     }
 
     pub fn current_state(&self) -> Vec<NetworkNamespace> {
@@ -367,12 +842,27 @@ impl State {
                 id: netns.id.clone(),
                 fs_path: netns.fs_path.clone(),
                 pids: pids_per_inode.remove(&inode).unwrap_or_else(|| Vec::new()),
+                fds: vec![],
+                uuid: self.identities.get(inode).map(|identity| identity.uuid),
+                machine: None,
             })
             .collect()
     }
 
+    pub fn pid_counts(&self) -> HashMap<INode, usize> {
+        let mut counts: HashMap<INode, usize> =
+            self.namespaces.keys().map(|&inode| (inode, 0)).collect();
+
+        for inode in self.pids.values() {
+            *counts.entry(*inode).or_default() += 1;
+        }
+
+        counts
+    }
+
     pub fn ensure_namespace_mut(&mut self, inode: INode) -> &mut ShallowNamespace {
         if !self.namespaces.contains_key(&inode) {
+            self.identities.get_or_assign(inode);
             self.namespaces.insert(
                 inode,
                 ShallowNamespace {
@@ -409,6 +899,7 @@ impl State {
             for pid in netns.pids {
                 self.pids.insert(pid, netns.inode);
             }
+            self.identities.get_or_assign(netns.inode);
             self.namespaces.insert(
                 netns.inode,
                 ShallowNamespace {
@@ -422,6 +913,7 @@ impl State {
 
     pub fn remove_namespace(&mut self, inode: INode) -> bool {
         if let Some(_netns) = self.namespaces.remove(&inode) {
+            self.identities.forget(inode);
             self.pids = self
                 .pids
                 .iter()
@@ -449,7 +941,7 @@ impl State {
                 self.pids
                     .iter()
                     .filter_map(|(pid, inode)| {
-                        (*inode == namespace).then(|| process_netns_path(*pid))
+                        (*inode == namespace).then(|| thread_netns_path(*pid))
                     })
                     .next()
             })
@@ -469,9 +961,9 @@ struct MountState {
 impl MountState {
     pub fn on_event(&mut self, event: MountChange) {
         match event {
-            MountChange::Added(uuid, mount_point) => self.mounts.insert(uuid, mount_point),
-            MountChange::Removed(uuid) => self.mounts.remove(&uuid),
-            MountChange::Modified(uuid, mount_point) => self.mounts.insert(uuid, mount_point),
+            MountChange::Added(uuid, mount_point, _, _) => self.mounts.insert(uuid, mount_point),
+            MountChange::Removed(uuid, _) => self.mounts.remove(&uuid),
+            MountChange::Modified(uuid, mount_point, _, _) => self.mounts.insert(uuid, mount_point),
         };
     }
 
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use futures::StreamExt;
 use libc::RTNLGRP_NSID;
 use rtnetlink::{
@@ -11,7 +13,10 @@ use rtnetlink::{
 use thiserror::Error;
 use tokio::sync::broadcast::Receiver;
 
-use crate::netns::NsId;
+use crate::{
+    netns::NsId,
+    util::{CountingSender, MonitorStats},
+};
 
 #[derive(Debug, Clone, Copy)]
 pub enum NetnsIdEvent {
@@ -27,14 +32,18 @@ pub enum MonitorError {
     Io(#[from] std::io::Error),
 }
 
-/// Returns a Receiver for NetnsIdEvent and a Future that drives the monitor loop.
-pub fn monitor_netns_ids() -> Result<
-    (
-        Receiver<NetnsIdEvent>,
-        impl Send + Future<Output = Result<(), rtnetlink::Error>>,
-    ),
-    MonitorError,
-> {
+/// Started network namespace id monitor.
+#[derive(Debug)]
+pub struct NetnsIdMonitor<F> {
+    pub events: Receiver<NetnsIdEvent>,
+    pub stats: Arc<MonitorStats>,
+    /// Drives the monitor loop.
+    pub fut: F,
+}
+
+/// Starts listening for network namespace ids being assigned and removed, see [`NetnsIdMonitor`].
+pub fn monitor_netns_ids()
+-> Result<NetnsIdMonitor<impl Send + Future<Output = Result<(), rtnetlink::Error>>>, MonitorError> {
     let (mut conn, handle, mut messages) = rtnetlink::new_connection()?;
     drop(handle);
 
@@ -50,6 +59,8 @@ pub fn monitor_netns_ids() -> Result<
     let fut_handle = tokio::spawn(conn);
 
     let (send, recv) = tokio::sync::broadcast::channel(1024);
+    let send = CountingSender::new(send);
+    let stats = send.stats();
 
     // Receive events
     let monitor_fut = async move {
@@ -92,7 +103,11 @@ pub fn monitor_netns_ids() -> Result<
         Ok(())
     };
 
-    Ok((recv, monitor_fut))
+    Ok(NetnsIdMonitor {
+        events: recv,
+        stats,
+        fut: monitor_fut,
+    })
 }
 
 fn extract_nsid_from_attrs(attrs: impl IntoIterator<Item = NsidAttribute>) -> Option<NsId> {
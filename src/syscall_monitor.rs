@@ -1,4 +1,10 @@
-use std::{borrow::Cow, path::PathBuf, time::Duration};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet, hash_map::Entry},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
 use aya::{
     Ebpf, EbpfError,
@@ -8,14 +14,19 @@ use aya::{
 use thiserror::Error;
 use tokio::{
     io::unix::AsyncFd,
-    sync::broadcast::{Receiver, Sender, error::SendError},
-    time::sleep,
+    sync::broadcast::{Receiver, error::SendError},
+    time::{Instant, sleep, sleep_until},
+};
+
+use crate::{
+    netns::INode,
+    util::{CountingSender, MonitorStats, OneshotRecv},
 };
 
 const TASK_COMM_LENGTH: usize = 16;
 
 #[repr(u32)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum EventType {
     Fork = 0,
     Exec = 1,
@@ -25,6 +36,23 @@ pub enum EventType {
     Setns = 5,
 }
 
+impl EventType {
+    pub const ALL: [EventType; 6] = [
+        EventType::Fork,
+        EventType::Exec,
+        EventType::Exit,
+        EventType::Clone,
+        EventType::Unshare,
+        EventType::Setns,
+    ];
+
+    /// `(category, tracepoint)` the events of this kind come from.
+    pub fn tracepoint(self) -> (&'static str, &'static str) {
+        let (_program, category, tracepoint) = TRACEPOINTS[self as usize];
+        (category, tracepoint)
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct EbpfEvent {
@@ -34,10 +62,23 @@ pub struct EbpfEvent {
     pub uid: u32,
     pub gid: u32,
     pub parent_pid: u32,
+    /// Inode of the network namespace the task was in at the moment of the event, zero if unknown.
+    /// See [`EbpfEvent::netns`].
+    pub netns_inode: u32,
+    /// Thread id of the new task, for `Fork`. Zero for other kinds.
+    pub child_tid: u32,
     pub command: [u8; TASK_COMM_LENGTH],
 }
 
 impl EbpfEvent {
+    /// Network namespace of the task at the moment of the event, read by the eBPF program, so `/proc` does not have to be.
+    ///
+    /// `Unshare` and `Setns` are reported on exit from the syscall, so this is the namespace the task ended up in.
+    /// Other syscalls are reported on entry. `None` if the task no longer has namespaces, which happens on `Exit`.
+    pub fn netns(&self) -> Option<INode> {
+        (self.netns_inode != 0).then_some(INode::from(self.netns_inode))
+    }
+
     pub fn command_as_string(&self) -> Cow<'_, str> {
         let len = self
             .command
@@ -57,6 +98,8 @@ impl std::fmt::Debug for EbpfEvent {
             .field("uid", &self.uid)
             .field("gid", &self.gid)
             .field("parent_pid", &self.parent_pid)
+            .field("netns", &self.netns())
+            .field("child_tid", &self.child_tid)
             .field("command", &self.command_as_string())
             .finish()
     }
@@ -87,9 +130,36 @@ fn get_object_path() -> std::io::Result<PathBuf> {
         }
     }
 
-    let filepath = object_dir.join("fork_monitor.bpf.o");
+    // Otherwise the misconfiguration only surfaces as a bare "No such file or directory".
+    let object_path = object_dir.join(OBJECT_FILE);
+    if !object_path.is_file() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!(
+                "eBPF object not found at {}; set EBPF_OBJECT_DIR to the directory with compiled eBPF objects",
+                object_path.display()
+            ),
+        ));
+    }
+
+    Ok(object_path)
+}
+
+/// eBPF object built by `build.rs`. It is CO-RE, so the same object works on every supported kernel.
+const OBJECT_FILE: &str = "fork_monitor.bpf.o";
 
-    Ok(filepath)
+/// Type information of the running kernel, which CO-RE relocations of the object are resolved against.
+pub const KERNEL_BTF_PATH: &str = "/sys/kernel/btf/vmlinux";
+
+/// Fails with [`Error::NoKernelBtf`] if the kernel does not expose its BTF at `btf_path`.
+///
+/// Without it the object can not be loaded on this kernel at all, and aya would only report a failed relocation.
+pub fn require_kernel_btf(btf_path: &Path) -> Result<(), Error> {
+    if btf_path.is_file() {
+        Ok(())
+    } else {
+        Err(Error::NoKernelBtf(btf_path.to_owned()))
+    }
 }
 
 #[derive(Debug, Error)]
@@ -104,39 +174,351 @@ pub enum Error {
     Map(#[from] MapError),
     #[error("send error - {0}")]
     Send(#[from] SendError<EbpfEvent>),
+    #[error("eBPF object does not have program {0}")]
+    MissingProgram(&'static str),
+    #[error(
+        "kernel has no BTF at {}, which the CO-RE eBPF object needs; kernel must be built with CONFIG_DEBUG_INFO_BTF",
+        .0.display()
+    )]
+    NoKernelBtf(PathBuf),
 }
 
-pub fn monitor_syscalls() -> Result<
-    (
-        Receiver<EbpfEvent>,
-        impl Send + Future<Output = Result<(), Error>>,
-    ),
-    Error,
-> {
-    let mut bpf = Ebpf::load_file(get_object_path()?)?;
-
-    // Attach fork tracepoint
-    let attachments = [
-        ("trace_sched_process_fork", "sched", "sched_process_fork"),
-        ("trace_exec", "syscalls", "sys_enter_execve"),
-        ("trace_exit", "sched", "sched_process_exit"),
-        ("trace_clone", "syscalls", "sys_enter_clone"),
-        ("trace_unshare", "syscalls", "sys_enter_unshare"),
-        ("trace_setns", "syscalls", "sys_enter_setns"),
-    ];
-    for (program_name, category, attachment) in attachments {
-        let program: &mut TracePoint = bpf.program_mut(program_name).unwrap().try_into()?;
-        program.load()?;
-        program.attach(category, attachment)?;
+/// Receiver of events, handle to stop the monitor and the Future that drives it.
+pub type Stoppable<T, F> = (Receiver<T>, async_oneshot::Sender<()>, F);
+
+/// Returns a Receiver for all syscall events, a handle to stop the monitor (see [`SyscallMonitor::stop`]),
+/// and a Future that drives the monitor loop. Same as `SyscallMonitorBuilder::new().build()`.
+pub fn monitor_syscalls()
+-> Result<Stoppable<EbpfEvent, impl Send + Future<Output = Result<(), Error>>>, Error> {
+    let monitor = SyscallMonitorBuilder::new().build()?;
+    Ok((monitor.events, monitor.stop, monitor.fut))
+}
+
+/// Same as `monitor_syscalls`, but only events of these kinds are forwarded.
+/// Same as `SyscallMonitorBuilder::new().kinds(kinds).build()`.
+pub fn monitor_syscalls_kinds(
+    kinds: &[EventType],
+) -> Result<Stoppable<EbpfEvent, impl Send + Future<Output = Result<(), Error>>>, Error> {
+    let monitor = SyscallMonitorBuilder::new().kinds(kinds).build()?;
+    Ok((monitor.events, monitor.stop, monitor.fut))
+}
+
+/// Same as `monitor_syscalls`, but events of the same kind from the same process, coming within `window` of each other,
+/// are sent as one [`SampledEvent`]. Same as `SyscallMonitorBuilder::new().build_sampled(window)`.
+pub fn monitor_syscalls_sampled(
+    window: Duration,
+) -> Result<Stoppable<SampledEvent, impl Send + Future<Output = Result<(), Error>>>, Error> {
+    let monitor = SyscallMonitorBuilder::new().build_sampled(window)?;
+    Ok((monitor.events, monitor.stop, monitor.fut))
+}
+
+/// Which events are forwarded to the receiver. By default, everything is.
+///
+/// Filtering happens in userspace, after events are received from the kernel.
+#[derive(Debug, Clone)]
+pub struct EventFilter {
+    kinds: Vec<EventType>,
+    uids: Option<HashSet<u32>>,
+}
+
+impl Default for EventFilter {
+    fn default() -> Self {
+        Self {
+            kinds: EventType::ALL.to_vec(),
+            uids: None,
+        }
+    }
+}
+
+impl EventFilter {
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    let (send, recv) = tokio::sync::broadcast::channel(1024);
+    /// Only forward events of these kinds.
+    pub fn kinds(mut self, kinds: &[EventType]) -> Self {
+        self.kinds = kinds.to_vec();
+        self
+    }
 
-    let fut = poll_messages(bpf, send);
-    Ok((recv, fut))
+    /// Only forward events of processes running as one of these users.
+    pub fn uids(mut self, uids: impl IntoIterator<Item = u32>) -> Self {
+        self.uids = Some(uids.into_iter().collect());
+        self
+    }
+
+    /// Checks whether the event passes all the filters.
+    pub fn matches(&self, event: &EbpfEvent) -> bool {
+        if !self.kinds.contains(&event.kind) {
+            return false;
+        }
+        if let Some(uids) = &self.uids
+            && !uids.contains(&event.uid)
+        {
+            return false;
+        }
+        true
+    }
+}
+
+/// eBPF programs of the monitor, with `(category, tracepoint)` each of them is attached to.
+/// In the order of `EventType` values, each program reports events of one kind.
+const TRACEPOINTS: [(&str, &str, &str); 6] = [
+    ("trace_sched_process_fork", "sched", "sched_process_fork"),
+    ("trace_exec", "syscalls", "sys_enter_execve"),
+    ("trace_exit", "sched", "sched_process_exit"),
+    ("trace_clone", "syscalls", "sys_enter_clone"),
+    ("trace_unshare", "syscalls", "sys_exit_unshare"),
+    ("trace_setns", "syscalls", "sys_exit_setns"),
+];
+
+/// Diagnostic information about a started syscall monitor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonitorStatus {
+    /// `(category, tracepoint)` pairs the monitor is attached to.
+    pub attached_tracepoints: Vec<(&'static str, &'static str)>,
+    /// `(category, tracepoint)` pairs that could not be attached, for example because the kernel does not have them.
+    /// Events of their kinds are never reported.
+    pub failed_tracepoints: Vec<(&'static str, &'static str)>,
+}
+
+/// Started syscall monitor.
+#[derive(Debug)]
+pub struct SyscallMonitor<T, F> {
+    pub events: Receiver<T>,
+    /// Stops the monitor, without dropping the receiver. Receiver gets `RecvError::Closed` once the monitor stops.
+    ///
+    /// Dropping the handle without sending anything does not stop the monitor.
+    pub stop: async_oneshot::Sender<()>,
+    pub status: MonitorStatus,
+    pub stats: Arc<MonitorStats>,
+    /// Drives the monitor loop.
+    pub fut: F,
+}
+
+/// Sets up a syscall monitor.
+///
+/// ```no_run
+/// # async fn example() -> Result<(), net_device_mapping::syscall_monitor::Error> {
+/// use net_device_mapping::syscall_monitor::{EventType, SyscallMonitorBuilder};
+///
+/// let mut monitor = SyscallMonitorBuilder::new()
+///     .kinds(&[EventType::Unshare, EventType::Setns])
+///     .build()?;
+/// tokio::spawn(monitor.fut);
+/// let event = monitor.events.recv().await.unwrap();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SyscallMonitorBuilder {
+    filter: EventFilter,
+}
+
+impl SyscallMonitorBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only forward events that pass the `filter`.
+    pub fn filter(mut self, filter: EventFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Only forward events of these kinds. Same as `EventFilter::kinds`, on the filter of this builder.
+    pub fn kinds(mut self, kinds: &[EventType]) -> Self {
+        self.filter = self.filter.kinds(kinds);
+        self
+    }
+
+    /// Loads and attaches the eBPF programs.
+    pub fn build(
+        self,
+    ) -> Result<SyscallMonitor<EbpfEvent, impl Send + Future<Output = Result<(), Error>>>, Error>
+    {
+        let filter = self.filter.clone();
+        self.start(|bpf, send, stop| poll_messages(bpf, send, filter, stop))
+    }
+
+    /// Same as `build`, but events of the same kind from the same process, coming within `window` of each other,
+    /// are sent as one [`SampledEvent`] (see [`EventSampler`]). Groups still held back when the monitor is stopped
+    /// are sent before the receiver is closed.
+    ///
+    /// Meant for hosts with a lot of process churn, where a fork storm would otherwise make a slow receiver lag behind.
+    pub fn build_sampled(
+        self,
+        window: Duration,
+    ) -> Result<SyscallMonitor<SampledEvent, impl Send + Future<Output = Result<(), Error>>>, Error>
+    {
+        let filter = self.filter.clone();
+        let sampler = EventSampler::new(window);
+        self.start(|bpf, send, stop| poll_messages_sampled(bpf, send, filter, sampler, stop))
+    }
+
+    fn start<T, F>(
+        self,
+        poll: impl FnOnce(Ebpf, CountingSender<T>, OneshotRecv<()>) -> F,
+    ) -> Result<SyscallMonitor<T, F>, Error>
+    where
+        T: Clone + Send + 'static,
+    {
+        let (bpf, status) = load_programs()?;
+
+        let (send, events) = tokio::sync::broadcast::channel(1024);
+        let send = CountingSender::new(send);
+        let stats = send.stats();
+
+        let (stop, stop_rx) = async_oneshot::oneshot();
+
+        let fut = poll(bpf, send, stop_rx.into());
+        Ok(SyscallMonitor {
+            events,
+            stop,
+            status,
+            stats,
+            fut,
+        })
+    }
 }
 
-async fn poll_messages(mut bpf: Ebpf, send: Sender<EbpfEvent>) -> Result<(), Error> {
+/// Loads the eBPF object and attaches as many of its programs as possible.
+/// Fails only if none of them could be attached.
+fn load_programs() -> Result<(Ebpf, MonitorStatus), Error> {
+    let object_path = get_object_path()?;
+    require_kernel_btf(KERNEL_BTF_PATH.as_ref())?;
+    let mut bpf = Ebpf::load_file(object_path)?;
+
+    let mut status = MonitorStatus {
+        attached_tracepoints: Vec::new(),
+        failed_tracepoints: Vec::new(),
+    };
+    let mut first_error = None;
+    for (program_name, category, tracepoint) in TRACEPOINTS {
+        match attach_program(&mut bpf, program_name, category, tracepoint) {
+            Ok(()) => status.attached_tracepoints.push((category, tracepoint)),
+            Err(err) => {
+                eprintln!(
+                    "[syscall_monitor] WARN could not attach {category}/{tracepoint} - {err}"
+                );
+                status.failed_tracepoints.push((category, tracepoint));
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+
+    if let Some(err) = first_error
+        && status.attached_tracepoints.is_empty()
+    {
+        return Err(err);
+    }
+
+    Ok((bpf, status))
+}
+
+fn attach_program(
+    bpf: &mut Ebpf,
+    program_name: &'static str,
+    category: &str,
+    tracepoint: &str,
+) -> Result<(), Error> {
+    let program: &mut TracePoint = bpf
+        .program_mut(program_name)
+        .ok_or(Error::MissingProgram(program_name))?
+        .try_into()?;
+    program.load()?;
+    program.attach(category, tracepoint)?;
+    Ok(())
+}
+
+/// Events of the same kind from the same process, coalesced by [`EventSampler`].
+#[derive(Debug, Clone, Copy)]
+pub struct SampledEvent {
+    /// First of the coalesced events.
+    pub event: EbpfEvent,
+    /// How many events were coalesced, including the first one.
+    pub count: u32,
+}
+
+/// Coalesces events of the same kind from the same process (`pid`), that come within `window` of the first one.
+///
+/// Each group is held back until its window closes, so events are delayed by up to `window`.
+#[derive(Debug)]
+pub struct EventSampler {
+    window: Duration,
+    pending: HashMap<(u32, EventType), PendingGroup>,
+    /// Sequence number of the next opened group.
+    next_seq: u64,
+}
+
+/// Group of events whose window is still open.
+#[derive(Debug)]
+struct PendingGroup {
+    closes: Instant,
+    /// Orders groups opened at the same instant, e.g. from one batch of the ring buffer.
+    seq: u64,
+    sampled: SampledEvent,
+}
+
+impl EventSampler {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: HashMap::new(),
+            next_seq: 0,
+        }
+    }
+
+    /// Adds an event that was received at `now`.
+    pub fn push(&mut self, event: EbpfEvent, now: Instant) {
+        match self.pending.entry((event.pid, event.kind)) {
+            Entry::Occupied(mut group) => {
+                let sampled = &mut group.get_mut().sampled;
+                sampled.count = sampled.count.saturating_add(1);
+            }
+            Entry::Vacant(slot) => {
+                slot.insert(PendingGroup {
+                    closes: now + self.window,
+                    seq: self.next_seq,
+                    sampled: SampledEvent { event, count: 1 },
+                });
+                self.next_seq += 1;
+            }
+        }
+    }
+
+    /// Takes out groups whose window has closed by `now`, in the order they were opened.
+    pub fn take_due(&mut self, now: Instant) -> Vec<SampledEvent> {
+        let due = self
+            .pending
+            .extract_if(|_key, group| group.closes <= now)
+            .map(|(_key, group)| group);
+        in_opening_order(due)
+    }
+
+    /// Takes out all the groups, including those whose window is still open, in the order they were opened.
+    pub fn take_all(&mut self) -> Vec<SampledEvent> {
+        in_opening_order(self.pending.drain().map(|(_key, group)| group))
+    }
+
+    /// When the earliest open window closes. `None` if there are no events held back.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.pending.values().map(|group| group.closes).min()
+    }
+}
+
+fn in_opening_order(groups: impl Iterator<Item = PendingGroup>) -> Vec<SampledEvent> {
+    let mut groups: Vec<_> = groups.collect();
+    groups.sort_by_key(|group| group.seq);
+    groups.into_iter().map(|group| group.sampled).collect()
+}
+
+async fn poll_messages(
+    mut bpf: Ebpf,
+    send: CountingSender<EbpfEvent>,
+    filter: EventFilter,
+    mut stop: OneshotRecv<()>,
+) -> Result<(), Error> {
     let ringbuf = RingBuf::try_from(bpf.map_mut("events").unwrap())?;
     let mut async_fd = AsyncFd::new(ringbuf)?;
 
@@ -144,10 +526,14 @@ async fn poll_messages(mut bpf: Ebpf, send: Sender<EbpfEvent>) -> Result<(), Err
         tokio::select! {
             _ = send.closed() => break 'main,
 
+            // Dropped handle only means nobody is going to stop the monitor.
+            result = &mut stop => if result.is_ok() {
+                break 'main;
+            },
+
             guard = async_fd.readable_mut() => {
                 let mut guard = guard?;
-                while let Some(item) = guard.get_inner_mut().next() {
-                    let event: EbpfEvent = unsafe { std::ptr::read(item.as_ptr() as *const _) };
+                while let Some(event) = next_event(guard.get_inner_mut(), &filter) {
                     match send.send(event) {
                         Ok(_) => {}
                         Err(_) => break 'main,
@@ -161,3 +547,69 @@ async fn poll_messages(mut bpf: Ebpf, send: Sender<EbpfEvent>) -> Result<(), Err
 
     Ok(())
 }
+
+async fn poll_messages_sampled(
+    mut bpf: Ebpf,
+    send: CountingSender<SampledEvent>,
+    filter: EventFilter,
+    mut sampler: EventSampler,
+    mut stop: OneshotRecv<()>,
+) -> Result<(), Error> {
+    let ringbuf = RingBuf::try_from(bpf.map_mut("events").unwrap())?;
+    let mut async_fd = AsyncFd::new(ringbuf)?;
+
+    'main: loop {
+        let next_due = sampler.next_due();
+
+        tokio::select! {
+            // Nobody is left to send held back groups to.
+            _ = send.closed() => return Ok(()),
+
+            // Dropped handle only means nobody is going to stop the monitor.
+            result = &mut stop => if result.is_ok() {
+                break 'main;
+            },
+
+            _ = sleep_until(next_due.unwrap_or_else(Instant::now)), if next_due.is_some() => {
+                for sampled in sampler.take_due(Instant::now()) {
+                    if send.send(sampled).is_err() {
+                        break 'main;
+                    }
+                }
+            }
+
+            guard = async_fd.readable_mut() => {
+                let mut guard = guard?;
+                let now = Instant::now();
+                while let Some(event) = next_event(guard.get_inner_mut(), &filter) {
+                    sampler.push(event, now);
+                }
+
+                sleep(Duration::from_millis(1)).await;
+            }
+        }
+    }
+
+    // Events of the groups were already seen, they are not lost just because their window is still open.
+    for sampled in sampler.take_all() {
+        if send.send(sampled).is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Takes the next event out of the ring buffer, skipping those that do not pass the filter.
+fn next_event(
+    ringbuf: &mut RingBuf<&mut aya::maps::MapData>,
+    filter: &EventFilter,
+) -> Option<EbpfEvent> {
+    while let Some(item) = ringbuf.next() {
+        let event: EbpfEvent = unsafe { std::ptr::read(item.as_ptr() as *const _) };
+        if filter.matches(&event) {
+            return Some(event);
+        }
+    }
+    None
+}
@@ -0,0 +1,292 @@
+//! Fixtures for tests that need real network namespaces and devices.
+//!
+//! All of them require `CAP_SYS_ADMIN` and `CAP_NET_ADMIN`. Tests should start with
+//! [`skip_if_unprivileged!`](crate::skip_if_unprivileged), so that they pass (as skipped) when run as a regular user.
+
+use std::{
+    ffi::CString,
+    os::{fd::AsRawFd, unix::ffi::OsStrExt},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    ptr::null,
+};
+
+use futures::TryStreamExt;
+use rtnetlink::{LinkUnspec, LinkVeth};
+use uuid::Uuid;
+
+use crate::{
+    capabilities::{Capability, check_capabilities},
+    net_device::Error,
+};
+
+/// Directory where `ip netns` keeps named namespaces.
+const NETNS_RUN_DIR: &str = "/run/netns";
+
+/// Whether current process is able to create namespaces and devices.
+pub fn is_privileged() -> bool {
+    let report = check_capabilities();
+    report.has(Capability::SysAdmin) && report.has(Capability::NetAdmin)
+}
+
+/// Returns early from a test (with a note in stderr), if the process is not privileged enough to use fixtures.
+#[macro_export]
+macro_rules! skip_if_unprivileged {
+    () => {
+        if !$crate::testutil::is_privileged() {
+            eprintln!("skipping test: CAP_SYS_ADMIN and CAP_NET_ADMIN are required");
+            return;
+        }
+    };
+}
+
+/// Returns early from a test (with a note in stderr), if the kernel can not create devices of one of the given kinds.
+/// Privilege alone is not enough: drivers like `dummy` or `bond` may be missing from the kernel.
+#[macro_export]
+macro_rules! skip_if_link_kind_unsupported {
+    ($($kind:expr),+ $(,)?) => {
+        $(
+            if !$crate::testutil::supports_link_kind($kind) {
+                eprintln!("skipping test: kernel does not support {} devices", $kind);
+                return;
+            }
+        )+
+    };
+}
+
+/// Whether a device of the given kind (`dummy`, `bond`, `gre`, ...) can be created, i.e. its driver is built in or loadable.
+/// Probed by creating one in a throwaway namespace. Always `false` without privileges.
+pub fn supports_link_kind(kind: &str) -> bool {
+    let Ok(netns) = TempNetns::new() else {
+        return false;
+    };
+
+    Command::new("ip")
+        .args(["-n", netns.name(), "link", "add", "probe0", "type", kind])
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Runs `ip -n <netns> <args>`, failing if it exits unsuccessfully.
+pub fn ip_in(netns: &TempNetns, args: &[&str]) -> std::io::Result<()> {
+    let status = Command::new("ip")
+        .args(["-n", netns.name()])
+        .args(args)
+        .status()?;
+
+    if !status.success() {
+        return Err(std::io::Error::other(format!(
+            "`ip -n {} {}` failed with {status}",
+            netns.name(),
+            args.join(" ")
+        )));
+    }
+    Ok(())
+}
+
+/// Unique name with a given prefix, so that concurrently running tests do not collide.
+pub fn unique_name(prefix: &str) -> String {
+    format!("{prefix}{}", &Uuid::new_v4().simple().to_string()[..8])
+}
+
+/// Named network namespace (same as `ip netns add`), which is deleted on drop.
+#[derive(Debug)]
+pub struct TempNetns {
+    name: String,
+    path: PathBuf,
+}
+
+impl TempNetns {
+    /// Creates a uniquely named network namespace, bound to `/run/netns/<name>`.
+    pub fn new() -> std::io::Result<Self> {
+        let name = unique_name("test_ns_");
+        let path = Path::new(NETNS_RUN_DIR).join(&name);
+
+        std::fs::create_dir_all(NETNS_RUN_DIR)?;
+        std::fs::File::create(&path)?;
+
+        // Unsharing moves the calling thread to the new namespace, so do it on a throwaway thread.
+        let bind_path = path.clone();
+        let result = std::thread::spawn(move || bind_new_netns(&bind_path))
+            .join()
+            .unwrap_or_else(|_| Err(std::io::Error::other("netns thread panicked")));
+
+        if let Err(err) = result {
+            let _ = std::fs::remove_file(&path);
+            return Err(err);
+        }
+
+        Ok(Self { name, path })
+    }
+
+    /// Binds the network namespace of an existing process to a uniquely named `/run/netns/<name>`
+    /// (same as `ip netns attach <name> <pid>`). Only the bind is removed on drop.
+    pub fn attach(pid: u32) -> std::io::Result<Self> {
+        let name = unique_name("test_ns_");
+        let path = Path::new(NETNS_RUN_DIR).join(&name);
+
+        std::fs::create_dir_all(NETNS_RUN_DIR)?;
+        std::fs::File::create(&path)?;
+
+        let source = Path::new("/proc")
+            .join(pid.to_string())
+            .join("ns")
+            .join("net");
+        if let Err(err) = bind_netns_file(&source, &path) {
+            let _ = std::fs::remove_file(&path);
+            return Err(err);
+        }
+
+        Ok(Self { name, path })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// File the namespace is bound to. Can be used with `setns`, or any of the `*_by_path` queries.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempNetns {
+    fn drop(&mut self) {
+        if let Ok(path) = CString::new(self.path.as_os_str().as_bytes()) {
+            unsafe {
+                libc::umount2(path.as_ptr(), libc::MNT_DETACH);
+            }
+        }
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Creates a new network namespace for the current thread, and bind-mounts it to `path`.
+fn bind_new_netns(path: &Path) -> std::io::Result<()> {
+    unsafe {
+        if libc::unshare(libc::CLONE_NEWNET) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    bind_netns_file(Path::new("/proc/thread-self/ns/net"), path)
+}
+
+/// Bind-mounts namespace file `source` to `target`, which should already exist.
+fn bind_netns_file(source: &Path, target: &Path) -> std::io::Result<()> {
+    let source = CString::new(source.as_os_str().as_bytes())?;
+    let target = CString::new(target.as_os_str().as_bytes())?;
+
+    let code = unsafe {
+        libc::mount(
+            source.as_ptr(),
+            target.as_ptr(),
+            null(),
+            libc::MS_BIND,
+            null(),
+        )
+    };
+    if code != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Pair of veth devices in the current network namespace, which is deleted on drop.
+#[derive(Debug)]
+pub struct TempVeth {
+    name: String,
+    peer: String,
+    index: u32,
+}
+
+impl TempVeth {
+    /// Creates a uniquely named veth pair.
+    pub async fn new(handle: &mut rtnetlink::Handle) -> Result<Self, Error> {
+        // Interface names are limited to 15 characters.
+        let name = unique_name("tveth");
+        let peer = format!("{name}p");
+
+        handle
+            .link()
+            .add(LinkVeth::new(&name, &peer).build())
+            .execute()
+            .await?;
+
+        let index = link_index(handle, &name).await?;
+
+        Ok(Self { name, peer, index })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn peer(&self) -> &str {
+        &self.peer
+    }
+
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Moves the peer end to another network namespace. It keeps its name there.
+    pub async fn move_peer_to(
+        &self,
+        handle: &mut rtnetlink::Handle,
+        netns: &TempNetns,
+    ) -> Result<(), Error> {
+        let netns_file = std::fs::File::open(netns.path())?;
+        let peer_index = link_index(handle, &self.peer).await?;
+
+        handle
+            .link()
+            .set(
+                LinkUnspec::new_with_index(peer_index)
+                    .setns_by_fd(netns_file.as_raw_fd())
+                    .build(),
+            )
+            .execute()
+            .await?;
+
+        Ok(())
+    }
+}
+
+impl Drop for TempVeth {
+    fn drop(&mut self) {
+        // Drop can not be async, and may be called from within a runtime, so use a separate thread and runtime.
+        // Deleting one end of the pair deletes the other one too.
+        let index = self.index;
+        let _ = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?;
+
+            runtime.block_on(async move {
+                let (conn, handle, _) = rtnetlink::new_connection()?;
+                let conn_task = tokio::spawn(conn);
+                let result = handle.link().del(index).execute().await;
+                conn_task.abort();
+                result.map_err(std::io::Error::other)
+            })
+        })
+        .join();
+    }
+}
+
+/// Looks up index of a device by its name.
+pub async fn link_index(handle: &mut rtnetlink::Handle, name: &str) -> Result<u32, Error> {
+    let link = handle
+        .link()
+        .get()
+        .match_name(name.to_owned())
+        .execute()
+        .try_next()
+        .await?;
+
+    link.map(|link| link.header.index)
+        .ok_or_else(|| Error::Io(std::io::Error::from(std::io::ErrorKind::NotFound)))
+}
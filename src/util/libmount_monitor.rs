@@ -27,32 +27,58 @@ pub struct Event {
 ///
 /// Not `Send` nor `Sync` by original design due to reference counting.
 /// It is safe to `Send` and `Sync` it when is it never cloned.
-pub struct RcMonitor(*mut libmnt_monitor);
+///
+/// What was enabled is tracked per instance: enabling a monitor through a clone is not visible here.
+pub struct RcMonitor {
+    raw: *mut libmnt_monitor,
+    /// Filename userspace monitor was first enabled with. libmount keeps using it even after the monitor is disabled.
+    userspace_filename: Option<PathBuf>,
+    /// Whether userspace monitor was ever enabled through this instance, with or without a filename.
+    userspace_enabled_once: bool,
+    /// Which monitors are enabled now.
+    enabled: EnabledMonitors,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct EnabledMonitors {
+    kernel: bool,
+    userspace: bool,
+}
 
 impl Drop for RcMonitor {
     fn drop(&mut self) {
-        unsafe { libmount_sys::mnt_unref_monitor(self.0) };
+        unsafe { libmount_sys::mnt_unref_monitor(self.raw) };
     }
 }
 
 impl Clone for RcMonitor {
     fn clone(&self) -> Self {
-        unsafe { libmount_sys::mnt_ref_monitor(self.0) };
-        Self(self.0)
+        unsafe { libmount_sys::mnt_ref_monitor(self.raw) };
+        Self {
+            raw: self.raw,
+            userspace_filename: self.userspace_filename.clone(),
+            userspace_enabled_once: self.userspace_enabled_once,
+            enabled: self.enabled,
+        }
     }
 }
 
 impl RcMonitor {
     pub fn new() -> Self {
-        Self(unsafe { libmount_sys::mnt_new_monitor() })
+        unsafe { Self::from_inner(libmount_sys::mnt_new_monitor()) }
     }
 
     pub unsafe fn from_inner(mon: *mut libmnt_monitor) -> Self {
-        Self(mon)
+        Self {
+            raw: mon,
+            userspace_filename: None,
+            userspace_enabled_once: false,
+            enabled: EnabledMonitors::default(),
+        }
     }
 
     pub unsafe fn into_inner(self) -> *mut libmnt_monitor {
-        self.0
+        self.raw
     }
 
     /// It is only safe to do, when this instance of `RcMonitor` was never cloned.
@@ -69,32 +95,53 @@ impl RcMonitor {
     /// The filename is used only the first time when you enable the monitor. It's impossible to have more than one userspace monitor. The recommended is to use NULL as filename.
     ///
     /// The userspace monitor is unsupported for systems with classic regular /etc/mtab file.
+    ///
+    /// Filename containing a nul byte is rejected with `InvalidInput` error.
     pub fn enable_userspace(
         &mut self,
         enable: bool,
         filename: Option<PathBuf>,
     ) -> std::io::Result<()> {
-        let enable = if enable { 1 } else { 0 };
-        let cstring_filename =
-            filename.map(|filename| CString::new(filename.as_os_str().as_bytes()).unwrap());
+        let enable_code = if enable { 1 } else { 0 };
+        let cstring_filename = filename
+            .as_ref()
+            .map(|filename| CString::new(filename.as_os_str().as_bytes()))
+            .transpose()
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
 
         let code = unsafe {
             libmount_sys::mnt_monitor_enable_userspace(
-                self.0,
-                enable,
+                self.raw,
+                enable_code,
                 cstring_filename
+                    .as_ref()
                     .map(|x| x.as_c_str().as_ptr())
                     .unwrap_or_else(|| null()),
             )
         };
 
         match code {
-            0 => Ok(()),
+            0 => {
+                // libmount ignores filename on subsequent calls.
+                if enable && !self.userspace_enabled_once {
+                    self.userspace_enabled_once = true;
+                    self.userspace_filename = filename;
+                }
+                self.enabled.userspace = enable;
+                Ok(())
+            }
             neg_errno if neg_errno < 0 => Err(std::io::Error::from_raw_os_error(-neg_errno)),
             _ => panic!("Undefined behaviour return code received from libmount"),
         }
     }
 
+    /// Filename userspace monitor was first enabled with, ever: libmount keeps using it after the monitor
+    /// is disabled and enabled again. `None` if it was never enabled, or was first enabled without a filename
+    /// (libmount default is used then).
+    pub fn userspace_filename(&self) -> Option<PathBuf> {
+        self.userspace_filename.clone()
+    }
+
     /// <https://cdn.kernel.org/pub/linux/utils/util-linux/v2.37/libmount-docs/libmount-Monitor.html#mnt-monitor-enable-kernel>
     ///
     /// Enables or disables kernel VFS monitoring. If the monitor does not exist and enable=1 then allocates new resources necessary for the monitor.
@@ -105,10 +152,13 @@ impl RcMonitor {
     pub fn enable_kernel(&mut self, enable: bool) -> std::io::Result<()> {
         let enable = if enable { 1 } else { 0 };
 
-        let code = unsafe { libmount_sys::mnt_monitor_enable_kernel(self.0, enable) };
+        let code = unsafe { libmount_sys::mnt_monitor_enable_kernel(self.raw, enable) };
 
         match code {
-            0 => Ok(()),
+            0 => {
+                self.enabled.kernel = enable == 1;
+                Ok(())
+            }
             neg_errno if neg_errno < 0 => Err(std::io::Error::from_raw_os_error(-neg_errno)),
             _ => panic!("Undefined behaviour return code received from libmount"),
         }
@@ -118,7 +168,7 @@ impl RcMonitor {
     ///
     /// The file descriptor is associated with all monitored files and it's usable for example for epoll. You have to call mnt_monitor_event_cleanup() or mnt_monitor_next_change() after each event.
     pub fn get_fd(&mut self) -> std::io::Result<RawFd> {
-        let fd = unsafe { libmount_sys::mnt_monitor_get_fd(self.0) };
+        let fd = unsafe { libmount_sys::mnt_monitor_get_fd(self.raw) };
         if fd >= 0 {
             Ok(fd)
         } else {
@@ -131,10 +181,15 @@ impl RcMonitor {
     /// Close monitor file descriptor. This is usually unnecessary, because mnt_unref_monitor() cleanups all.
     ///
     /// The function is necessary only if you want to reset monitor setting. The next mnt_monitor_get_fd() or mnt_monitor_wait() will use newly initialized monitor. This restart is unnecessary for mnt_monitor_enable_*() functions.
+    ///
+    /// libmount disables all the monitors too, so they have to be enabled again before the next use.
     pub fn close_fd(&mut self) -> std::io::Result<()> {
-        let code = unsafe { libmount_sys::mnt_monitor_close_fd(self.0) };
+        let code = unsafe { libmount_sys::mnt_monitor_close_fd(self.raw) };
         match code {
-            0 => Ok(()),
+            0 => {
+                self.enabled = EnabledMonitors::default();
+                Ok(())
+            }
             neg_errno if neg_errno < 0 => Err(std::io::Error::from_raw_os_error(-neg_errno)),
             _ => panic!("Undefined behaviour return code received from libmount"),
         }
@@ -147,7 +202,7 @@ impl RcMonitor {
         let mut path_ptr: *const c_char = std::ptr::null();
         let mut etype: c_int = 0;
         let result_code =
-            unsafe { libmount_sys::mnt_monitor_next_change(self.0, &mut path_ptr, &mut etype) };
+            unsafe { libmount_sys::mnt_monitor_next_change(self.raw, &mut path_ptr, &mut etype) };
         if result_code == 0 {
             let path =
                 unsafe { PathBuf::from(CStr::from_ptr(path_ptr).to_string_lossy().into_owned()) };
@@ -173,7 +228,7 @@ impl RcMonitor {
     ///
     /// This function cleanups (drain) internal buffers. It's necessary to call this function after event if you do not call mnt_monitor_next_change().
     pub fn event_cleanup(&mut self) -> std::io::Result<()> {
-        let code = unsafe { libmount_sys::mnt_monitor_event_cleanup(self.0) };
+        let code = unsafe { libmount_sys::mnt_monitor_event_cleanup(self.raw) };
         match code {
             0 => Ok(()),
             neg_errno if neg_errno < 0 => Err(std::io::Error::from_raw_os_error(-neg_errno)),
@@ -198,7 +253,7 @@ impl RcMonitor {
             }
         };
 
-        let code = unsafe { libmount_sys::mnt_monitor_wait(self.0, timeout) };
+        let code = unsafe { libmount_sys::mnt_monitor_wait(self.raw, timeout) };
         match code {
             1 => Ok(true),
             0 => Ok(false),
@@ -209,6 +264,19 @@ impl RcMonitor {
 }
 
 impl RcMonitor {
+    /// Gets a new fd after the monitor was reset (`close_fd`, possibly through a clone),
+    /// enabling again the monitors that were enabled through this instance.
+    fn reopen_fd(&mut self) -> std::io::Result<RawFd> {
+        let enabled = self.enabled;
+        if enabled.kernel {
+            self.enable_kernel(true)?;
+        }
+        if enabled.userspace {
+            self.enable_userspace(true, self.userspace_filename())?;
+        }
+        self.get_fd()
+    }
+
     /// [Non-Official]: Custom addition
     ///
     /// Creates a stream (polled by returned future) that will monitor all changes in real time.
@@ -232,44 +300,9 @@ impl RcMonitor {
     /// ```
     ///
     pub fn stream(
-        mut self,
+        self,
     ) -> std::io::Result<(Receiver<Event>, impl Future<Output = std::io::Result<()>>)> {
-        let fd: RawFd = self.get_fd()?;
-
-        let (send, recv) = tokio::sync::broadcast::channel(1024);
-
-        let fut = async move {
-            use tokio::io::unix::AsyncFd;
-            let mut afd = AsyncFd::new(fd)?;
-
-            'main: loop {
-                tokio::select! {
-                    _ = send.closed() => {
-                        break 'main;
-                    }
-                    _ = afd.readable_mut() => {
-                        let mut changed_files = HashSet::<Event>::new();
-
-                        while let Ok(Some(event)) = self.next_change() {
-                            if changed_files.insert(event.clone()) {
-                                match send.send(event) {
-                                    Ok(_) => {},
-                                    Err(_) => break 'main, // No more receivers
-                                }
-                            }
-                        }
-
-                        sleep(Duration::from_millis(1)).await;
-                    }
-                }
-            }
-
-            // Clean up
-            self.event_cleanup()?;
-            Ok(())
-        };
-
-        Ok((recv, fut))
+        stream_events(self)
     }
 }
 
@@ -313,6 +346,11 @@ impl SendMonitor {
         self.0.enable_userspace(enable, filename)
     }
 
+    /// Filename userspace monitor was first enabled with, ever. See [`RcMonitor::userspace_filename`].
+    pub fn userspace_filename(&self) -> Option<PathBuf> {
+        self.0.userspace_filename()
+    }
+
     /// <https://cdn.kernel.org/pub/linux/utils/util-linux/v2.37/libmount-docs/libmount-Monitor.html#mnt-monitor-enable-kernel>
     ///
     /// Enables or disables kernel VFS monitoring. If the monitor does not exist and enable=1 then allocates new resources necessary for the monitor.
@@ -386,43 +424,109 @@ impl SendMonitor {
     /// ```
     ///
     pub fn stream(
-        mut self,
+        self,
     ) -> std::io::Result<(Receiver<Event>, impl Future<Output = std::io::Result<()>>)> {
-        let fd: RawFd = self.get_fd()?;
+        stream_events(self)
+    }
+}
 
-        let (send, recv) = tokio::sync::broadcast::channel(1024);
+/// How often the monitor fd is checked for having been closed (see [`stream_events`]).
+const FD_CHECK_INTERVAL: Duration = Duration::from_millis(100);
 
-        let fut = async move {
-            use tokio::io::unix::AsyncFd;
-            let mut afd = AsyncFd::new(fd)?;
+/// Gives access to the monitor a stream is driven by, for either of the wrappers.
+trait StreamedMonitor {
+    fn monitor(&mut self) -> &mut RcMonitor;
+}
 
-            'main: loop {
-                tokio::select! {
-                    _ = send.closed() => {
-                        break 'main;
-                    }
-                    _ = afd.readable_mut() => {
-                        let mut changed_files = HashSet::<Event>::new();
-
-                        while let Ok(Some(event)) = self.next_change() {
-                            if changed_files.insert(event.clone()) {
-                                match send.send(event) {
-                                    Ok(_) => {},
-                                    Err(_) => break 'main, // No more receivers
+impl StreamedMonitor for RcMonitor {
+    fn monitor(&mut self) -> &mut RcMonitor {
+        self
+    }
+}
+
+impl StreamedMonitor for SendMonitor {
+    fn monitor(&mut self) -> &mut RcMonitor {
+        &mut self.0
+    }
+}
+
+/// Shared by `RcMonitor::stream` and `SendMonitor::stream`.
+///
+/// Monitor fd may be closed behind our back (`close_fd` through a clone of the monitor resets it).
+/// A closed fd is silently dropped from epoll, so readiness would never come again: the fd is checked
+/// every `FD_CHECK_INTERVAL` and after each readiness. On `EBADF`, monitors are enabled again
+/// (a reset disables them) and a new fd from `get_fd()` is registered instead. An fd number which got reused by something else before the check is not noticed.
+fn stream_events<M: StreamedMonitor>(
+    mut monitor: M,
+) -> std::io::Result<(Receiver<Event>, impl Future<Output = std::io::Result<()>>)> {
+    let fd: RawFd = monitor.monitor().get_fd()?;
+
+    let (send, recv) = tokio::sync::broadcast::channel(1024);
+
+    let fut = async move {
+        use tokio::io::unix::AsyncFd;
+        let mut afd = match AsyncFd::new(fd) {
+            Ok(afd) => afd,
+            // Closed before the stream got polled for the first time.
+            Err(err) if err.raw_os_error() == Some(libc::EBADF) => {
+                AsyncFd::new(monitor.monitor().reopen_fd()?)?
+            }
+            Err(err) => return Err(err),
+        };
+        let mut fd_check = tokio::time::interval(FD_CHECK_INTERVAL);
+
+        'main: loop {
+            let mut stale = false;
+
+            tokio::select! {
+                _ = send.closed() => {
+                    break 'main;
+                }
+                _ = fd_check.tick() => {
+                    stale = is_closed(*afd.get_ref());
+                }
+                _ = afd.readable_mut() => {
+                    let mut changed_files = HashSet::<Event>::new();
+
+                    loop {
+                        match monitor.monitor().next_change() {
+                            Ok(Some(event)) => {
+                                if changed_files.insert(event.clone()) && send.send(event).is_err() {
+                                    break 'main; // No more receivers
                                 }
                             }
+                            Ok(None) => break,
+                            Err(err) => {
+                                stale = err.raw_os_error() == Some(libc::EBADF);
+                                break;
+                            }
                         }
-
-                        sleep(Duration::from_millis(1)).await;
                     }
+
+                    sleep(Duration::from_millis(1)).await;
                 }
             }
 
-            // Clean up
-            self.event_cleanup()?;
-            Ok(())
-        };
+            if stale {
+                // Old registration goes first, new fd may get the same number.
+                drop(afd);
+                let fd = monitor.monitor().reopen_fd()?;
+                eprintln!(
+                    "[libmount_monitor] WARN monitor fd was closed, continuing with a new one ({fd})"
+                );
+                afd = AsyncFd::new(fd)?;
+            }
+        }
 
-        Ok((recv, fut))
-    }
+        // Clean up
+        monitor.monitor().event_cleanup()?;
+        Ok(())
+    };
+
+    Ok((recv, fut))
+}
+
+fn is_closed(fd: RawFd) -> bool {
+    let code = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    code == -1 && std::io::Error::last_os_error().raw_os_error() == Some(libc::EBADF)
 }
@@ -1,9 +1,15 @@
 mod libmount_monitor;
 mod line_count_writer;
+mod monitor_stats;
 mod oneshot_recv;
+mod shutdown;
 mod stoppable_stream;
+mod terminal_redraw;
 
 pub use libmount_monitor::*;
 pub use line_count_writer::*;
+pub use monitor_stats::*;
 pub use oneshot_recv::*;
+pub use shutdown::*;
 pub use stoppable_stream::*;
+pub use terminal_redraw::*;
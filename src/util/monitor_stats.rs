@@ -0,0 +1,85 @@
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::SystemTime,
+};
+
+use tokio::sync::broadcast::{Sender, WeakSender, error::SendError};
+
+/// Health of a running monitor, updated by its loop. Shared (`Arc`) between the monitor and whoever reports on it.
+pub struct MonitorStats {
+    events_emitted: AtomicU64,
+    last_event: Mutex<Option<SystemTime>>,
+    subscribers: Box<dyn Send + Sync + Fn() -> usize>,
+}
+
+impl MonitorStats {
+    fn new<T: Send + 'static>(sender: WeakSender<T>) -> Self {
+        Self {
+            events_emitted: AtomicU64::new(0),
+            last_event: Mutex::new(None),
+            subscribers: Box::new(move || {
+                sender.upgrade().map_or(0, |sender| sender.receiver_count())
+            }),
+        }
+    }
+
+    /// Number of events sent to subscribers so far.
+    pub fn events_emitted(&self) -> u64 {
+        self.events_emitted.load(Ordering::Relaxed)
+    }
+
+    /// When the last event was sent. `None` if there were none yet.
+    pub fn last_event(&self) -> Option<SystemTime> {
+        *self.last_event.lock().unwrap()
+    }
+
+    /// Number of receivers currently subscribed to the monitor. Zero once the monitor has stopped.
+    pub fn subscribers(&self) -> usize {
+        (self.subscribers)()
+    }
+
+    fn record_event(&self) {
+        self.events_emitted.fetch_add(1, Ordering::Relaxed);
+        *self.last_event.lock().unwrap() = Some(SystemTime::now());
+    }
+}
+
+impl std::fmt::Debug for MonitorStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MonitorStats")
+            .field("events_emitted", &self.events_emitted())
+            .field("last_event", &self.last_event())
+            .field("subscribers", &self.subscribers())
+            .finish()
+    }
+}
+
+/// Broadcast sender which records every sent event in `MonitorStats`.
+pub(crate) struct CountingSender<T> {
+    sender: Sender<T>,
+    stats: Arc<MonitorStats>,
+}
+
+impl<T: Send + 'static> CountingSender<T> {
+    pub fn new(sender: Sender<T>) -> Self {
+        let stats = Arc::new(MonitorStats::new(sender.downgrade()));
+        Self { sender, stats }
+    }
+
+    pub fn stats(&self) -> Arc<MonitorStats> {
+        self.stats.clone()
+    }
+
+    pub fn send(&self, value: T) -> Result<usize, SendError<T>> {
+        let receivers = self.sender.send(value)?;
+        self.stats.record_event();
+        Ok(receivers)
+    }
+
+    pub async fn closed(&self) {
+        self.sender.closed().await
+    }
+}
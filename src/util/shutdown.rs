@@ -0,0 +1,30 @@
+use std::time::Duration;
+
+use tokio::task::{JoinError, JoinHandle};
+
+/// How long binaries wait for monitors to stop after Ctrl-C.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Awaits named tasks, which should be stopping by now, for at most `timeout` in total.
+///
+/// Tasks still running after the timeout are aborted, and their result is `None`.
+pub async fn shutdown_tasks<T>(
+    tasks: Vec<(&'static str, JoinHandle<T>)>,
+    timeout: Duration,
+) -> Vec<(&'static str, Option<Result<T, JoinError>>)> {
+    let deadline = tokio::time::Instant::now() + timeout;
+
+    let mut results = Vec::with_capacity(tasks.len());
+    for (name, mut task) in tasks {
+        match tokio::time::timeout_at(deadline, &mut task).await {
+            Ok(result) => results.push((name, Some(result))),
+            Err(_elapsed) => {
+                eprintln!("{name} did not stop in {timeout:?}, aborting it");
+                task.abort();
+                results.push((name, None));
+            }
+        }
+    }
+
+    results
+}
@@ -2,13 +2,21 @@ use tokio::sync::broadcast::{Receiver, error::RecvError};
 
 use super::OneshotRecv;
 
-pub struct StoppableStream<T: Clone>(Option<Receiver<T>>, OneshotRecv<()>);
+/// Stream which ends as soon as any of its stop signals fires.
+pub struct StoppableStream<T: Clone>(Option<Receiver<T>>, Vec<OneshotRecv<()>>);
 
 impl<T: Clone> StoppableStream<T> {
     pub fn new(stream: Receiver<T>) -> (Self, async_oneshot::Sender<()>) {
         let (stop_tx, stop_rx) = async_oneshot::oneshot();
 
-        (Self(Some(stream), OneshotRecv::from(stop_rx)), stop_tx)
+        (Self::with_stops(stream, vec![stop_rx.into()]), stop_tx)
+    }
+
+    /// Stream which ends on whichever of `stops` fires first (SIGINT, a timeout, ...).
+    ///
+    /// With no stops at all, it only ends when the inner stream is closed.
+    pub fn with_stops(stream: Receiver<T>, stops: Vec<OneshotRecv<()>>) -> Self {
+        Self(Some(stream), stops)
     }
 
     pub fn inner(&self) -> Option<&Receiver<T>> {
@@ -17,24 +25,30 @@ impl<T: Clone> StoppableStream<T> {
     pub fn inner_mut(&mut self) -> Option<&mut Receiver<T>> {
         self.0.as_mut()
     }
-    pub fn into_inner(self) -> (Option<Receiver<T>>, OneshotRecv<()>) {
+    pub fn into_inner(self) -> (Option<Receiver<T>>, Vec<OneshotRecv<()>>) {
         (self.0, self.1)
     }
-    pub fn from_inner(stream: Option<Receiver<T>>, stop: OneshotRecv<()>) -> Self {
-        Self(stream, stop)
+    pub fn from_inner(stream: Option<Receiver<T>>, stops: Vec<OneshotRecv<()>>) -> Self {
+        Self(stream, stops)
     }
 
     pub async fn recv(&mut self) -> Result<T, RecvError> {
-        if self.1.is_closed() || self.0.is_none() {
+        if self.1.iter().any(OneshotRecv::is_closed) {
+            self.0 = None;
+        }
+        let Some(stream) = self.0.as_mut() else {
             return Err(RecvError::Closed);
+        };
+        if self.1.is_empty() {
+            return stream.recv().await;
         }
 
         tokio::select! {
-            _ = &mut self.1 => {
+            _ = futures::future::select_all(self.1.iter_mut()) => {
                 self.0 = None;
                 Err(RecvError::Closed)
             },
-            result = self.0.as_mut().unwrap().recv() => {
+            result = stream.recv() => {
                 result
             }
         }
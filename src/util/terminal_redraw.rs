@@ -0,0 +1,90 @@
+use std::io::{self, Write};
+
+use crossterm::{
+    cursor::MoveUp,
+    queue,
+    terminal::{Clear, ClearType},
+};
+
+/// Redraws a block of text in place: each frame clears the rows the previous one took.
+///
+/// Lines longer than the terminal width wrap into several rows, which are counted too.
+#[derive(Debug, Default)]
+pub struct TerminalRedraw {
+    /// Fixed width, instead of the current terminal size.
+    width: Option<u16>,
+    last_rows: u16,
+}
+
+impl TerminalRedraw {
+    /// Wraps lines at the width of the terminal, as of each redraw.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wraps lines at `width` columns, regardless of the terminal size. Useful when not writing to a terminal.
+    pub fn with_width(width: u16) -> Self {
+        Self {
+            width: Some(width),
+            last_rows: 0,
+        }
+    }
+
+    /// Number of rows the last frame took, which are cleared on the next redraw.
+    pub fn last_rows(&self) -> u16 {
+        self.last_rows
+    }
+
+    /// Clears the previous frame, then writes a new one rendered by `render`.
+    pub fn redraw<W: Write>(
+        &mut self,
+        out: &mut W,
+        render: impl FnOnce(&mut Vec<u8>) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let mut frame = Vec::new();
+        render(&mut frame)?;
+
+        if self.last_rows > 0 {
+            queue!(
+                out,
+                MoveUp(self.last_rows),
+                Clear(ClearType::FromCursorDown)
+            )?;
+        }
+        out.write_all(&frame)?;
+        out.flush()?;
+
+        let width = match self.width {
+            Some(width) => width,
+            None => crossterm::terminal::size().map_or(u16::MAX, |(columns, _rows)| columns),
+        };
+        self.last_rows = rows_moved(&String::from_utf8_lossy(&frame), width);
+        Ok(())
+    }
+}
+
+/// How many rows up the cursor is from where `text` started, once it is written to a terminal `width` columns wide.
+fn rows_moved(text: &str, width: u16) -> u16 {
+    let width = usize::from(width.max(1));
+    let mut lines = text.split('\n');
+    // Cursor stays at the row the last (unterminated) line ends at.
+    let last = lines.next_back().unwrap_or_default();
+
+    let full_rows: usize = lines
+        .map(|line| line_width(line).max(1).div_ceil(width))
+        .sum();
+    // Line which fills the row exactly does not wrap until something else is written.
+    let last_rows = line_width(last).saturating_sub(1) / width;
+
+    u16::try_from(full_rows + last_rows).unwrap_or(u16::MAX)
+}
+
+/// Columns taken by a line. Tabs go to the next multiple of 8, escape sequences are not accounted for.
+fn line_width(line: &str) -> usize {
+    line.trim_end_matches('\r')
+        .chars()
+        .fold(0, |column, c| match c {
+            '\t' => (column / 8 + 1) * 8,
+            _ => column + 1,
+        })
+}
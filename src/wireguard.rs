@@ -0,0 +1,154 @@
+//! WireGuard devices: listen port and peers, which rtnetlink does not report.
+//!
+//! They are only available over generic netlink, from the `wireguard` family (`WG_CMD_GET_DEVICE`).
+//! Family exists only while the `wireguard` module is loaded, so a missing one is not an error.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures::TryStreamExt;
+use genetlink::{GenetlinkError, GenetlinkHandle};
+use netlink_packet_core::{NLM_F_DUMP, NLM_F_REQUEST, NetlinkMessage, NetlinkPayload};
+use netlink_packet_generic::GenlMessage;
+use netlink_packet_wireguard::{
+    Wireguard, WireguardCmd,
+    nlas::{WgDeviceAttrs, WgPeerAttrs},
+};
+use thiserror::Error;
+
+/// Peer of a WireGuard device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WgPeer {
+    pub public_key: [u8; 32],
+    /// `None` if there was no handshake with the peer yet.
+    pub last_handshake: Option<SystemTime>,
+}
+
+/// State of a WireGuard device, as reported by `WG_CMD_GET_DEVICE`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct WgDevice {
+    /// Zero if the device does not listen (yet).
+    pub listen_port: u16,
+    pub peers: Vec<WgPeer>,
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("io error - {0}")]
+    Io(#[from] std::io::Error),
+    #[error("generic netlink request failed - {0}")]
+    Genetlink(#[from] GenetlinkError),
+    #[error("could not decode generic netlink message - {0}")]
+    Decode(#[from] netlink_packet_core::DecodeError),
+    #[error("malformed generic netlink message - {0}")]
+    Malformed(&'static str),
+}
+
+/// Queries the WireGuard devices with the given indices, in the network namespace of the current thread.
+///
+/// A single generic netlink connection is used for all of them, so the family is resolved once.
+/// Returns `None` if the `wireguard` family is not registered, i.e. the module is not loaded.
+/// Otherwise, each index comes with the result of its own query.
+pub async fn query_devices(
+    indices: &[u32],
+) -> Result<Option<Vec<(u32, Result<WgDevice, Error>)>>, Error> {
+    // Socket is bound to the namespace of the thread.
+    let (conn, handle, _) = genetlink::new_connection()?;
+    let conn_handle = tokio::spawn(conn);
+
+    let result = query_with(handle, indices).await;
+
+    conn_handle.abort();
+    result
+}
+
+async fn query_with(
+    mut handle: GenetlinkHandle,
+    indices: &[u32],
+) -> Result<Option<Vec<(u32, Result<WgDevice, Error>)>>, Error> {
+    // Resolved family is cached in the handle, and reused by all requests below.
+    match handle.resolve_family::<Wireguard>().await {
+        Ok(_family) => {}
+        Err(GenetlinkError::NetlinkError(err)) if err.raw_os_error() == Some(libc::ENOENT) => {
+            return Ok(None);
+        }
+        Err(err) => return Err(err.into()),
+    }
+
+    let mut devices = Vec::with_capacity(indices.len());
+    for &index in indices {
+        devices.push((index, query_device(&mut handle, index).await));
+    }
+
+    Ok(Some(devices))
+}
+
+async fn query_device(handle: &mut GenetlinkHandle, ifindex: u32) -> Result<WgDevice, Error> {
+    let mut request = NetlinkMessage::from(GenlMessage::from_payload(Wireguard {
+        cmd: WireguardCmd::GetDevice,
+        nlas: vec![WgDeviceAttrs::IfIndex(ifindex)],
+    }));
+    // Device can only be dumped: peers of a busy device do not fit into a single message.
+    request.header.flags = NLM_F_REQUEST | NLM_F_DUMP;
+
+    let mut responses = std::pin::pin!(handle.request(request).await?);
+    let mut messages = Vec::new();
+    while let Some(response) = responses.try_next().await? {
+        match response.payload {
+            NetlinkPayload::InnerMessage(message) => messages.push(message.payload),
+            // Error without a code is an acknowledgement.
+            NetlinkPayload::Error(err) if err.code.is_some() => return Err(err.to_io().into()),
+            _ => {}
+        }
+    }
+
+    device_from_messages(&messages)
+}
+
+/// Builds a device out of the `WG_CMD_GET_DEVICE` dump messages.
+///
+/// A peer may be continued in the next message, in which case it comes again with the same public key.
+pub fn device_from_messages(messages: &[Wireguard]) -> Result<WgDevice, Error> {
+    let mut device = WgDevice::default();
+
+    for message in messages {
+        for attr in &message.nlas {
+            match attr {
+                WgDeviceAttrs::ListenPort(port) => device.listen_port = *port,
+                WgDeviceAttrs::Peers(peers) => {
+                    for peer in peers {
+                        let peer = peer_from_attributes(peer)?;
+                        match device.peers.last_mut() {
+                            Some(last) if last.public_key == peer.public_key => {
+                                last.last_handshake = last.last_handshake.or(peer.last_handshake);
+                            }
+                            _ => device.peers.push(peer),
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(device)
+}
+
+fn peer_from_attributes(attrs: &[WgPeerAttrs]) -> Result<WgPeer, Error> {
+    let mut public_key = None;
+    let mut last_handshake = None;
+
+    for attr in attrs {
+        match attr {
+            WgPeerAttrs::PublicKey(key) => public_key = Some(*key),
+            // All zeroes if there was no handshake.
+            WgPeerAttrs::LastHandshake(time) if *time != UNIX_EPOCH => last_handshake = Some(*time),
+            _ => {}
+        }
+    }
+
+    Ok(WgPeer {
+        public_key: public_key.ok_or(Error::Malformed("peer without public key"))?,
+        last_handshake,
+    })
+}
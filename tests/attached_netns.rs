@@ -0,0 +1,203 @@
+// Every test here runs on real namespaces, with the `testutil` fixtures.
+#![cfg(feature = "testutil")]
+
+use std::{
+    os::unix::fs::MetadataExt,
+    process::{Child, Command},
+    time::Duration,
+};
+
+use net_device_mapping::{
+    netns::{Error, NetworkNamespace, named_namespaces, nsfs_mount_map, pids_in_named_namespace},
+    netns_tracker::{NetnsTrackerBuilder, StateRequest, StateResponse},
+    skip_if_unprivileged,
+    testutil::{TempNetns, unique_name},
+};
+
+/// Process in a new, unnamed network namespace (same as `unshare -n sleep`).
+fn spawn_in_new_netns() -> (Child, u64) {
+    let child = Command::new("unshare")
+        .args(["-n", "sleep", "30"])
+        .spawn()
+        .unwrap();
+    let path = format!("/proc/{}/ns/net", child.id());
+
+    // `unshare` execs `sleep` only after it moved to the new namespace.
+    let own = std::fs::metadata("/proc/self/ns/net").unwrap().ino();
+    for _ in 0..100 {
+        let inode = std::fs::metadata(&path).unwrap().ino();
+        if inode != own {
+            return (child, inode);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    panic!("process did not move to a new namespace");
+}
+
+#[tokio::test]
+async fn attached_namespace_is_named() {
+    skip_if_unprivileged!();
+
+    let (mut child, inode) = spawn_in_new_netns();
+    let attached = TempNetns::attach(child.id()).unwrap();
+
+    let all = NetworkNamespace::all().await.unwrap();
+    let named = named_namespaces().await.unwrap();
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    let netns = all.iter().find(|netns| netns.inode == inode).unwrap();
+    assert!(netns.fs_path.contains(attached.path()));
+    assert!(netns.pids.contains(&child.id()));
+    assert!(
+        named
+            .iter()
+            .any(|(name, named_inode, _)| name == attached.name() && *named_inode == inode)
+    );
+}
+
+#[tokio::test]
+async fn tracker_names_attached_namespace() {
+    skip_if_unprivileged!();
+
+    // Syscall monitor needs eBPF objects, mount and NETNSID ones are real.
+    let (_syscall_tx, syscalls) = tokio::sync::broadcast::channel(16);
+    let (requests, mut responses, fut) = NetnsTrackerBuilder::new()
+        .syscalls(syscalls)
+        .build()
+        .unwrap();
+    let task = tokio::spawn(fut);
+
+    let (mut child, inode) = spawn_in_new_netns();
+    let attached = TempNetns::attach(child.id()).unwrap();
+
+    let mut named = false;
+    for _ in 0..50 {
+        requests.send(StateRequest::Snapshot).unwrap();
+        let response = tokio::time::timeout(Duration::from_secs(10), responses.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        if let StateResponse::Snapshot(namespaces) = response
+            && namespaces
+                .iter()
+                .any(|netns| netns.inode == inode && netns.fs_path.contains(attached.path()))
+        {
+            named = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+    drop(responses);
+    task.abort();
+
+    assert!(named, "attached namespace was not named by the tracker");
+}
+
+#[tokio::test]
+async fn bound_namespace_is_in_mount_map() {
+    skip_if_unprivileged!();
+
+    let netns = TempNetns::new().unwrap();
+    let inode = std::fs::metadata(netns.path()).unwrap().ino();
+
+    let mounts = nsfs_mount_map().await.unwrap();
+
+    assert_eq!(mounts.get(netns.path()), Some(&inode));
+}
+
+#[tokio::test]
+async fn namespaces_of_other_types_are_not_in_mount_map() {
+    skip_if_unprivileged!();
+
+    let path = std::env::temp_dir().join(unique_name("uts_ns"));
+    std::fs::File::create(&path).unwrap();
+    let status = Command::new("mount")
+        .args(["--bind", "/proc/self/ns/uts"])
+        .arg(&path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let mounts = nsfs_mount_map().await;
+
+    let _ = Command::new("umount").arg(&path).status();
+    let _ = std::fs::remove_file(&path);
+
+    assert!(!mounts.unwrap().contains_key(&path));
+}
+
+#[tokio::test]
+async fn pids_of_named_namespace_are_listed() {
+    skip_if_unprivileged!();
+
+    let (mut child, _inode) = spawn_in_new_netns();
+    let attached = TempNetns::attach(child.id()).unwrap();
+
+    let pids = pids_in_named_namespace(attached.name()).await;
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    assert_eq!(pids.unwrap(), [child.id()]);
+    assert!(matches!(
+        pids_in_named_namespace("no_such_test_ns").await,
+        Err(Error::NoSuchNamedNamespace(name)) if name == "no_such_test_ns"
+    ));
+}
+
+#[tokio::test]
+async fn namespace_is_found_by_name() {
+    skip_if_unprivileged!();
+
+    let netns = TempNetns::new().unwrap();
+    let inode = std::fs::metadata(netns.path()).unwrap().ino();
+
+    let (conn, mut handle, _) = rtnetlink::new_connection().unwrap();
+    tokio::spawn(conn);
+
+    let found = NetworkNamespace::by_name(&mut handle, netns.name())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found.inode, inode);
+    assert_eq!(found.name(), Some(netns.name()));
+
+    for missing in ["no_such_test_ns", "..", "../run"] {
+        let found = NetworkNamespace::by_name(&mut handle, missing).await;
+        assert!(found.unwrap().is_none(), "{missing}");
+    }
+}
+
+#[tokio::test]
+async fn thread_in_its_own_namespace_is_listed_there() {
+    skip_if_unprivileged!();
+
+    let (moved_tx, moved_rx) = std::sync::mpsc::channel();
+    let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+    let thread = std::thread::spawn(move || {
+        assert_eq!(unsafe { libc::unshare(libc::CLONE_NEWNET) }, 0);
+        let inode = std::fs::metadata("/proc/thread-self/ns/net").unwrap().ino();
+        moved_tx
+            .send((unsafe { libc::gettid() } as u32, inode))
+            .unwrap();
+        let _ = done_rx.recv();
+    });
+    let (tid, inode) = moved_rx.recv().unwrap();
+
+    let all = NetworkNamespace::all().await;
+    done_tx.send(()).unwrap();
+    thread.join().unwrap();
+
+    let all = all.unwrap();
+    let own = all
+        .iter()
+        .find(|netns| netns.pids.contains(&std::process::id()))
+        .unwrap();
+    let thread_netns = all.iter().find(|netns| netns.inode == inode).unwrap();
+    assert_ne!(own.inode, inode);
+    assert_eq!(thread_netns.pids, [tid]);
+}
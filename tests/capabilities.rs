@@ -0,0 +1,73 @@
+use net_device_mapping::capabilities::{
+    Capability, CapabilityReport, check_capabilities, parse_effective_capabilities,
+};
+
+/// Effective capabilities of root on a 5.8+ kernel, `CAP_CHOWN` up to `CAP_CHECKPOINT_RESTORE`.
+const ALL: u64 = 0x000001ffffffffff;
+
+fn without(capabilities: &[Capability]) -> u64 {
+    capabilities
+        .iter()
+        .fold(ALL, |mask, &cap| mask & !(1 << cap as u8))
+}
+
+#[test]
+fn effective_capabilities_are_parsed_from_status() {
+    let status = "Name:\tcat\nCapInh:\t0000000000000000\nCapPrm:\t000001ffffffffff\n\
+                  CapEff:\t0000000000201000\nCapBnd:\t000001ffffffffff\n";
+
+    assert_eq!(parse_effective_capabilities(status), Some(0x201000));
+    assert_eq!(parse_effective_capabilities("Name:\tcat\n"), None);
+    assert_eq!(parse_effective_capabilities("CapEff:\tnot hex\n"), None);
+}
+
+#[test]
+fn all_capabilities_allow_every_monitor() {
+    let report = CapabilityReport::from_effective(ALL);
+
+    assert!(report.syscall_monitor);
+    assert!(report.nsid_monitor);
+    assert!(report.mount_monitor);
+    assert!(report.netns_tracker);
+    assert!(report.device_queries);
+    assert!(report.device_changes);
+}
+
+#[test]
+fn report_reflects_dropped_capabilities() {
+    // eBPF still loads with `CAP_BPF` and `CAP_PERFMON`, but threads can not enter other namespaces.
+    let report = CapabilityReport::from_effective(without(&[Capability::SysAdmin]));
+    assert!(!report.has(Capability::SysAdmin));
+    assert!(report.syscall_monitor);
+    assert!(report.netns_tracker);
+    assert!(!report.device_queries);
+    assert!(report.device_changes);
+
+    let report =
+        CapabilityReport::from_effective(without(&[Capability::SysAdmin, Capability::Bpf]));
+    assert!(!report.syscall_monitor);
+    assert!(!report.netns_tracker);
+    assert!(report.nsid_monitor);
+    assert!(report.mount_monitor);
+
+    let report = CapabilityReport::from_effective(without(&[Capability::NetAdmin]));
+    assert!(!report.device_changes);
+    assert!(report.device_queries);
+
+    let report = CapabilityReport::from_effective(0);
+    assert!(!report.syscall_monitor);
+    assert!(!report.device_queries);
+    assert!(!report.device_changes);
+    assert!(report.nsid_monitor);
+}
+
+#[test]
+fn own_capabilities_are_checked() {
+    let status = std::fs::read_to_string("/proc/self/status").unwrap();
+    let effective = parse_effective_capabilities(&status).unwrap();
+
+    assert_eq!(
+        check_capabilities(),
+        CapabilityReport::from_effective(effective)
+    );
+}
@@ -0,0 +1,80 @@
+use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::PathBuf, process::Command, time::Duration};
+
+use net_device_mapping::util::{RcMonitor, SendMonitor};
+use tokio::task::LocalSet;
+
+#[test]
+fn userspace_filename_with_nul_is_rejected() {
+    let mut monitor = RcMonitor::new();
+    let filename = PathBuf::from(OsStr::from_bytes(b"/run/mount/ut\0ab"));
+
+    let err = monitor.enable_userspace(true, Some(filename)).unwrap_err();
+
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(monitor.userspace_filename(), None);
+}
+
+#[test]
+fn userspace_filename_is_the_first_one_ever_enabled() {
+    let mut monitor = RcMonitor::new();
+    let first = PathBuf::from("/run/mount/utab");
+
+    monitor.enable_userspace(true, Some(first.clone())).unwrap();
+    monitor.enable_userspace(false, None).unwrap();
+    // libmount ignores the filename now, the first one is still used.
+    monitor
+        .enable_userspace(true, Some(PathBuf::from("/tmp/other_utab")))
+        .unwrap();
+
+    assert_eq!(monitor.userspace_filename(), Some(first));
+}
+
+#[test]
+fn send_monitor_rejects_nul_in_filename() {
+    let mut monitor = SendMonitor::new();
+    let filename = PathBuf::from(OsStr::from_bytes(b"/tmp/\0evil"));
+
+    assert!(monitor.enable_userspace(true, Some(filename)).is_err());
+}
+
+#[tokio::test]
+async fn stream_recovers_after_monitor_reset() {
+    if unsafe { libc::geteuid() } != 0 {
+        eprintln!("skipping test: mounting requires root");
+        return;
+    }
+
+    let mut monitor = RcMonitor::new();
+    monitor.enable_kernel(true).unwrap();
+    let mut reset_handle = monitor.clone();
+    let (mut events, fut) = monitor.stream().unwrap();
+
+    let dir = std::env::temp_dir().join(format!("mnt_reset_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let local = LocalSet::new();
+    local.spawn_local(fut);
+    let event = local
+        .run_until(async {
+            // Let the stream start, so that the reset happens mid-stream.
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            reset_handle.close_fd().unwrap();
+            // Give the stream a chance to notice, before the fd number can be reused.
+            tokio::time::sleep(Duration::from_millis(300)).await;
+
+            let status = Command::new("mount")
+                .args(["-t", "tmpfs", "tmpfs"])
+                .arg(&dir)
+                .status()
+                .unwrap();
+            assert!(status.success());
+
+            tokio::time::timeout(Duration::from_secs(5), events.recv()).await
+        })
+        .await;
+
+    let _ = Command::new("umount").arg(&dir).status();
+    let _ = std::fs::remove_dir(&dir);
+
+    assert!(event.unwrap().is_ok());
+}
@@ -0,0 +1,130 @@
+use std::{
+    os::unix::fs::MetadataExt,
+    process::{Child, Command},
+    time::Duration,
+};
+
+use net_device_mapping::{
+    machined::{label_machines_with, machine_leaders},
+    netns::{INode, NetworkNamespace},
+};
+use zbus::{Guid, connection::Builder, zvariant::OwnedObjectPath};
+
+/// Mock of machined's manager object, with the registered machines.
+struct Manager {
+    machines: Vec<String>,
+}
+
+#[zbus::interface(name = "org.freedesktop.machine1.Manager")]
+impl Manager {
+    fn list_machines(&self) -> Vec<(String, String, String, OwnedObjectPath)> {
+        self.machines
+            .iter()
+            .map(|name| {
+                (
+                    name.clone(),
+                    "container".to_owned(),
+                    "systemd-nspawn".to_owned(),
+                    OwnedObjectPath::try_from(machine_path(name)).unwrap(),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Mock of a machine object.
+struct Machine {
+    leader: u32,
+}
+
+#[zbus::interface(name = "org.freedesktop.machine1.Machine")]
+impl Machine {
+    #[zbus(property)]
+    fn leader(&self) -> u32 {
+        self.leader
+    }
+}
+
+fn machine_path(name: &str) -> String {
+    format!("/org/freedesktop/machine1/machine/{name}")
+}
+
+/// Connection to a mocked machined which has the given machines (name and leader pid) registered.
+/// Server side of the connection has to be kept alive while it is used.
+async fn mock_machined(machines: &[(&str, u32)]) -> (zbus::Connection, zbus::Connection) {
+    let (server, client) = tokio::net::UnixStream::pair().unwrap();
+
+    let manager = Manager {
+        machines: machines.iter().map(|(name, _)| name.to_string()).collect(),
+    };
+    let mut server = Builder::unix_stream(server)
+        .server(Guid::generate())
+        .unwrap()
+        .p2p()
+        .serve_at("/org/freedesktop/machine1", manager)
+        .unwrap();
+    for &(name, leader) in machines {
+        server = server
+            .serve_at(machine_path(name), Machine { leader })
+            .unwrap();
+    }
+    let client = Builder::unix_stream(client).p2p();
+
+    futures::try_join!(server.build(), client.build()).unwrap()
+}
+
+/// Process in a new, unnamed network namespace, which does not need privileges (same as `unshare -rn sleep`).
+fn spawn_in_new_netns() -> (Child, INode) {
+    let mut child = Command::new("unshare")
+        .args(["-rn", "sleep", "30"])
+        .spawn()
+        .unwrap();
+    let path = format!("/proc/{}/ns/net", child.id());
+
+    // `unshare` execs `sleep` only after it moved to the new namespace.
+    let own = std::fs::metadata("/proc/self/ns/net").unwrap().ino();
+    for _ in 0..100 {
+        let inode = std::fs::metadata(&path).unwrap().ino();
+        if inode != own {
+            return (child, inode);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    child.kill().unwrap();
+    child.wait().unwrap();
+    panic!("process did not move to a new namespace");
+}
+
+#[tokio::test]
+async fn leaders_are_listed() {
+    let (_server, client) = mock_machined(&[("web", 4242), ("db", 4343)]).await;
+
+    let leaders = machine_leaders(&client).await.unwrap();
+
+    assert_eq!(leaders.len(), 2);
+    assert_eq!(leaders["web"], 4242);
+    assert_eq!(leaders["db"], 4343);
+}
+
+#[tokio::test]
+async fn namespace_of_leader_is_labeled() {
+    let (mut child, inode) = spawn_in_new_netns();
+    // Machines that share a namespace, and one without a network of its own.
+    let (_server, client) = mock_machined(&[
+        ("web", child.id()),
+        ("app", child.id()),
+        ("toolbox", std::process::id()),
+    ])
+    .await;
+
+    let mut namespaces = NetworkNamespace::all().await.unwrap();
+    let result = label_machines_with(&client, &mut namespaces).await;
+    child.kill().unwrap();
+    child.wait().unwrap();
+    result.unwrap();
+
+    for netns in namespaces {
+        let expected = (netns.inode == inode).then(|| "app".to_owned());
+        assert_eq!(netns.machine, expected);
+    }
+}
@@ -0,0 +1,342 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    ffi::CString,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
+
+use net_device_mapping::{
+    mount_monitor::{
+        ChangeSource, FsType, MountChange, MountMonitor, MountOptions, MountPoint, ReadWrite,
+        monitor_mountinfo, monitor_userspace_mountinfo,
+    },
+    netns::NetworkNamespace,
+};
+use tokio::sync::broadcast::Receiver;
+
+#[tokio::test]
+async fn initial_nsfs_mounts_carry_netns_inode() {
+    let MountMonitor {
+        events: mut changes,
+        fut,
+        ..
+    } = monitor_mountinfo().unwrap();
+    let task = tokio::spawn(fut);
+
+    // Initial mountpoints are all sent at once on start.
+    let mut added = vec![];
+    while let Ok(Ok(change)) =
+        tokio::time::timeout(Duration::from_millis(200), changes.recv()).await
+    {
+        if let MountChange::Added(_, mount, _, netns_inode) = change {
+            added.push((mount, netns_inode));
+        }
+    }
+    drop(changes);
+    task.await.unwrap().unwrap();
+
+    let named = NetworkNamespace::all().await.unwrap();
+    assert!(!added.is_empty());
+    for (mount, netns_inode) in added {
+        if mount.fstype != FsType::Other("nsfs".to_owned()) {
+            assert_eq!(netns_inode, None, "{}", mount.path.display());
+        } else if let Some(inode) = netns_inode {
+            let netns = named.iter().find(|netns| netns.inode == inode).unwrap();
+            assert!(netns.fs_path.contains(&mount.path));
+        }
+    }
+}
+
+#[tokio::test]
+async fn stats_count_emitted_events() {
+    let MountMonitor {
+        events: mut changes,
+        stats,
+        fut,
+        ..
+    } = monitor_mountinfo().unwrap();
+    assert_eq!(stats.events_emitted(), 0);
+    assert_eq!(stats.last_event(), None);
+    assert_eq!(stats.subscribers(), 1);
+
+    let task = tokio::spawn(fut);
+
+    // Initial mountpoints are sent as events too.
+    let mut received = 0;
+    while let Ok(Ok(_change)) =
+        tokio::time::timeout(Duration::from_millis(200), changes.recv()).await
+    {
+        received += 1;
+    }
+    assert!(received > 0);
+    assert_eq!(stats.events_emitted(), received);
+    assert!(stats.last_event().is_some());
+
+    drop(changes);
+    task.await.unwrap().unwrap();
+    assert_eq!(stats.subscribers(), 0);
+}
+
+#[tokio::test]
+async fn snapshot_matches_state_built_from_changes() {
+    let MountMonitor {
+        snapshot: snapshot_requests,
+        mut snapshots,
+        events: mut changes,
+        fut,
+        ..
+    } = monitor_mountinfo().unwrap();
+    let task = tokio::spawn(fut);
+
+    // Mid-run: after the initial mountpoints, and whatever changed since.
+    let mut mounts = HashMap::new();
+    while let Ok(Ok(change)) =
+        tokio::time::timeout(Duration::from_millis(200), changes.recv()).await
+    {
+        match change {
+            MountChange::Added(uuid, mount, _, _) | MountChange::Modified(uuid, mount, _, _) => {
+                mounts.insert(uuid, mount);
+            }
+            MountChange::Removed(uuid, _) => {
+                mounts.remove(&uuid);
+            }
+        }
+    }
+    assert!(!mounts.is_empty());
+
+    snapshot_requests.send(()).unwrap();
+    let snapshot = tokio::time::timeout(Duration::from_secs(5), snapshots.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(snapshot, mounts);
+
+    drop(changes);
+    task.await.unwrap().unwrap();
+}
+
+fn mount_point(path: &str, id: u32) -> MountPoint {
+    MountPoint {
+        id: Some(id),
+        parent_id: Some(1),
+        root: Some(PathBuf::from("/")),
+        what: "tmpfs".to_owned(),
+        path: PathBuf::from(path),
+        fstype: FsType::Tmpfs,
+        options: MountOptions {
+            read_write: ReadWrite::ReadWrite,
+            others: vec![],
+        },
+    }
+}
+
+#[test]
+fn mount_points_are_ordered_by_path_then_id() {
+    let set: BTreeSet<_> = [
+        mount_point("/tmp", 30),
+        mount_point("/run", 20),
+        mount_point("/tmp", 10),
+        mount_point("/run", 20),
+    ]
+    .into_iter()
+    .collect();
+
+    let order: Vec<_> = set
+        .iter()
+        .map(|mount| (mount.path.to_str().unwrap(), mount.id.unwrap()))
+        .collect();
+    assert_eq!(order, [("/run", 20), ("/tmp", 10), ("/tmp", 30)]);
+
+    // Same path and id, but a different mount: both are kept.
+    let mut remounted = mount_point("/tmp", 10);
+    remounted.options.read_write = ReadWrite::ReadOnly;
+    let mut set = set;
+    assert!(set.insert(remounted.clone()));
+
+    let hashed: HashSet<_> = set.iter().cloned().collect();
+    assert_eq!(hashed.len(), 4);
+    assert!(hashed.contains(&remounted));
+}
+
+/// Changes received until the monitor goes quiet.
+async fn received(changes: &mut Receiver<MountChange>) -> Vec<MountChange> {
+    let mut received = vec![];
+    while let Ok(Ok(change)) =
+        tokio::time::timeout(Duration::from_millis(200), changes.recv()).await
+    {
+        received.push(change);
+    }
+    received
+}
+
+/// Source of the change that added a mount at `path`, if there was one.
+fn added_by(changes: &[MountChange], path: &Path) -> Option<ChangeSource> {
+    changes.iter().find_map(|change| match change {
+        MountChange::Added(_, mount, source, _) if mount.path == path => Some(*source),
+        _ => None,
+    })
+}
+
+fn temp_mountpoint(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("{name}_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Mounts a tmpfs with `mount(2)`, which (unlike `mount(8)`) does not touch utab.
+fn mount_tmpfs(dir: &Path) {
+    let target = CString::new(dir.as_os_str().as_bytes()).unwrap();
+    let mounted = unsafe {
+        libc::mount(
+            c"tmpfs".as_ptr(),
+            target.as_ptr(),
+            c"tmpfs".as_ptr(),
+            0,
+            std::ptr::null(),
+        )
+    };
+    assert_eq!(mounted, 0, "{}", std::io::Error::last_os_error());
+}
+
+fn unmount(dir: &Path) {
+    let target = CString::new(dir.as_os_str().as_bytes()).unwrap();
+    unsafe { libc::umount(target.as_ptr()) };
+    std::fs::remove_dir(dir).unwrap();
+}
+
+#[tokio::test]
+async fn rescan_request_finds_changes_without_libmount_event() {
+    if unsafe { libc::geteuid() } != 0 {
+        eprintln!("skipping test: mounting requires root");
+        return;
+    }
+
+    let MountMonitor {
+        events: mut changes,
+        rescan,
+        fut,
+        ..
+    } = monitor_userspace_mountinfo().unwrap();
+    let task = tokio::spawn(fut);
+    received(&mut changes).await;
+
+    let dir = temp_mountpoint("mnt_rescan");
+    mount_tmpfs(&dir);
+
+    let unprompted = tokio::time::timeout(Duration::from_millis(200), changes.recv()).await;
+    rescan.send(()).unwrap();
+    let rescanned = received(&mut changes).await;
+
+    unmount(&dir);
+    drop(changes);
+    task.await.unwrap().unwrap();
+
+    assert!(unprompted.is_err(), "{unprompted:?}");
+    assert!(
+        rescanned.iter().any(|change| matches!(
+            change,
+            MountChange::Added(_, mount, ChangeSource::Rescan, None) if mount.path == dir
+        )),
+        "{rescanned:?}"
+    );
+}
+
+#[tokio::test]
+async fn initial_mounts_are_marked_initial() {
+    let MountMonitor {
+        events: mut changes,
+        fut,
+        ..
+    } = monitor_mountinfo().unwrap();
+    let task = tokio::spawn(fut);
+
+    let initial = received(&mut changes).await;
+    drop(changes);
+    task.await.unwrap().unwrap();
+
+    assert_eq!(
+        added_by(&initial, Path::new("/")),
+        Some(ChangeSource::Initial)
+    );
+    // Other tests may be mounting in the meantime, those are the only changes from other sources.
+    for change in initial {
+        match change {
+            MountChange::Added(_, _, ChangeSource::Initial, _) => {}
+            MountChange::Added(_, mount, _, _) | MountChange::Modified(_, mount, _, _) => {
+                assert!(mount.path.starts_with(std::env::temp_dir()), "{mount:?}")
+            }
+            MountChange::Removed(_, _) => {}
+        }
+    }
+}
+
+#[tokio::test]
+async fn kernel_mount_is_marked_kernel() {
+    if unsafe { libc::geteuid() } != 0 {
+        eprintln!("skipping test: mounting requires root");
+        return;
+    }
+
+    let MountMonitor {
+        events: mut changes,
+        fut,
+        ..
+    } = monitor_mountinfo().unwrap();
+    let task = tokio::spawn(fut);
+    received(&mut changes).await;
+
+    let dir = temp_mountpoint("mnt_kernel");
+    mount_tmpfs(&dir);
+    let changed = received(&mut changes).await;
+
+    unmount(&dir);
+    drop(changes);
+    task.await.unwrap().unwrap();
+
+    assert_eq!(
+        added_by(&changed, &dir),
+        Some(ChangeSource::Kernel),
+        "{changed:?}"
+    );
+}
+
+#[tokio::test]
+async fn utab_mount_is_marked_userspace() {
+    if unsafe { libc::geteuid() } != 0 {
+        eprintln!("skipping test: mounting requires root");
+        return;
+    }
+
+    // Kernel events are not watched, so only the utab update can be the source.
+    let MountMonitor {
+        events: mut changes,
+        fut,
+        ..
+    } = monitor_userspace_mountinfo().unwrap();
+    let task = tokio::spawn(fut);
+    received(&mut changes).await;
+
+    // `mount(8)` only records userspace options (`x-*`) in utab.
+    let dir = temp_mountpoint("mnt_utab");
+    let status = Command::new("mount")
+        .args(["-t", "tmpfs", "-o", "x-netns-test=1", "tmpfs"])
+        .arg(&dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let changed = received(&mut changes).await;
+
+    let status = Command::new("umount").arg(&dir).status().unwrap();
+    assert!(status.success());
+    std::fs::remove_dir(&dir).unwrap();
+    drop(changes);
+    task.await.unwrap().unwrap();
+
+    assert_eq!(
+        added_by(&changed, &dir),
+        Some(ChangeSource::Userspace),
+        "{changed:?}"
+    );
+}
@@ -0,0 +1,1425 @@
+use std::{
+    net::{Ipv4Addr, Ipv6Addr},
+    time::Duration,
+};
+
+use net_device_mapping::net_device::{
+    DeviceInfo, DeviceQuery, Duplex, IpVlanMode, Kind, MacVlanMode, OperState, PeerRef, StatsDelta,
+    VxlanEndpoints, collect_dump, drvinfo_string, group_from_attrs, ipv4_broadcast, ipv4_mask,
+    ipv4_network, ipv6_mask, ipv6_network, kind_from_link, link_stats_from_attrs, mac_from_attrs,
+    mac_from_str, mac_to_string, oper_state_from_attrs, read_link_duplex, read_link_speed,
+    stats64_from_attrs, vxlan_endpoints_from_attrs,
+};
+use rtnetlink::packet_route::link::{
+    self, InfoData, InfoGreTap6, InfoIpVlan, InfoKind, InfoMacVlan, InfoVlan, InfoVxlan,
+    LinkAttribute, LinkInfo, LinkLayerType, LinkMessage, Stats, Stats64,
+};
+use rtnetlink::packet_utils::nla::DefaultNla;
+
+// Only tests on real namespaces and devices need the `testutil` fixtures, parsing is tested without them.
+#[cfg(feature = "testutil")]
+use std::{
+    net::{IpAddr, UdpSocket},
+    os::unix::fs::MetadataExt,
+    path::PathBuf,
+    process::Command,
+};
+
+#[cfg(feature = "testutil")]
+use futures::TryStreamExt;
+#[cfg(feature = "testutil")]
+use net_device_mapping::{
+    net_device::{
+        Error, LinkChange, LinkQuerier, QueryError, TunInfo, add_address, create_dummy,
+        delete_device, devices_for_pid, in_namespace, monitor_links, query_netns_links,
+        query_netns_sysctls, sample_device_stats, set_device_mtu, wait_for_device,
+    },
+    netns::{INode, NetworkNamespace},
+    skip_if_link_kind_unsupported, skip_if_unprivileged,
+    testutil::{TempNetns, TempVeth, ip_in, link_index, unique_name},
+};
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn dummy_device_round_trip() {
+    skip_if_unprivileged!();
+    skip_if_link_kind_unsupported!("dummy");
+
+    let (conn, mut handle, _) = rtnetlink::new_connection().unwrap();
+    tokio::spawn(conn);
+
+    let name = unique_name("tdummy");
+    create_dummy(&mut handle, &name).await.unwrap();
+
+    let index = link_index(&mut handle, &name).await.unwrap();
+    delete_device(&mut handle, index).await.unwrap();
+
+    assert!(link_index(&mut handle, &name).await.is_err());
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn mtu_is_set() {
+    skip_if_unprivileged!();
+
+    let (conn, mut handle, _) = rtnetlink::new_connection().unwrap();
+    tokio::spawn(conn);
+
+    let veth = TempVeth::new(&mut handle).await.unwrap();
+    set_device_mtu(&mut handle, veth.index(), 1400)
+        .await
+        .unwrap();
+
+    let link = handle
+        .link()
+        .get()
+        .match_index(veth.index())
+        .execute()
+        .try_next()
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(link.attributes.contains(&LinkAttribute::Mtu(1400)));
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn setting_mtu_of_missing_device_is_no_such_device() {
+    skip_if_unprivileged!();
+
+    let (conn, mut handle, _) = rtnetlink::new_connection().unwrap();
+    tokio::spawn(conn);
+
+    let index = TempVeth::new(&mut handle).await.unwrap().index();
+
+    let result = set_device_mtu(&mut handle, index, 1400).await;
+    assert!(matches!(result, Err(Error::NoSuchDevice(i)) if i == index));
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn deleting_missing_device_is_no_such_device() {
+    skip_if_unprivileged!();
+    skip_if_link_kind_unsupported!("dummy");
+
+    let (conn, mut handle, _) = rtnetlink::new_connection().unwrap();
+    tokio::spawn(conn);
+
+    let name = unique_name("tdummy");
+    create_dummy(&mut handle, &name).await.unwrap();
+    let index = link_index(&mut handle, &name).await.unwrap();
+    delete_device(&mut handle, index).await.unwrap();
+
+    let result = delete_device(&mut handle, index).await;
+    assert!(matches!(result, Err(Error::NoSuchDevice(i)) if i == index));
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn address_is_assigned() {
+    skip_if_unprivileged!();
+    skip_if_link_kind_unsupported!("dummy");
+
+    let (conn, mut handle, _) = rtnetlink::new_connection().unwrap();
+    tokio::spawn(conn);
+
+    let name = unique_name("tdummy");
+    create_dummy(&mut handle, &name).await.unwrap();
+    let index = link_index(&mut handle, &name).await.unwrap();
+
+    let addr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    add_address(&mut handle, index, addr, 24).await.unwrap();
+
+    let assigned = handle
+        .address()
+        .get()
+        .set_link_index_filter(index)
+        .set_address_filter(addr)
+        .set_prefix_length_filter(24)
+        .execute()
+        .try_next()
+        .await
+        .unwrap();
+    let again = add_address(&mut handle, index, addr, 24).await;
+
+    delete_device(&mut handle, index).await.unwrap();
+
+    assert!(assigned.is_some());
+    assert!(matches!(again, Err(Error::AddressExists(a, i)) if a == addr && i == index));
+}
+
+#[test]
+fn ipv4_network_and_broadcast() {
+    let addr = Ipv4Addr::new(192, 168, 1, 77);
+
+    assert_eq!(
+        ipv4_network(addr, [255, 255, 255, 0]),
+        Ipv4Addr::new(192, 168, 1, 0)
+    );
+    assert_eq!(
+        ipv4_broadcast(addr, [255, 255, 255, 0]),
+        Ipv4Addr::new(192, 168, 1, 255)
+    );
+    assert_eq!(
+        ipv4_network(addr, [255, 255, 255, 192]),
+        Ipv4Addr::new(192, 168, 1, 64)
+    );
+    assert_eq!(
+        ipv4_broadcast(addr, [255, 255, 255, 192]),
+        Ipv4Addr::new(192, 168, 1, 127)
+    );
+}
+
+#[test]
+fn ipv6_network_of_64() {
+    let addr: Ipv6Addr = "2001:db8:1:2:3:4:5:6".parse().unwrap();
+    let mask = [
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+
+    assert_eq!(
+        ipv6_network(addr, mask),
+        "2001:db8:1:2::".parse::<Ipv6Addr>().unwrap()
+    );
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn fresh_namespace_does_not_forward() {
+    skip_if_unprivileged!();
+
+    let netns = TempNetns::new().unwrap();
+    let sysctls = query_netns_sysctls(netns.path().to_owned(), &["net.ipv4.ip_forward"])
+        .await
+        .unwrap();
+
+    assert_eq!(sysctls["net.ipv4.ip_forward"], "0");
+}
+
+fn device(name: &str, index: u32, kind: Kind, master_index: Option<u32>) -> DeviceInfo {
+    DeviceInfo {
+        kind,
+        name: name.to_owned(),
+        index,
+        master_index,
+        mac_addr: None,
+        ipv4_addrs: vec![],
+        ipv6_addrs: vec![],
+        netns: 4026531840,
+        is_up: true,
+        oper_state: OperState::Up,
+        is_virtual: true,
+        speed_mbps: None,
+        duplex: None,
+        mtu: Some(1500),
+        group: 0,
+        driver: None,
+        bus_info: None,
+        stats: None,
+    }
+}
+
+#[test]
+fn bridge_and_its_port() {
+    let devices = vec![
+        device("br0", 2, Kind::Bridge { ports: vec![] }, None),
+        device("eth0", 3, Kind::Ethernet, Some(2)),
+        device("eth1", 4, Kind::Ethernet, None),
+    ];
+
+    assert!(devices[0].is_bridge());
+    assert!(!devices[0].is_bridge_port(&devices));
+
+    assert!(!devices[1].is_bridge());
+    assert!(devices[1].is_bridge_port(&devices));
+
+    assert!(!devices[2].is_bridge_port(&devices));
+}
+
+#[test]
+fn loopback_is_skipped_when_requested() {
+    let lo = device("lo", 1, Kind::Loopback, None);
+    let eth = device("eth0", 2, Kind::Ethernet, None);
+
+    let query = DeviceQuery::new().skip_loopback(true);
+    assert!(!query.matches(&lo));
+    assert!(query.matches(&eth));
+
+    assert!(DeviceQuery::new().matches(&lo));
+}
+
+#[test]
+fn link_modes_are_read_from_sysfs() {
+    let sysfs_net = std::env::temp_dir().join(format!("sysfs-net-{}", std::process::id()));
+    for (name, speed, duplex) in [
+        ("eth0", "1000\n", "full\n"),
+        ("eth1", "100\n", "half\n"),
+        ("nocarrier0", "-1\n", "unknown\n"),
+        ("oldkernel0", "4294967295\n", "unknown\n"),
+    ] {
+        let dir = sysfs_net.join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("speed"), speed).unwrap();
+        std::fs::write(dir.join("duplex"), duplex).unwrap();
+    }
+    // Virtual devices may have no attributes at all.
+    std::fs::create_dir_all(sysfs_net.join("virtual0")).unwrap();
+
+    assert_eq!(read_link_speed(&sysfs_net, "eth0"), Some(1000));
+    assert_eq!(read_link_duplex(&sysfs_net, "eth0"), Some(Duplex::Full));
+    assert_eq!(read_link_speed(&sysfs_net, "eth1"), Some(100));
+    assert_eq!(read_link_duplex(&sysfs_net, "eth1"), Some(Duplex::Half));
+    for name in ["nocarrier0", "oldkernel0", "virtual0", "missing0"] {
+        assert_eq!(read_link_speed(&sysfs_net, name), None, "{name}");
+        assert_eq!(read_link_duplex(&sysfs_net, name), None, "{name}");
+    }
+
+    std::fs::remove_dir_all(&sysfs_net).unwrap();
+}
+
+#[test]
+fn group_is_read_and_filtered() {
+    let mut grouped = link(LinkLayerType::Ether, None);
+    grouped.attributes.push(LinkAttribute::Group(7));
+    assert_eq!(group_from_attrs(&grouped.attributes), 7);
+    assert_eq!(
+        group_from_attrs(&link(LinkLayerType::Ether, None).attributes),
+        0
+    );
+
+    let mut eth = device("eth0", 2, Kind::Ethernet, None);
+    eth.group = group_from_attrs(&grouped.attributes);
+    let lo = device("lo", 1, Kind::Loopback, None);
+
+    let query = DeviceQuery::new().in_group(7);
+    assert!(query.matches(&eth));
+    assert!(!query.matches(&lo));
+}
+
+#[test]
+fn stats_delta_survives_wraparound() {
+    let mut before = Stats64::default();
+    before.rx_bytes = u64::MAX - 99;
+    let mut after = Stats64::default();
+    after.rx_bytes = 100;
+
+    let delta = StatsDelta::between(&before, &after, Duration::from_secs(2));
+
+    assert_eq!(delta.rx_bytes, 100.0);
+    assert_eq!(delta.tx_bytes, 0.0);
+}
+
+#[test]
+fn stats_delta_of_no_time_is_zero() {
+    let before = Stats64::default();
+    let mut after = Stats64::default();
+    after.rx_bytes = 100;
+
+    let delta = StatsDelta::between(&before, &after, Duration::ZERO);
+
+    assert_eq!(delta.rx_bytes, 0.0);
+    assert!(!delta.tx_bytes.is_nan());
+}
+
+#[test]
+fn stats64_fall_back_to_32_bit_stats() {
+    let mut stats = Stats::default();
+    stats.rx_bytes = u32::MAX;
+    stats.tx_dropped = 3;
+
+    let stats64 = stats64_from_attrs(&[LinkAttribute::Stats(stats)]).unwrap();
+    assert_eq!(stats64.rx_bytes, u32::MAX.into());
+    assert_eq!(stats64.tx_dropped, 3);
+
+    // 64-bit ones are preferred, when there are both.
+    let mut wide = Stats64::default();
+    wide.rx_bytes = u64::MAX;
+    let stats64 =
+        stats64_from_attrs(&[LinkAttribute::Stats(stats), LinkAttribute::Stats64(wide)]).unwrap();
+    assert_eq!(stats64.rx_bytes, u64::MAX);
+
+    assert_eq!(stats64_from_attrs(&[]), None);
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn stats_are_not_sampled_over_zero_interval() {
+    let result = sample_device_stats("/proc/self/ns/net".into(), "lo", Duration::ZERO).await;
+
+    assert!(matches!(result, Err(Error::ZeroInterval)));
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn loopback_traffic_is_sampled() {
+    skip_if_unprivileged!();
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let target = socket.local_addr().unwrap();
+    let traffic = std::thread::spawn(move || {
+        for _ in 0..50 {
+            socket.send_to(&[0; 512], target).unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    });
+
+    let delta = sample_device_stats("/proc/self/ns/net".into(), "lo", Duration::from_millis(200))
+        .await
+        .unwrap();
+    traffic.join().unwrap();
+
+    assert!(delta.rx_bytes > 0.0);
+    assert!(delta.rx_packets > 0.0);
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn both_veth_ends_are_listed() {
+    skip_if_unprivileged!();
+
+    let (conn, mut handle, _) = rtnetlink::new_connection().unwrap();
+    tokio::spawn(conn);
+    let veth = TempVeth::new(&mut handle).await.unwrap();
+
+    let netns_file = PathBuf::from("/proc/self/ns/net");
+    let netns = std::fs::metadata(&netns_file).unwrap().ino();
+    let mut devices = DeviceInfo::all([netns_file]).await;
+    devices.sort_by(|a, b| (a.netns, &a.name).cmp(&(b.netns, &b.name)));
+
+    for name in [veth.name(), veth.peer()] {
+        let device = devices.iter().find(|device| device.name == name).unwrap();
+        assert_eq!(device.netns, netns);
+        assert!(device.is_virtual);
+        assert!(!device.is_up);
+    }
+    assert!(
+        devices
+            .iter()
+            .any(|device| matches!(device.kind, Kind::Loopback))
+    );
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn driver_is_read_with_ethtool() {
+    skip_if_unprivileged!();
+
+    let (conn, mut handle, _) = rtnetlink::new_connection().unwrap();
+    tokio::spawn(conn);
+    let veth = TempVeth::new(&mut handle).await.unwrap();
+
+    let devices = DeviceInfo::of_namespace(PathBuf::from("/proc/self/ns/net"))
+        .await
+        .unwrap();
+
+    let veth = devices
+        .iter()
+        .find(|device| device.name == veth.name())
+        .unwrap();
+    assert_eq!(veth.driver.as_deref(), Some("veth"));
+    assert_eq!(veth.bus_info, None);
+
+    // Loopback does not implement `ETHTOOL_GDRVINFO`.
+    let lo = devices
+        .iter()
+        .find(|device| matches!(device.kind, Kind::Loopback))
+        .unwrap();
+    assert_eq!((&lo.driver, &lo.bus_info), (&None, &None));
+}
+
+#[test]
+fn drvinfo_fields_are_read_up_to_nul() {
+    let mut field = [0u8; 32];
+    field[..6].copy_from_slice(b"e1000e");
+    assert_eq!(drvinfo_string(&field).as_deref(), Some("e1000e"));
+
+    field[..12].copy_from_slice(b"0000:00:1f.6");
+    assert_eq!(drvinfo_string(&field).as_deref(), Some("0000:00:1f.6"));
+
+    assert_eq!(drvinfo_string(&[0; 32]), None);
+    assert_eq!(drvinfo_string(b"N/A\0\0\0"), None);
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn vanished_namespace_does_not_fail_the_scan() {
+    skip_if_unprivileged!();
+
+    let netns = TempNetns::new().unwrap();
+    let vanished = netns.path().to_owned();
+    drop(netns);
+
+    let own = PathBuf::from("/proc/self/ns/net");
+    let devices = DeviceInfo::all([vanished.clone(), own.clone()]).await;
+    assert!(devices.iter().any(|device| device.name == "lo"));
+
+    let per_namespace = DeviceInfo::all_per_namespace([vanished.clone(), own]).await;
+    assert!(matches!(
+        &per_namespace[0],
+        (path, Err(QueryError::CoulndtOpenNetns(_))) if *path == vanished
+    ));
+    assert!(per_namespace[1].1.is_ok());
+}
+
+#[test]
+fn mac_is_only_parsed_from_six_bytes() {
+    let ethernet = [LinkAttribute::Address(vec![0x02, 0, 0, 0xaa, 0xbb, 0xcc])];
+    assert_eq!(
+        mac_from_attrs(&ethernet),
+        Some([0x02, 0, 0, 0xaa, 0xbb, 0xcc])
+    );
+
+    let infiniband = [LinkAttribute::Address(vec![0x80; 20])];
+    assert_eq!(mac_from_attrs(&infiniband), None);
+
+    assert_eq!(mac_from_attrs(&[LinkAttribute::Address(vec![])]), None);
+    assert_eq!(mac_from_attrs(&[LinkAttribute::Mtu(1500)]), None);
+}
+
+#[test]
+fn mac_string_round_trip() {
+    let mac = [0x02, 0, 0, 0xaa, 0xbb, 0xcc];
+    assert_eq!(mac_to_string(mac), "02:00:00:aa:bb:cc");
+    assert_eq!(mac_from_str("02:00:00:AA:BB:CC"), Some(mac));
+
+    assert_eq!(mac_from_str("02:00:00:aa:bb"), None);
+    assert_eq!(mac_from_str("02:00:00:aa:bb:cc:dd"), None);
+    assert_eq!(mac_from_str("2:00:00:aa:bb:cc"), None);
+    assert_eq!(mac_from_str("02:00:00:aa:bb:zz"), None);
+}
+
+#[test]
+fn masks_from_prefix_length() {
+    assert_eq!(ipv4_mask(24), [255, 255, 255, 0]);
+    assert_eq!(ipv4_mask(26), [255, 255, 255, 192]);
+    assert_eq!(ipv4_mask(0), [0; 4]);
+    assert_eq!(ipv4_mask(32), [255; 4]);
+
+    let mut mask_64 = [0; 16];
+    mask_64[..8].fill(0xff);
+    assert_eq!(ipv6_mask(64), mask_64);
+    assert_eq!(ipv6_mask(128), [0xff; 16]);
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn addresses_are_attached_to_their_device() {
+    skip_if_unprivileged!();
+
+    let (conn, mut handle, _) = rtnetlink::new_connection().unwrap();
+    tokio::spawn(conn);
+    let veth = TempVeth::new(&mut handle).await.unwrap();
+    let addr = Ipv4Addr::new(10, 200, 0, 1);
+    add_address(&mut handle, veth.index(), IpAddr::V4(addr), 24)
+        .await
+        .unwrap();
+
+    let devices = DeviceInfo::of_namespace("/proc/self/ns/net".into())
+        .await
+        .unwrap();
+
+    let device = devices.iter().find(|d| d.name == veth.name()).unwrap();
+    assert_eq!(device.ipv4_addrs, vec![(addr, [255, 255, 255, 0])]);
+    let peer = devices.iter().find(|d| d.name == veth.peer()).unwrap();
+    assert!(peer.ipv4_addrs.is_empty());
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn veth_peers_are_resolved() {
+    skip_if_unprivileged!();
+
+    let (conn, mut handle, _) = rtnetlink::new_connection().unwrap();
+    tokio::spawn(conn);
+    let veth = TempVeth::new(&mut handle).await.unwrap();
+    let own = std::fs::metadata("/proc/self/ns/net").unwrap().ino();
+
+    let devices = DeviceInfo::of_namespace("/proc/self/ns/net".into())
+        .await
+        .unwrap();
+    let kind_of = |name: &str| {
+        devices
+            .iter()
+            .find(|device| device.name == name)
+            .map(|device| device.kind.clone())
+    };
+
+    let peer_of = |name: &str| PeerRef {
+        name: name.to_owned(),
+        netns: own,
+    };
+    assert_eq!(
+        kind_of(veth.name()),
+        Some(Kind::Veth {
+            peer: peer_of(veth.peer())
+        })
+    );
+    assert_eq!(
+        kind_of(veth.peer()),
+        Some(Kind::Veth {
+            peer: peer_of(veth.name())
+        })
+    );
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn veth_peer_in_other_namespace_is_resolved() {
+    skip_if_unprivileged!();
+
+    let (conn, mut handle, _) = rtnetlink::new_connection().unwrap();
+    tokio::spawn(conn);
+    let netns = TempNetns::new().unwrap();
+    // Two peers in the same namespace, which is only looked up once for both.
+    let veths = [
+        TempVeth::new(&mut handle).await.unwrap(),
+        TempVeth::new(&mut handle).await.unwrap(),
+    ];
+
+    for veth in &veths {
+        veth.move_peer_to(&mut handle, &netns).await.unwrap();
+    }
+
+    let devices = DeviceInfo::of_namespace("/proc/self/ns/net".into())
+        .await
+        .unwrap();
+    for veth in &veths {
+        let device = devices.iter().find(|d| d.name == veth.name()).unwrap();
+        assert_eq!(
+            device.kind,
+            Kind::Veth {
+                peer: PeerRef {
+                    name: veth.peer().to_owned(),
+                    netns: std::fs::metadata(netns.path()).unwrap().ino(),
+                }
+            }
+        );
+    }
+
+    // Seen from the other namespace, the id of the namespace of the veths is not resolved.
+    let devices = DeviceInfo::of_namespace(netns.path().to_owned())
+        .await
+        .unwrap();
+    let peer = devices.iter().find(|d| d.name == veths[0].peer()).unwrap();
+    assert_eq!(
+        peer.kind,
+        Kind::Veth {
+            peer: PeerRef {
+                name: String::new(),
+                netns: std::fs::metadata(netns.path()).unwrap().ino(),
+            }
+        }
+    );
+}
+
+fn link(link_layer_type: LinkLayerType, info_kind: Option<InfoKind>) -> LinkMessage {
+    let mut link = LinkMessage::default();
+    link.header.link_layer_type = link_layer_type;
+    if let Some(info_kind) = info_kind {
+        link.attributes
+            .push(LinkAttribute::LinkInfo(vec![LinkInfo::Kind(info_kind)]));
+    }
+    link
+}
+
+#[test]
+fn only_operational_devices_are_queried() {
+    let query = DeviceQuery::new().operational_only(true);
+    let device_in = |state: Option<link::State>, is_up: bool| {
+        let mut link = link(LinkLayerType::Ether, None);
+        if let Some(state) = state {
+            link.attributes.push(LinkAttribute::OperState(state));
+        }
+        let mut eth = device("eth0", 2, Kind::Ethernet, None);
+        eth.oper_state = oper_state_from_attrs(&link.attributes);
+        eth.is_up = is_up;
+        eth
+    };
+
+    let up = device_in(Some(link::State::Up), true);
+    let dormant = device_in(Some(link::State::Dormant), true);
+    let lower_down = device_in(Some(link::State::LowerLayerDown), true);
+    assert_eq!(dormant.oper_state, OperState::Dormant);
+    assert_eq!(lower_down.oper_state, OperState::LowerLayerDown);
+
+    assert!(query.matches(&up));
+    assert!(!query.matches(&dormant));
+    assert!(!query.matches(&lower_down));
+    // Without carrier, administrative state decides.
+    assert!(query.matches(&device_in(None, true)));
+    assert!(!query.matches(&device_in(None, false)));
+
+    // Not filtered unless asked for.
+    assert!(DeviceQuery::new().matches(&dormant));
+}
+
+#[test]
+fn kind_is_parsed_from_link() {
+    let netns = 4026531840;
+    let kind =
+        |link_layer_type, info_kind| kind_from_link(netns, &link(link_layer_type, info_kind));
+    let placeholder = PeerRef {
+        name: String::new(),
+        netns,
+    };
+
+    assert_eq!(kind(LinkLayerType::Loopback, None), Kind::Loopback);
+    assert_eq!(kind(LinkLayerType::Ether, None), Kind::Ethernet);
+    assert_eq!(kind(LinkLayerType::Ppp, None), Kind::Ppp);
+    assert_eq!(
+        kind(LinkLayerType::Ether, Some(InfoKind::Veth)),
+        Kind::Veth {
+            peer: placeholder.clone()
+        }
+    );
+    assert_eq!(
+        kind(LinkLayerType::Ether, Some(InfoKind::Bridge)),
+        Kind::Bridge { ports: vec![] }
+    );
+    assert_eq!(
+        kind(LinkLayerType::Ether, Some(InfoKind::Bond)),
+        Kind::Bond { slaves: vec![] }
+    );
+    assert_eq!(
+        kind(LinkLayerType::Ether, Some(InfoKind::Vlan)),
+        Kind::Vlan {
+            id: 0,
+            parent: placeholder
+        }
+    );
+    assert_eq!(
+        kind(LinkLayerType::Ether, Some(InfoKind::Vxlan)),
+        Kind::Vxlan { vni: 0 }
+    );
+    assert_eq!(
+        kind(LinkLayerType::None, Some(InfoKind::Tun)),
+        Kind::Tun(None)
+    );
+    assert_eq!(
+        kind(LinkLayerType::Ether, Some(InfoKind::Tun)),
+        Kind::Tap(None)
+    );
+    assert_eq!(
+        kind(LinkLayerType::None, Some(InfoKind::Wireguard)),
+        Kind::Wireguard {
+            listen_port: 0,
+            peers: vec![]
+        }
+    );
+    assert_eq!(
+        kind(
+            LinkLayerType::Ether,
+            Some(InfoKind::Other("foo".to_owned()))
+        ),
+        Kind::Other("foo".to_owned())
+    );
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn device_moved_into_namespace_is_awaited() {
+    skip_if_unprivileged!();
+
+    let (conn, mut handle, _) = rtnetlink::new_connection().unwrap();
+    tokio::spawn(conn);
+    let netns = TempNetns::new().unwrap();
+    let veth = TempVeth::new(&mut handle).await.unwrap();
+
+    let (netns_path, peer) = (netns.path().to_owned(), veth.peer().to_owned());
+    let waiting =
+        tokio::spawn(
+            async move { wait_for_device(netns_path, &peer, Duration::from_secs(5)).await },
+        );
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    veth.move_peer_to(&mut handle, &netns).await.unwrap();
+
+    let device = waiting.await.unwrap().unwrap();
+    assert_eq!(device.name, veth.peer());
+    assert_eq!(device.netns, std::fs::metadata(netns.path()).unwrap().ino());
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn waiting_for_missing_device_times_out() {
+    skip_if_unprivileged!();
+
+    let timeout = Duration::from_millis(100);
+    let result = wait_for_device("/proc/self/ns/net".into(), "nonexistent0", timeout).await;
+
+    assert!(
+        matches!(result, Err(Error::DeviceTimeout(name, t)) if name == "nonexistent0" && t == timeout)
+    );
+}
+
+#[test]
+fn vlan_id_is_parsed() {
+    let mut vlan = link(LinkLayerType::Ether, None);
+    vlan.attributes.push(LinkAttribute::LinkInfo(vec![
+        LinkInfo::Kind(InfoKind::Vlan),
+        LinkInfo::Data(InfoData::Vlan(vec![InfoVlan::Id(42)])),
+    ]));
+    vlan.attributes.push(LinkAttribute::Link(2));
+
+    let Kind::Vlan { id, .. } = kind_from_link(4026531840, &vlan) else {
+        panic!("expected a VLAN");
+    };
+    assert_eq!(id, 42);
+}
+
+#[test]
+fn vxlan_vni_and_endpoints_are_parsed() {
+    let mut vxlan = link(LinkLayerType::Ether, None);
+    vxlan.attributes.push(LinkAttribute::LinkInfo(vec![
+        LinkInfo::Kind(InfoKind::Vxlan),
+        LinkInfo::Data(InfoData::Vxlan(vec![
+            InfoVxlan::Id(100),
+            InfoVxlan::Group(Ipv4Addr::new(239, 1, 1, 1)),
+            InfoVxlan::Local(Ipv4Addr::new(10, 0, 0, 1)),
+        ])),
+    ]));
+
+    assert_eq!(kind_from_link(4026531840, &vxlan), Kind::Vxlan { vni: 100 });
+    assert_eq!(
+        vxlan_endpoints_from_attrs(&vxlan.attributes),
+        VxlanEndpoints {
+            group: Some(Ipv4Addr::new(239, 1, 1, 1).into()),
+            local: Some(Ipv4Addr::new(10, 0, 0, 1).into()),
+        }
+    );
+
+    let mut without_vni = link(LinkLayerType::Ether, None);
+    without_vni
+        .attributes
+        .push(LinkAttribute::LinkInfo(vec![LinkInfo::Kind(
+            InfoKind::Vxlan,
+        )]));
+    assert_eq!(
+        kind_from_link(4026531840, &without_vni),
+        Kind::Vxlan { vni: 0 }
+    );
+}
+
+#[test]
+fn ip6gre_endpoints_are_parsed() {
+    let local: Ipv6Addr = "fd00::1".parse().unwrap();
+    let mut gre = link(LinkLayerType::Ether, None);
+    gre.attributes.push(LinkAttribute::LinkInfo(vec![
+        LinkInfo::Kind(InfoKind::GreTap6),
+        LinkInfo::Data(InfoData::GreTap6(vec![
+            InfoGreTap6::Other(DefaultNla::new(6, local.octets().to_vec())),
+            // Remote is unset (point-to-multipoint).
+            InfoGreTap6::Other(DefaultNla::new(7, vec![0; 16])),
+        ])),
+    ]));
+
+    assert_eq!(
+        kind_from_link(4026531840, &gre),
+        Kind::Gre {
+            local: Some(local.into()),
+            remote: None,
+        }
+    );
+}
+
+#[test]
+fn macvlan_and_ipvlan_modes_are_parsed() {
+    let netns = 4026531840;
+    let placeholder = PeerRef {
+        name: String::new(),
+        netns,
+    };
+
+    let mut macvlan = link(LinkLayerType::Ether, None);
+    macvlan.attributes.push(LinkAttribute::LinkInfo(vec![
+        LinkInfo::Kind(InfoKind::MacVlan),
+        LinkInfo::Data(InfoData::MacVlan(vec![InfoMacVlan::Mode(
+            link::MacVlanMode::Passthrough,
+        )])),
+    ]));
+    assert_eq!(
+        kind_from_link(netns, &macvlan),
+        Kind::MacVlan {
+            parent: placeholder.clone(),
+            mode: MacVlanMode::Passthru,
+        }
+    );
+
+    let mut ipvlan = link(LinkLayerType::Ether, None);
+    ipvlan.attributes.push(LinkAttribute::LinkInfo(vec![
+        LinkInfo::Kind(InfoKind::IpVlan),
+        LinkInfo::Data(InfoData::IpVlan(vec![InfoIpVlan::Mode(
+            link::IpVlanMode::L2,
+        )])),
+    ]));
+    assert_eq!(
+        kind_from_link(netns, &ipvlan),
+        Kind::IpVlan {
+            parent: placeholder.clone(),
+            mode: IpVlanMode::L2,
+        }
+    );
+
+    // Without the mode attribute, kernel defaults apply.
+    assert_eq!(
+        kind_from_link(netns, &link(LinkLayerType::Ether, Some(InfoKind::MacVlan))),
+        Kind::MacVlan {
+            parent: placeholder.clone(),
+            mode: MacVlanMode::Vepa,
+        }
+    );
+    assert_eq!(
+        kind_from_link(netns, &link(LinkLayerType::Ether, Some(InfoKind::IpVlan))),
+        Kind::IpVlan {
+            parent: placeholder,
+            mode: IpVlanMode::L3,
+        }
+    );
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn gre_tunnel_endpoints_are_read() {
+    skip_if_unprivileged!();
+    skip_if_link_kind_unsupported!("gre");
+
+    let netns = TempNetns::new().unwrap();
+    ip_in(
+        &netns,
+        &[
+            "tunnel", "add", "tgre0", "mode", "gre", "local", "10.0.0.1", "remote", "10.0.0.2",
+        ],
+    )
+    .unwrap();
+
+    let devices = DeviceInfo::of_namespace(netns.path().to_owned())
+        .await
+        .unwrap();
+    let gre = devices
+        .iter()
+        .find(|device| device.name == "tgre0")
+        .unwrap();
+
+    assert_eq!(
+        gre.kind,
+        Kind::Gre {
+            local: Some(Ipv4Addr::new(10, 0, 0, 1).into()),
+            remote: Some(Ipv4Addr::new(10, 0, 0, 2).into()),
+        }
+    );
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn bridge_ports_are_resolved() {
+    skip_if_unprivileged!();
+
+    let netns = TempNetns::new().unwrap();
+    let ip = |args: &[&str]| ip_in(&netns, args).unwrap();
+    ip(&["link", "add", "br0", "type", "bridge"]);
+    ip(&["link", "add", "br1", "type", "bridge"]);
+    ip(&[
+        "link", "add", "veth0", "type", "veth", "peer", "name", "veth1",
+    ]);
+    ip(&["link", "set", "veth0", "master", "br0"]);
+    ip(&["link", "set", "veth1", "master", "br0"]);
+
+    let devices = DeviceInfo::of_namespace(netns.path().to_owned())
+        .await
+        .unwrap();
+    let kind = |name: &str| {
+        devices
+            .iter()
+            .find(|device| device.name == name)
+            .map(|device| device.kind.clone())
+            .unwrap()
+    };
+    let netns_inode = std::fs::metadata(netns.path()).unwrap().ino();
+
+    let Kind::Bridge { mut ports } = kind("br0") else {
+        panic!("expected a bridge");
+    };
+    ports.sort_by(|a, b| a.name.cmp(&b.name));
+    assert_eq!(
+        ports,
+        ["veth0", "veth1"].map(|name| PeerRef {
+            name: name.to_owned(),
+            netns: netns_inode,
+        })
+    );
+    // Bridge without ports is still a bridge.
+    assert_eq!(kind("br1"), Kind::Bridge { ports: vec![] });
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn bond_slaves_are_resolved() {
+    skip_if_unprivileged!();
+    skip_if_link_kind_unsupported!("bond", "dummy");
+
+    let netns = TempNetns::new().unwrap();
+    let ip = |args: &[&str]| ip_in(&netns, args).unwrap();
+    ip(&["link", "add", "bond0", "type", "bond"]);
+    for slave in ["dummy0", "dummy1"] {
+        ip(&["link", "add", slave, "type", "dummy"]);
+        ip(&["link", "set", slave, "master", "bond0"]);
+    }
+
+    let devices = DeviceInfo::of_namespace(netns.path().to_owned())
+        .await
+        .unwrap();
+    let bond = devices
+        .iter()
+        .find(|device| device.name == "bond0")
+        .unwrap();
+    let netns_inode = std::fs::metadata(netns.path()).unwrap().ino();
+
+    let Kind::Bond { mut slaves } = bond.kind.clone() else {
+        panic!("expected a bond");
+    };
+    slaves.sort_by(|a, b| a.name.cmp(&b.name));
+    assert_eq!(
+        slaves,
+        ["dummy0", "dummy1"].map(|name| PeerRef {
+            name: name.to_owned(),
+            netns: netns_inode,
+        })
+    );
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn tun_owner_is_read() {
+    skip_if_unprivileged!();
+
+    let netns = TempNetns::new().unwrap();
+    ip_in(
+        &netns,
+        &[
+            "tuntap", "add", "mode", "tun", "name", "tun0", "user", "1000",
+        ],
+    )
+    .unwrap();
+
+    let devices = DeviceInfo::of_namespace(netns.path().to_owned())
+        .await
+        .unwrap();
+    let tun = devices.iter().find(|device| device.name == "tun0").unwrap();
+
+    assert_eq!(
+        tun.kind,
+        Kind::Tun(Some(TunInfo {
+            owner: Some(1000),
+            group: None,
+            packet_info: false,
+            vnet_hdr: false,
+            multi_queue: false,
+            persistent: true,
+        }))
+    );
+}
+
+#[test]
+fn link_stats_prefer_64_bit_counters() {
+    let mut stats = Stats::default();
+    stats.rx_bytes = 1;
+    let mut stats64 = Stats64::default();
+    stats64.rx_bytes = u64::from(u32::MAX) + 1;
+
+    let mut link = link(LinkLayerType::Ether, None);
+    assert_eq!(link_stats_from_attrs(&link.attributes), None);
+
+    link.attributes.push(LinkAttribute::Stats(stats));
+    assert_eq!(
+        link_stats_from_attrs(&link.attributes).map(|stats| stats.rx_bytes),
+        Some(1)
+    );
+
+    link.attributes.push(LinkAttribute::Stats64(stats64));
+    assert_eq!(
+        link_stats_from_attrs(&link.attributes).map(|stats| stats.rx_bytes),
+        Some(u64::from(u32::MAX) + 1)
+    );
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn link_querier_reuses_workers_across_namespaces() {
+    skip_if_unprivileged!();
+
+    let namespaces: Vec<TempNetns> = (0..4).map(|_| TempNetns::new().unwrap()).collect();
+    let querier = LinkQuerier::new(2);
+
+    // More namespaces than workers, and each one twice.
+    let queries = namespaces
+        .iter()
+        .chain(&namespaces)
+        .map(|netns| querier.query_links(netns.path().to_owned()));
+    let dumps = futures::future::join_all(queries).await;
+
+    for dump in dumps {
+        let names: Vec<_> = dump
+            .unwrap()
+            .items
+            .iter()
+            .filter_map(|link| {
+                link.attributes.iter().find_map(|attr| match attr {
+                    LinkAttribute::IfName(name) => Some(name.clone()),
+                    _ => None,
+                })
+            })
+            .collect();
+        // Fresh namespaces only have a loopback device.
+        assert_eq!(names, ["lo"]);
+    }
+
+    let own = querier
+        .query_links("/proc/self/ns/net".into())
+        .await
+        .unwrap();
+    let expected = query_netns_links("/proc/self/ns/net".into()).await.unwrap();
+    assert_eq!(own.items.len(), expected.items.len());
+}
+
+/// Network namespace inodes of all threads of this process.
+#[cfg(feature = "testutil")]
+fn own_threads_netns() -> Vec<INode> {
+    std::fs::read_dir("/proc/self/task")
+        .unwrap()
+        .filter_map(|task| std::fs::metadata(task.ok()?.path().join("ns/net")).ok())
+        .map(|metadata| metadata.ino())
+        .collect()
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn link_querier_workers_leave_queried_namespaces() {
+    skip_if_unprivileged!();
+
+    let netns = TempNetns::new().unwrap();
+    let inode = std::fs::metadata(netns.path()).unwrap().ino();
+    let querier = LinkQuerier::new(2);
+
+    querier.query_links(netns.path().to_owned()).await.unwrap();
+    // Failed job, for a file that is not a network namespace.
+    assert!(
+        querier
+            .query_links("/proc/self/ns/uts".into())
+            .await
+            .is_err()
+    );
+
+    // Workers are idle now, but still running.
+    assert!(!own_threads_netns().contains(&inode));
+
+    drop(netns);
+    let namespaces = NetworkNamespace::all().await.unwrap();
+    assert!(namespaces.iter().all(|netns| netns.inode != inode));
+
+    drop(querier);
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn device_is_found_by_name() {
+    skip_if_unprivileged!();
+    let own = PathBuf::from("/proc/self/ns/net");
+
+    let lo = DeviceInfo::by_name(own.clone(), "lo")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(lo.kind, Kind::Loopback);
+
+    let missing = DeviceInfo::by_name(own, "nonexistent0").await.unwrap();
+    assert!(missing.is_none());
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn link_changes_are_monitored_in_namespace() {
+    skip_if_unprivileged!();
+
+    let (conn, mut handle, _) = rtnetlink::new_connection().unwrap();
+    tokio::spawn(conn);
+    let netns = TempNetns::new().unwrap();
+    let veth = TempVeth::new(&mut handle).await.unwrap();
+
+    let (mut changes, fut) = monitor_links(netns.path().to_owned()).await.unwrap();
+    tokio::spawn(fut);
+
+    veth.move_peer_to(&mut handle, &netns).await.unwrap();
+
+    let peer = veth.peer().to_owned();
+    let next_change = async |changes: &mut tokio::sync::broadcast::Receiver<LinkChange>| {
+        tokio::time::timeout(Duration::from_secs(5), changes.recv())
+            .await
+            .unwrap()
+            .unwrap()
+    };
+
+    // Only the moved end shows up, host side of the pair is in another namespace.
+    let added = next_change(&mut changes).await;
+    assert!(
+        matches!(&added, LinkChange::Added(device) if device.name == peer),
+        "{added:?}"
+    );
+
+    drop(veth);
+    loop {
+        match next_change(&mut changes).await {
+            LinkChange::Removed(device) => {
+                assert_eq!(device.name, peer);
+                assert_eq!(device.netns, std::fs::metadata(netns.path()).unwrap().ino());
+                break;
+            }
+            LinkChange::Changed(device) => assert_eq!(device.name, peer),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn veth_pair_is_reported_as_one_event() {
+    skip_if_unprivileged!();
+
+    let netns = TempNetns::new().unwrap();
+    let (mut changes, fut) = monitor_links(netns.path().to_owned()).await.unwrap();
+    tokio::spawn(fut);
+
+    ip_in(
+        &netns,
+        &[
+            "link", "add", "pair0", "type", "veth", "peer", "name", "pair1",
+        ],
+    )
+    .unwrap();
+
+    let change = tokio::time::timeout(Duration::from_secs(5), changes.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    let LinkChange::VethPairCreated(pair) = change else {
+        panic!("expected a veth pair, got {change:?}");
+    };
+    let (first, second) = *pair;
+    let mut names = [first.name.as_str(), second.name.as_str()];
+    names.sort();
+    assert_eq!(names, ["pair0", "pair1"]);
+    let netns_inode = std::fs::metadata(netns.path()).unwrap().ino();
+    assert_eq!(
+        first.kind,
+        Kind::Veth {
+            peer: PeerRef {
+                name: second.name.clone(),
+                netns: netns_inode,
+            }
+        }
+    );
+
+    // Neither end is reported again as added on its own.
+    while let Ok(Ok(change)) =
+        tokio::time::timeout(Duration::from_millis(300), changes.recv()).await
+    {
+        assert!(matches!(change, LinkChange::Changed(_)), "{change:?}");
+    }
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn loopback_only_namespaces_are_not_active() {
+    skip_if_unprivileged!();
+
+    let (conn, mut handle, _) = rtnetlink::new_connection().unwrap();
+    tokio::spawn(conn);
+    let empty = TempNetns::new().unwrap();
+    let with_veth = TempNetns::new().unwrap();
+    let veth = TempVeth::new(&mut handle).await.unwrap();
+
+    veth.move_peer_to(&mut handle, &with_veth).await.unwrap();
+
+    let active =
+        DeviceInfo::active_per_namespace([empty.path().to_owned(), with_veth.path().to_owned()])
+            .await;
+
+    let paths: Vec<_> = active.iter().map(|(path, _)| path.as_path()).collect();
+    assert_eq!(paths, [with_veth.path()]);
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn async_closure_runs_inside_namespace() {
+    skip_if_unprivileged!();
+
+    let netns = TempNetns::new().unwrap();
+    let inode = std::fs::metadata(netns.path()).unwrap().ino();
+    let own = std::fs::metadata("/proc/thread-self/ns/net").unwrap().ino();
+
+    let seen = in_namespace(netns.path().to_owned(), || async {
+        // Local tasks run on the same thread, in the same namespace.
+        let task = tokio::task::spawn_local(async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            std::fs::metadata("/proc/thread-self/ns/net").unwrap().ino()
+        });
+        Ok(task.await.unwrap())
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(seen, inode);
+    assert_eq!(
+        std::fs::metadata("/proc/thread-self/ns/net").unwrap().ino(),
+        own
+    );
+
+    let missing = in_namespace(PathBuf::from("/nonexistent/netns"), || async { Ok(()) }).await;
+    assert!(matches!(missing, Err(QueryError::CoulndtOpenNetns(_))));
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn link_modes_are_only_read_when_asked_for() {
+    skip_if_unprivileged!();
+
+    let netns = TempNetns::new().unwrap();
+    for args in [
+        &[
+            "link", "add", "modes0", "type", "veth", "peer", "name", "modes1",
+        ][..],
+        &["link", "set", "modes0", "up"],
+        &["link", "set", "modes1", "up"],
+    ] {
+        ip_in(&netns, args).unwrap();
+    }
+
+    let devices = DeviceInfo::of_namespace(netns.path().to_owned())
+        .await
+        .unwrap();
+    let veth = devices
+        .iter()
+        .find(|device| device.name == "modes0")
+        .unwrap();
+    assert_eq!((veth.speed_mbps, veth.duplex), (None, None));
+
+    let query = DeviceQuery::new().skip_loopback(true).with_link_modes(true);
+    let mut per_namespace = query.execute_per_namespace([netns.path().to_owned()]).await;
+    let (_path, devices) = per_namespace.pop().unwrap();
+    let devices = devices.unwrap();
+    assert_eq!(devices.len(), 2);
+    let veth = devices
+        .iter()
+        .find(|device| device.name == "modes0")
+        .unwrap();
+    // veth always claims 10Gbit/s full duplex.
+    assert_eq!(veth.speed_mbps, Some(10000));
+    assert_eq!(veth.duplex, Some(Duplex::Full));
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn devices_of_a_process_are_the_ones_of_its_namespace() {
+    skip_if_unprivileged!();
+
+    let pid = std::process::id();
+    let summary = |devices: Vec<DeviceInfo>| {
+        let mut devices: Vec<_> = devices
+            .into_iter()
+            .map(|device| (device.index, device.name, device.netns))
+            .collect();
+        devices.sort();
+        devices
+    };
+
+    let of_pid = devices_for_pid(pid).await.unwrap();
+    let of_namespace = DeviceInfo::of_namespace(format!("/proc/{pid}/ns/net").into())
+        .await
+        .unwrap();
+
+    assert!(!of_pid.is_empty());
+    assert_eq!(summary(of_pid), summary(of_namespace));
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn devices_of_exited_process_are_not_found() {
+    let mut child = Command::new("true").spawn().unwrap();
+    let pid = child.id();
+    child.wait().unwrap();
+
+    let result = devices_for_pid(pid).await;
+
+    assert!(matches!(result, Err(Error::NoSuchProcess(p)) if p == pid));
+}
+
+#[tokio::test]
+async fn interrupted_dump_keeps_received_items() {
+    let stream = futures::stream::iter([
+        Ok(1),
+        Ok(2),
+        Ok(3),
+        Err(rtnetlink::Error::RequestFailed),
+        Ok(4),
+    ]);
+
+    let dump = collect_dump(stream).await.unwrap();
+
+    assert_eq!(dump.items, [1, 2, 3]);
+    assert_eq!(dump.interrupted, Some(rtnetlink::Error::RequestFailed));
+}
+
+#[tokio::test]
+async fn dump_failing_right_away_is_an_error() {
+    let stream = futures::stream::iter([Err::<u32, _>(rtnetlink::Error::RequestFailed)]);
+    assert_eq!(
+        collect_dump(stream).await.unwrap_err(),
+        rtnetlink::Error::RequestFailed
+    );
+
+    let complete = collect_dump(futures::stream::iter([Ok::<_, rtnetlink::Error>(1), Ok(2)]))
+        .await
+        .unwrap();
+    assert_eq!(complete.items, [1, 2]);
+    assert_eq!(complete.interrupted, None);
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn raw_messages_accompany_parsed_devices() {
+    skip_if_unprivileged!();
+    let netns = TempNetns::new().unwrap();
+    ip_in(
+        &netns,
+        &[
+            "link", "add", "raw0", "mtu", "1400", "type", "veth", "peer", "name", "raw1",
+        ],
+    )
+    .unwrap();
+
+    let devices = DeviceInfo::of_namespace_with_raw(netns.path().to_owned())
+        .await
+        .unwrap();
+
+    let mut names: Vec<_> = devices
+        .iter()
+        .map(|(device, _)| device.name.as_str())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["lo", "raw0", "raw1"]);
+    for (device, link) in &devices {
+        assert_eq!(link.header.index, device.index);
+        assert!(
+            link.attributes
+                .contains(&LinkAttribute::IfName(device.name.clone()))
+        );
+        assert!(
+            link.attributes
+                .contains(&LinkAttribute::Mtu(device.mtu.unwrap()))
+        );
+    }
+    let (raw0, _) = devices
+        .iter()
+        .find(|(device, _)| device.name == "raw0")
+        .unwrap();
+    assert_eq!(raw0.mtu, Some(1400));
+}
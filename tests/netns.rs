@@ -0,0 +1,453 @@
+use std::{
+    net::UdpSocket,
+    os::{fd::AsRawFd, unix::fs::MetadataExt},
+    path::{Path, PathBuf},
+    process::Command,
+    time::{Duration, Instant},
+};
+
+use net_device_mapping::{
+    mount_monitor::{FsType, MountOptions, MountPoint, ReadWrite},
+    netns::{
+        Error, IdError, NamespaceScan, NetworkNamespace, container_netns, fd_namespace_map,
+        is_nsfs, is_nsfs_path, named_namespaces, namespace_for_mount, namespaces_for_pids,
+        namespaces_to_csv, netns_for_socket, parse_netns_link_target, pid_namespace_table,
+        pid_netns_inode, settled_pid_namespace_table,
+    },
+};
+#[cfg(feature = "testutil")]
+use net_device_mapping::{netns::LookupContext, skip_if_unprivileged, testutil::TempNetns};
+
+#[tokio::test]
+async fn container_netns_includes_init_process() {
+    let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+    let pid = child.id();
+
+    let netns = container_netns(pid).await;
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    let netns = netns.unwrap();
+    assert!(netns.pids.contains(&pid));
+    assert!(netns.pids.contains(&std::process::id()));
+}
+
+#[tokio::test]
+async fn container_netns_of_exited_init_process() {
+    let mut child = Command::new("true").spawn().unwrap();
+    let pid = child.id();
+    child.wait().unwrap();
+
+    let result = container_netns(pid).await;
+
+    assert!(matches!(result, Err(Error::NoSuchProcess(p)) if p == pid));
+}
+
+#[test]
+fn socket_resolves_to_own_namespace() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let socket_inode = std::fs::metadata(format!("/proc/self/fd/{}", socket.as_raw_fd()))
+        .unwrap()
+        .ino();
+    let own_netns = std::fs::metadata("/proc/self/ns/net").unwrap().ino();
+
+    assert_eq!(netns_for_socket(socket_inode).unwrap(), Some(own_netns));
+}
+
+#[tokio::test]
+async fn named_namespaces_are_among_all() {
+    let named = named_namespaces().await.unwrap();
+    let all = NetworkNamespace::all().await.unwrap();
+
+    for (name, inode, id) in named {
+        let netns = all.iter().find(|netns| netns.inode == inode).unwrap();
+        assert!(netns.fs_path.contains(&Path::new("/run/netns").join(&name)));
+        assert_eq!(netns.id, id);
+    }
+}
+
+#[tokio::test]
+async fn pid_table_matches_grouped_namespaces() {
+    let table = pid_namespace_table().await.unwrap();
+    let all = NetworkNamespace::all().await.unwrap();
+
+    let own_pid = std::process::id();
+    let own_netns = std::fs::metadata("/proc/self/ns/net").unwrap().ino();
+    assert_eq!(table.get(&own_pid), Some(&own_netns));
+    let settled = settled_pid_namespace_table(Duration::from_millis(10))
+        .await
+        .unwrap();
+    assert_eq!(settled.get(&own_pid), Some(&own_netns));
+
+    // Processes could have started or exited between the scans, so only compare the ones seen by both.
+    for netns in &all {
+        for pid in &netns.pids {
+            if let Some(&inode) = table.get(pid) {
+                assert_eq!(inode, netns.inode, "pid {pid}");
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn recheck_waits_for_moving_process_to_settle() {
+    let own_netns = std::fs::metadata("/proc/self/ns/net").unwrap().ino();
+    // Starts in our namespace, and moves to a new one a moment later (same as `ip netns exec` does).
+    let mut child = Command::new("sh")
+        .args(["-c", "sleep 0.2; exec unshare -rn sleep 5"])
+        .spawn()
+        .unwrap();
+    let pid = child.id();
+
+    // A single read sees where the process is right now, even though it is about to move.
+    let stale = pid_netns_inode(pid, None).await.unwrap();
+    let settled = pid_netns_inode(pid, Some(Duration::from_millis(400))).await;
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    assert_eq!(stale, own_netns);
+    assert_ne!(settled.unwrap(), own_netns);
+}
+
+#[tokio::test]
+async fn recheck_of_exited_process() {
+    let mut child = Command::new("true").spawn().unwrap();
+    let pid = child.id();
+    child.wait().unwrap();
+
+    let result = pid_netns_inode(pid, Some(Duration::from_millis(10))).await;
+
+    assert!(matches!(result, Err(Error::NoSuchProcess(p)) if p == pid));
+}
+
+#[test]
+fn nsfs_is_recognized() {
+    assert!(is_nsfs(&FsType::Other("nsfs".to_owned())));
+    assert!(!is_nsfs(&FsType::Other("nfs".to_owned())));
+    assert!(!is_nsfs(&FsType::Proc));
+    assert!(!is_nsfs(&FsType::Tmpfs));
+
+    assert!(is_nsfs_path(Path::new("/proc/self/ns/net")).unwrap());
+    assert!(!is_nsfs_path(Path::new("/proc/self")).unwrap());
+    assert!(is_nsfs_path(Path::new("/nonexistent")).is_err());
+}
+
+#[tokio::test]
+async fn namespace_held_only_by_fd_is_listed() {
+    let own_netns = std::fs::metadata("/proc/self/ns/net").unwrap().ino();
+    let mut child = Command::new("unshare")
+        .args(["-rn", "sleep", "5"])
+        .spawn()
+        .unwrap();
+    let pid = child.id();
+
+    // Wait for the child to move, then keep its namespace open after it exits.
+    let inode = pid_netns_inode(pid, Some(Duration::from_millis(100)))
+        .await
+        .unwrap();
+    let file = std::fs::File::open(format!("/proc/{pid}/ns/net")).unwrap();
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert_ne!(inode, own_netns);
+
+    let fd_path = Path::new("/proc")
+        .join(std::process::id().to_string())
+        .join("fd")
+        .join(file.as_raw_fd().to_string());
+    let fds = fd_namespace_map().await.unwrap();
+    assert_eq!(fds.get(&fd_path), Some(&inode));
+
+    let all = NetworkNamespace::all().await.unwrap();
+    let hidden = all.iter().find(|netns| netns.inode == inode).unwrap();
+    assert!(hidden.pids.is_empty());
+    assert!(hidden.fs_path.is_empty());
+    assert_eq!(hidden.fds, [fd_path]);
+}
+
+#[tokio::test]
+async fn unreadable_processes_do_not_abort_the_scan() {
+    let NamespaceScan {
+        namespaces,
+        unreadable,
+    } = NetworkNamespace::all_with_unreadable().await.unwrap();
+
+    let own = std::fs::metadata("/proc/self/ns/net").unwrap().ino();
+    assert!(namespaces.iter().any(|netns| netns.inode == own));
+    for (path, err) in &unreadable {
+        assert!(path.starts_with("/proc"), "{}", path.display());
+        assert_ne!(err.kind(), std::io::ErrorKind::NotFound);
+    }
+
+    // Root can read all of them, otherwise namespace of init is not accessible.
+    if unsafe { libc::geteuid() } != 0 {
+        assert!(
+            unreadable
+                .iter()
+                .any(|(path, _err)| path.starts_with("/proc/1/")),
+            "{unreadable:?}"
+        );
+    }
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn named_namespaces_are_listed_without_procfs() {
+    skip_if_unprivileged!();
+    let netns = TempNetns::new().unwrap();
+    let procfs = std::env::temp_dir().join(format!("no-procfs-{}", std::process::id()));
+
+    let NamespaceScan {
+        namespaces,
+        unreadable,
+    } = NetworkNamespace::all_in_procfs(&procfs).await.unwrap();
+
+    assert!(unreadable.is_empty(), "{unreadable:?}");
+    let named = namespaces
+        .iter()
+        .find(|found| found.fs_path.iter().any(|path| path == netns.path()))
+        .unwrap();
+    assert_eq!(named.inode, std::fs::metadata(netns.path()).unwrap().ino());
+    for netns in &namespaces {
+        assert!(netns.pids.is_empty(), "{netns:?}");
+        assert!(netns.fds.is_empty(), "{netns:?}");
+    }
+}
+
+#[tokio::test]
+async fn scan_of_many_processes_is_bounded() {
+    const PROCESSES: u32 = 2_000;
+    let procfs = std::env::temp_dir().join(format!("many-procfs-{}", std::process::id()));
+    for pid in 1..=PROCESSES {
+        let ns = procfs.join(format!("{pid}/task/{pid}/ns"));
+        std::fs::create_dir_all(&ns).unwrap();
+        std::os::unix::fs::symlink("/proc/self/ns/net", ns.join("net")).unwrap();
+        std::os::unix::fs::symlink(format!("task/{pid}/ns"), procfs.join(format!("{pid}/ns")))
+            .unwrap();
+    }
+
+    let start = Instant::now();
+    let scan = NetworkNamespace::all_in_procfs(&procfs).await;
+    let elapsed = start.elapsed();
+    std::fs::remove_dir_all(&procfs).unwrap();
+
+    let NamespaceScan {
+        namespaces,
+        unreadable,
+    } = scan.unwrap();
+    assert!(unreadable.is_empty(), "{unreadable:?}");
+    let own = std::fs::metadata("/proc/self/ns/net").unwrap().ino();
+    let mut pids = namespaces
+        .into_iter()
+        .find(|netns| netns.inode == own)
+        .unwrap()
+        .pids;
+    pids.sort();
+    assert_eq!(pids, (1..=PROCESSES).collect::<Vec<_>>());
+    // Only a bound: `metadata()` of local files never blocks, so overlapping calls is not measurably faster here.
+    assert!(elapsed < Duration::from_secs(5), "{elapsed:?}");
+}
+
+fn mount_point(path: &Path, fstype: FsType) -> MountPoint {
+    MountPoint {
+        id: None,
+        parent_id: None,
+        root: None,
+        what: "nsfs".to_owned(),
+        path: path.to_owned(),
+        fstype,
+        options: MountOptions {
+            read_write: ReadWrite::ReadWrite,
+            others: vec![],
+        },
+    }
+}
+
+#[tokio::test]
+async fn non_nsfs_mount_has_no_namespace() {
+    let mount = mount_point(Path::new("/tmp"), FsType::Tmpfs);
+
+    assert_eq!(namespace_for_mount(&mount).await.unwrap(), None);
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn nsfs_mount_resolves_to_its_namespace() {
+    skip_if_unprivileged!();
+    let netns = TempNetns::new().unwrap();
+    let mount = mount_point(netns.path(), FsType::Other("nsfs".to_owned()));
+
+    let inode = std::fs::metadata(netns.path()).unwrap().ino();
+    assert_eq!(namespace_for_mount(&mount).await.unwrap(), Some(inode));
+}
+
+#[tokio::test]
+async fn namespaces_of_pids_are_deduplicated() {
+    let own = std::fs::metadata("/proc/self/ns/net").unwrap().ino();
+    let mut sibling = Command::new("sleep").arg("10").spawn().unwrap();
+    let mut isolated = Command::new("unshare")
+        .args(["-rn", "sleep", "10"])
+        .spawn()
+        .unwrap();
+    let mut exited = Command::new("true").spawn().unwrap();
+    exited.wait().unwrap();
+
+    // `unshare` moves itself to the new namespace before it executes `sleep`.
+    let isolated_ns = format!("/proc/{}/ns/net", isolated.id());
+    let mut isolated_inode = own;
+    for _ in 0..50 {
+        isolated_inode = std::fs::metadata(&isolated_ns).unwrap().ino();
+        if isolated_inode != own {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let (conn, mut handle, _) = rtnetlink::new_connection().unwrap();
+    tokio::spawn(conn);
+    let pids = [
+        std::process::id(),
+        sibling.id(),
+        isolated.id(),
+        exited.id(),
+        std::process::id(),
+    ];
+    let namespaces = namespaces_for_pids(&mut handle, &pids).await;
+
+    for child in [&mut sibling, &mut isolated] {
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    let inodes: Vec<_> = namespaces
+        .unwrap()
+        .iter()
+        .map(|netns| netns.inode)
+        .collect();
+    assert_ne!(isolated_inode, own, "unshare did not create a namespace");
+    assert_eq!(inodes, [own, isolated_inode]);
+}
+
+#[test]
+fn netns_link_target_is_parsed() {
+    assert_eq!(
+        parse_netns_link_target("net:[4026531992]"),
+        Some(4026531992)
+    );
+
+    assert_eq!(parse_netns_link_target("mnt:[4026531841]"), None);
+    assert_eq!(parse_netns_link_target("net:[]"), None);
+    assert_eq!(parse_netns_link_target("net:[4026531992"), None);
+    assert_eq!(parse_netns_link_target("net:4026531992"), None);
+    assert_eq!(parse_netns_link_target("net:[-1]"), None);
+    assert_eq!(parse_netns_link_target("net:[abc]"), None);
+    assert_eq!(parse_netns_link_target(""), None);
+}
+
+#[test]
+fn own_netns_link_target_matches_metadata() {
+    let target = std::fs::read_link("/proc/self/ns/net").unwrap();
+    let inode = std::fs::metadata("/proc/self/ns/net").unwrap().ino();
+
+    assert_eq!(
+        parse_netns_link_target(target.to_str().unwrap()),
+        Some(inode)
+    );
+}
+
+fn namespace(inode: u64, id: Option<u32>, fs_path: &[&str], pids: Vec<u32>) -> NetworkNamespace {
+    NetworkNamespace {
+        inode,
+        id,
+        fs_path: fs_path.iter().map(PathBuf::from).collect(),
+        pids,
+        fds: vec![],
+        uuid: None,
+        machine: None,
+    }
+}
+
+#[test]
+fn namespaces_are_formatted_as_csv() {
+    let namespaces = [
+        namespace(4026531840, Some(0), &[], vec![1, 2, 3]),
+        namespace(4026532000, None, &["/run/netns/b", "/run/netns/a"], vec![]),
+        namespace(4026532100, Some(7), &["/run/netns/a,b"], vec![42]),
+        namespace(4026532200, Some(8), &["/run/netns/\"quoted\""], vec![]),
+    ];
+
+    let csv = namespaces_to_csv(&namespaces);
+
+    assert_eq!(
+        csv.lines().collect::<Vec<_>>(),
+        [
+            "inode,id,path,pid_count",
+            "4026531840,0,,3",
+            "4026532000,,/run/netns/a;/run/netns/b,0",
+            "4026532100,7,\"/run/netns/a,b\",1",
+            "4026532200,8,\"/run/netns/\"\"quoted\"\"\",0",
+        ]
+    );
+    assert!(csv.ends_with('\n'));
+}
+
+#[test]
+fn no_namespaces_is_only_header() {
+    assert_eq!(namespaces_to_csv(&[]), "inode,id,path,pid_count\n");
+}
+
+#[tokio::test]
+async fn failed_connection_is_a_clean_error() {
+    let (conn, mut handle, _) = rtnetlink::new_connection().unwrap();
+    let conn = tokio::spawn(conn);
+    conn.abort();
+    assert!(conn.await.unwrap_err().is_cancelled());
+
+    let result = tokio::time::timeout(
+        Duration::from_secs(10),
+        NetworkNamespace::all_with_handle(&mut handle),
+    )
+    .await
+    .expect("scan did not finish after its connection failed");
+
+    assert!(
+        matches!(
+            result,
+            Err(Error::IdQueryFailed(IdError::Rtnetlink(
+                rtnetlink::Error::RequestFailed
+            )))
+        ),
+        "{result:?}"
+    );
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn lookup_context_reads_mounts_once() {
+    skip_if_unprivileged!();
+    let (conn, mut handle, _) = rtnetlink::new_connection().unwrap();
+    tokio::spawn(conn);
+
+    let before = TempNetns::new().unwrap();
+    let context = LookupContext::new().unwrap();
+    // Bound after the context took its snapshot of mountinfo.
+    let after = TempNetns::new().unwrap();
+    let inode = |netns: &TempNetns| std::fs::metadata(netns.path()).unwrap().ino();
+
+    for _ in 0..2 {
+        let found = context.by_inode(&mut handle, inode(&before)).await.unwrap();
+        assert!(found.unwrap().fs_path.contains(before.path()));
+        assert!(
+            context
+                .by_inode(&mut handle, inode(&after))
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    let fresh = LookupContext::new().unwrap();
+    let found = fresh.by_inode(&mut handle, inode(&after)).await.unwrap();
+    assert!(found.unwrap().fs_path.contains(after.path()));
+}
@@ -0,0 +1,80 @@
+use std::{path::PathBuf, time::UNIX_EPOCH};
+
+use net_device_mapping::netns_identity::{IdentityStore, boot_id};
+
+fn store_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn restart_reuses_persisted_uuids() {
+    let path = store_path("identities-restart");
+
+    // First run: nothing persisted yet.
+    let mut first = IdentityStore::load_with_boot_id(path.clone(), "boot-a".to_owned()).unwrap();
+    assert_eq!(first.identities().count(), 0);
+    assert!(!first.is_dirty());
+    let assigned = [
+        *first.get_or_assign(4026531840),
+        *first.get_or_assign(4026532000),
+    ];
+    assert!(first.is_dirty());
+    first.save().unwrap();
+    assert!(!first.is_dirty());
+    drop(first);
+
+    // Second run, same boot.
+    let mut second = IdentityStore::load_with_boot_id(path.clone(), "boot-a".to_owned()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(second.identities().count(), 2);
+    for identity in assigned {
+        let restored = *second.get_or_assign(identity.inode);
+        assert_eq!(restored.uuid, identity.uuid);
+        // Only whole seconds are persisted.
+        assert_eq!(
+            restored
+                .first_seen
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            identity
+                .first_seen
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        );
+    }
+    assert!(!second.is_dirty());
+}
+
+#[test]
+fn changed_boot_id_discards_persisted_identities() {
+    let path = store_path("identities-reboot");
+
+    let mut before = IdentityStore::load_with_boot_id(path.clone(), "boot-a".to_owned()).unwrap();
+    let uuid = before.get_or_assign(4026531840).uuid;
+    before.save().unwrap();
+
+    let mut after = IdentityStore::load_with_boot_id(path.clone(), "boot-b".to_owned()).unwrap();
+    assert_eq!(after.identities().count(), 0);
+    assert!(after.get(4026531840).is_none());
+    // Stale file is rewritten even if nothing gets assigned.
+    assert!(after.is_dirty());
+    after.save().unwrap();
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(contents, "boot_id boot-b\n");
+    assert_ne!(after.get_or_assign(4026531840).uuid, uuid);
+}
+
+#[test]
+fn boot_id_is_read() {
+    let id = boot_id().unwrap();
+
+    assert_eq!(id.len(), 36, "{id}");
+    assert_eq!(id, boot_id().unwrap());
+}
@@ -0,0 +1,624 @@
+use std::{collections::HashMap, os::unix::fs::MetadataExt, time::Duration};
+
+use net_device_mapping::{
+    netns::{INode, NetworkNamespace, Pid},
+    netns_identity::IdentityStore,
+    netns_tracker::{
+        IdSearchFailure, IdSearchTier, NetnsTrackerBuilder, StateRequest, StateResponse,
+        TierOutcome, TrackerOptions,
+    },
+    syscall_monitor::{EbpfEvent, EventType},
+};
+use tokio::sync::broadcast::{Receiver, Sender};
+
+#[cfg(feature = "testutil")]
+use net_device_mapping::{
+    mount_monitor::MountChange, netns::NsId, netns_tracker::NamespaceIdChanged,
+    nsid_monitor::NetnsIdEvent, skip_if_unprivileged, testutil::TempNetns,
+};
+
+#[tokio::test]
+async fn built_tracker_answers_snapshot_requests() {
+    // Monitors are provided explicitly, since the syscall one needs eBPF objects and privileges.
+    let (_nsid_tx, nsid_events) = tokio::sync::broadcast::channel(16);
+    let (_mount_tx, mount_events) = tokio::sync::broadcast::channel(16);
+    let (_syscall_tx, syscalls) = tokio::sync::broadcast::channel(16);
+
+    let (requests, mut responses, fut) = NetnsTrackerBuilder::new()
+        .nsid_events(nsid_events)
+        .mount_events(mount_events)
+        .syscalls(syscalls)
+        .capacity(8)
+        .build()
+        .unwrap();
+    let task = tokio::spawn(fut);
+
+    requests.send(StateRequest::Snapshot).unwrap();
+    let response = tokio::time::timeout(Duration::from_secs(10), responses.recv())
+        .await
+        .unwrap()
+        .unwrap();
+
+    let StateResponse::Snapshot(namespaces) = response else {
+        panic!("expected a snapshot, got {response:?}");
+    };
+    let own = std::fs::metadata("/proc/self/ns/net").unwrap();
+    assert!(
+        namespaces
+            .iter()
+            .any(|netns| netns.inode == std::os::unix::fs::MetadataExt::ino(&own))
+    );
+
+    drop(responses);
+    tokio::time::timeout(Duration::from_secs(10), task)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+}
+
+/// Own namespace, as seen by a tracker which persists identities to `path`.
+async fn own_namespace_with_identities(path: &std::path::Path) -> NetworkNamespace {
+    let (_nsid_tx, nsid_events) = tokio::sync::broadcast::channel(16);
+    let (_mount_tx, mount_events) = tokio::sync::broadcast::channel(16);
+    let (_syscall_tx, syscalls) = tokio::sync::broadcast::channel(16);
+
+    let identities = IdentityStore::load(path.to_owned()).unwrap();
+    let (requests, mut responses, fut) = NetnsTrackerBuilder::new()
+        .options(TrackerOptions::new().identities(identities))
+        .nsid_events(nsid_events)
+        .mount_events(mount_events)
+        .syscalls(syscalls)
+        .build()
+        .unwrap();
+    let task = tokio::spawn(fut);
+
+    let namespaces = snapshot(&requests, &mut responses).await;
+    drop(responses);
+    tokio::time::timeout(Duration::from_secs(10), task)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    let own = std::fs::metadata("/proc/self/ns/net").unwrap().ino();
+    namespaces
+        .into_iter()
+        .find(|netns| netns.inode == own)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn restarted_tracker_reuses_persisted_uuids() {
+    let path = std::env::temp_dir().join(format!("tracker-identities-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let first = own_namespace_with_identities(&path).await;
+    let second = own_namespace_with_identities(&path).await;
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(first.uuid.is_some());
+    assert_eq!(first.uuid, second.uuid);
+}
+
+#[test]
+fn id_search_failure_is_logged_with_all_tiers() {
+    let failure = IdSearchFailure {
+        id: 7,
+        tiers: vec![
+            TierOutcome {
+                tier: IdSearchTier::ScanExisting,
+                scanned: 3,
+                failed: 0,
+            },
+            TierOutcome {
+                tier: IdSearchTier::ScanMounts,
+                scanned: 2,
+                failed: 1,
+            },
+        ],
+    };
+
+    assert_eq!(
+        failure.to_string(),
+        "netnsid=7 resolved=false \
+         tier=ScanExisting scanned=3 failed=0 \
+         tier=ScanMounts scanned=2 failed=1 \
+         reason=\"some candidates could not be queried, namespace may be among them\""
+    );
+
+    let clean = IdSearchFailure {
+        id: 7,
+        tiers: vec![],
+    };
+    assert_eq!(clean.reason(), "no search tiers are enabled");
+}
+
+fn syscall(kind: EventType, tid: Pid, netns_inode: u32, child_tid: Pid) -> EbpfEvent {
+    EbpfEvent {
+        kind,
+        pid: std::process::id(),
+        tid,
+        uid: 0,
+        gid: 0,
+        parent_pid: 1,
+        netns_inode,
+        child_tid,
+        command: [0; _],
+    }
+}
+
+async fn snapshot(
+    requests: &Sender<StateRequest>,
+    responses: &mut Receiver<StateResponse>,
+) -> Vec<NetworkNamespace> {
+    requests.send(StateRequest::Snapshot).unwrap();
+    loop {
+        let response = tokio::time::timeout(Duration::from_secs(10), responses.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        if let StateResponse::Snapshot(namespaces) = response {
+            return namespaces;
+        }
+    }
+}
+
+/// Polls snapshots until `check` accepts one, since syscalls and requests come through different channels.
+async fn wait_for(
+    requests: &Sender<StateRequest>,
+    responses: &mut Receiver<StateResponse>,
+    check: impl Fn(&[NetworkNamespace]) -> bool,
+) -> Vec<NetworkNamespace> {
+    for _ in 0..100 {
+        let namespaces = snapshot(requests, responses).await;
+        if check(&namespaces) {
+            return namespaces;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("tracker did not reach the expected state");
+}
+
+fn tracks(namespaces: &[NetworkNamespace], tid: Pid) -> bool {
+    namespaces.iter().any(|netns| netns.pids.contains(&tid))
+}
+
+#[tokio::test]
+async fn watch_mode_keeps_latest_snapshot() {
+    let (_nsid_tx, nsid_events) = tokio::sync::broadcast::channel(16);
+    let (_mount_tx, mount_events) = tokio::sync::broadcast::channel(16);
+    let (syscall_tx, syscalls) = tokio::sync::broadcast::channel(16);
+    let (mut snapshots, fut) = NetnsTrackerBuilder::new()
+        .nsid_events(nsid_events)
+        .mount_events(mount_events)
+        .syscalls(syscalls)
+        .build_watch()
+        .unwrap();
+    let task = tokio::spawn(fut);
+
+    // First change is the startup scan.
+    tokio::time::timeout(Duration::from_secs(10), snapshots.changed())
+        .await
+        .unwrap()
+        .unwrap();
+    let own_netns = std::fs::metadata("/proc/self/ns/net").unwrap().ino();
+    assert!(
+        snapshots
+            .borrow_and_update()
+            .iter()
+            .any(|netns| netns.inode == own_netns)
+    );
+
+    // Task that does not exist, forked in our namespace, shows up without any request.
+    let child_tid = Pid::MAX - 1;
+    syscall_tx
+        .send(syscall(EventType::Fork, 1, own_netns as u32, child_tid))
+        .unwrap();
+    tokio::time::timeout(Duration::from_secs(10), snapshots.changed())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(tracks(&snapshots.borrow_and_update(), child_tid));
+
+    // Tracker stops once nobody watches it.
+    drop(snapshots);
+    tokio::time::timeout(Duration::from_secs(10), task)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn threads_are_tracked_by_their_own_ids() {
+    // A thread of this process, which the startup scan records by its thread id.
+    let (tid_tx, tid_rx) = std::sync::mpsc::channel();
+    let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+    let thread = std::thread::spawn(move || {
+        tid_tx.send(unsafe { libc::gettid() } as Pid).unwrap();
+        let _ = done_rx.recv();
+    });
+    let thread_tid = tid_rx.recv().unwrap();
+
+    let (_nsid_tx, nsid_events) = tokio::sync::broadcast::channel(16);
+    let (_mount_tx, mount_events) = tokio::sync::broadcast::channel(16);
+    let (syscall_tx, syscalls) = tokio::sync::broadcast::channel(16);
+    let (requests, mut responses, fut) = NetnsTrackerBuilder::new()
+        .nsid_events(nsid_events)
+        .mount_events(mount_events)
+        .syscalls(syscalls)
+        .build()
+        .unwrap();
+    let task = tokio::spawn(fut);
+
+    let own_netns = std::fs::metadata("/proc/self/ns/net").unwrap().ino() as u32;
+    let own_pid = std::process::id();
+    let namespaces = snapshot(&requests, &mut responses).await;
+    assert!(tracks(&namespaces, thread_tid));
+
+    // Exit of a thread only forgets that thread, not the whole process.
+    syscall_tx
+        .send(syscall(EventType::Exit, thread_tid, 0, 0))
+        .unwrap();
+    // Fork is reported by the parent, the new task is the child.
+    let child_tid = Pid::MAX - 1;
+    syscall_tx
+        .send(syscall(EventType::Fork, own_pid, own_netns, child_tid))
+        .unwrap();
+
+    let namespaces = wait_for(&requests, &mut responses, |namespaces| {
+        !tracks(namespaces, thread_tid) && tracks(namespaces, child_tid)
+    })
+    .await;
+    assert!(tracks(&namespaces, own_pid));
+    let own = namespaces
+        .iter()
+        .find(|netns| netns.inode == own_netns.into())
+        .unwrap();
+    assert!(own.pids.contains(&child_tid));
+
+    syscall_tx
+        .send(syscall(EventType::Exit, child_tid, 0, 0))
+        .unwrap();
+    wait_for(&requests, &mut responses, |namespaces| {
+        !tracks(namespaces, child_tid)
+    })
+    .await;
+
+    drop(done_tx);
+    thread.join().unwrap();
+    drop(responses);
+    tokio::time::timeout(Duration::from_secs(10), task)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+}
+
+async fn pid_counts(
+    requests: &Sender<StateRequest>,
+    responses: &mut Receiver<StateResponse>,
+) -> HashMap<INode, usize> {
+    requests.send(StateRequest::PidCounts).unwrap();
+    loop {
+        let response = tokio::time::timeout(Duration::from_secs(10), responses.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        if let StateResponse::PidCounts(counts) = response {
+            return counts;
+        }
+    }
+}
+
+#[tokio::test]
+async fn pid_counts_match_snapshot() {
+    let (_nsid_tx, nsid_events) = tokio::sync::broadcast::channel(16);
+    let (_mount_tx, mount_events) = tokio::sync::broadcast::channel(16);
+    let (syscall_tx, syscalls) = tokio::sync::broadcast::channel(16);
+    let (requests, mut responses, fut) = NetnsTrackerBuilder::new()
+        .nsid_events(nsid_events)
+        .mount_events(mount_events)
+        .syscalls(syscalls)
+        .build()
+        .unwrap();
+    let task = tokio::spawn(fut);
+
+    // Counts change with the tracked state, they are not taken from `/proc` again.
+    let own_netns = std::fs::metadata("/proc/self/ns/net").unwrap().ino();
+    let child_tid = Pid::MAX - 2;
+    syscall_tx
+        .send(syscall(
+            EventType::Fork,
+            std::process::id(),
+            own_netns as u32,
+            child_tid,
+        ))
+        .unwrap();
+    wait_for(&requests, &mut responses, |namespaces| {
+        tracks(namespaces, child_tid)
+    })
+    .await;
+
+    let counts = pid_counts(&requests, &mut responses).await;
+    let namespaces = snapshot(&requests, &mut responses).await;
+
+    let expected: HashMap<INode, usize> = namespaces
+        .iter()
+        .map(|netns| (netns.inode, netns.pids.len()))
+        .collect();
+    assert_eq!(counts, expected);
+    assert!(counts[&own_netns] >= 2);
+
+    drop(responses);
+    tokio::time::timeout(Duration::from_secs(10), task)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+}
+
+/// Tracker fed by hand, NETNSID events included.
+#[cfg(feature = "testutil")]
+struct FedTracker {
+    nsid_tx: Sender<NetnsIdEvent>,
+    requests: Sender<StateRequest>,
+    responses: Receiver<StateResponse>,
+    task: tokio::task::JoinHandle<Result<(), net_device_mapping::netns_tracker::Error>>,
+    _mount_tx: Sender<MountChange>,
+    _syscall_tx: Sender<EbpfEvent>,
+}
+
+#[cfg(feature = "testutil")]
+impl FedTracker {
+    /// Tracker using only the given id search tiers.
+    async fn start(tiers: &[IdSearchTier]) -> Self {
+        Self::start_with(TrackerOptions::new().id_search(tiers)).await
+    }
+
+    async fn start_with(options: TrackerOptions) -> Self {
+        let (nsid_tx, nsid_events) = tokio::sync::broadcast::channel(16);
+        let (mount_tx, mount_events) = tokio::sync::broadcast::channel(16);
+        let (syscall_tx, syscalls) = tokio::sync::broadcast::channel(16);
+        let (requests, mut responses, fut) = NetnsTrackerBuilder::new()
+            .options(options)
+            .nsid_events(nsid_events)
+            .mount_events(mount_events)
+            .syscalls(syscalls)
+            .build()
+            .unwrap();
+        let task = tokio::spawn(fut);
+        // Startup scan is over once the first snapshot comes.
+        snapshot(&requests, &mut responses).await;
+
+        Self {
+            nsid_tx,
+            requests,
+            responses,
+            task,
+            _mount_tx: mount_tx,
+            _syscall_tx: syscall_tx,
+        }
+    }
+
+    /// Reports a NETNSID as newly assigned, and waits for the tracker to resolve it to a namespace.
+    async fn resolve(&mut self, id: NsId) -> Option<NamespaceIdChanged> {
+        self.nsid_tx.send(NetnsIdEvent::Added(id)).unwrap();
+        self.id_change(|changed| changed.new == Some(id)).await
+    }
+
+    /// Reports a NETNSID as removed, and waits for the tracker to tell which namespace lost it.
+    async fn remove(&mut self, id: NsId) -> Option<NamespaceIdChanged> {
+        self.nsid_tx.send(NetnsIdEvent::Removed(id)).unwrap();
+        self.id_change(|changed| changed.old == Some(id)).await
+    }
+
+    async fn id_change(
+        &mut self,
+        wanted: impl Fn(&NamespaceIdChanged) -> bool,
+    ) -> Option<NamespaceIdChanged> {
+        tokio::time::timeout(Duration::from_secs(2), async {
+            loop {
+                match self.responses.recv().await.unwrap() {
+                    StateResponse::IdChanged(changed) if wanted(&changed) => return changed,
+                    _ => continue,
+                }
+            }
+        })
+        .await
+        .ok()
+    }
+
+    async fn stop(self) {
+        drop(self.responses);
+        tokio::time::timeout(Duration::from_secs(10), self.task)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+}
+
+/// Same as `ip netns set <name> auto`. Id is assigned after the tracker started, so that it is not known yet.
+#[cfg(feature = "testutil")]
+async fn assign_id(netns: &TempNetns) -> NsId {
+    let status = std::process::Command::new("ip")
+        .args(["netns", "set", netns.name(), "auto"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    NetworkNamespace::id_by_path_own_connection(netns.path())
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+#[cfg(feature = "testutil")]
+fn inode(netns: &TempNetns) -> INode {
+    std::fs::metadata(netns.path()).unwrap().ino()
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn existing_tier_resolves_known_namespace() {
+    skip_if_unprivileged!();
+    let netns = TempNetns::new().unwrap();
+    let mut tracker = FedTracker::start(&[IdSearchTier::ScanExisting]).await;
+
+    let id = assign_id(&netns).await;
+    let changed = tracker.resolve(id).await;
+
+    tracker.stop().await;
+    assert_eq!(
+        changed,
+        Some(NamespaceIdChanged {
+            inode: inode(&netns),
+            old: None,
+            new: Some(id),
+        })
+    );
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn existing_tier_alone_misses_unknown_namespace() {
+    skip_if_unprivileged!();
+    // Mount events are not forwarded, so the tracker never learns about a namespace created after it started.
+    let mut tracker = FedTracker::start(&[IdSearchTier::ScanExisting]).await;
+    let netns = TempNetns::new().unwrap();
+
+    let id = assign_id(&netns).await;
+    let changed = tracker.resolve(id).await;
+
+    tracker.stop().await;
+    assert_eq!(changed, None);
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn mounts_tier_resolves_unknown_namespace() {
+    skip_if_unprivileged!();
+    let mut tracker = FedTracker::start(&[IdSearchTier::ScanMounts]).await;
+    let netns = TempNetns::new().unwrap();
+
+    let id = assign_id(&netns).await;
+    let changed = tracker.resolve(id).await;
+
+    tracker.stop().await;
+    assert_eq!(changed.map(|changed| changed.inode), Some(inode(&netns)));
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn procs_tier_resolves_namespace_of_a_process() {
+    skip_if_unprivileged!();
+    let mut tracker = FedTracker::start(&[IdSearchTier::ScanProcs]).await;
+    let mut child = std::process::Command::new("unshare")
+        .args(["-n", "sleep", "10"])
+        .spawn()
+        .unwrap();
+    // `unshare` moves itself to the new namespace before it executes `sleep`.
+    let own = std::fs::metadata("/proc/self/ns/net").unwrap().ino();
+    let child_ns = format!("/proc/{}/ns/net", child.id());
+    while std::fs::metadata(&child_ns).unwrap().ino() == own {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    // Only bound so that `ip` can name it.
+    let netns = TempNetns::attach(child.id()).unwrap();
+
+    let id = assign_id(&netns).await;
+    let changed = tracker.resolve(id).await;
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+    tracker.stop().await;
+    assert_eq!(changed.map(|changed| changed.inode), Some(inode(&netns)));
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn no_tiers_leave_id_unresolved() {
+    skip_if_unprivileged!();
+    let netns = TempNetns::new().unwrap();
+    let mut tracker = FedTracker::start(&[]).await;
+
+    let id = assign_id(&netns).await;
+    let changed = tracker.resolve(id).await;
+
+    tracker.stop().await;
+    assert_eq!(changed, None);
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn later_tier_resolves_after_earlier_one_misses() {
+    skip_if_unprivileged!();
+    let mut tracker =
+        FedTracker::start(&[IdSearchTier::ScanExisting, IdSearchTier::ScanMounts]).await;
+    let netns = TempNetns::new().unwrap();
+
+    let id = assign_id(&netns).await;
+    let changed = tracker.resolve(id).await;
+
+    tracker.stop().await;
+    assert_eq!(changed.map(|changed| changed.inode), Some(inode(&netns)));
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn namespace_gaining_and_losing_id_is_reported() {
+    skip_if_unprivileged!();
+    let netns = TempNetns::new().unwrap();
+    let mut tracker = FedTracker::start_with(TrackerOptions::new()).await;
+
+    let id = assign_id(&netns).await;
+    let assigned = tracker.resolve(id).await;
+    let namespaces = snapshot(&tracker.requests, &mut tracker.responses).await;
+    let removed = tracker.remove(id).await;
+
+    tracker.stop().await;
+    assert_eq!(
+        assigned,
+        Some(NamespaceIdChanged {
+            inode: inode(&netns),
+            old: None,
+            new: Some(id),
+        })
+    );
+    let tracked = namespaces
+        .iter()
+        .find(|tracked| tracked.inode == inode(&netns))
+        .unwrap();
+    assert_eq!(tracked.id, Some(id));
+    assert_eq!(
+        removed,
+        Some(NamespaceIdChanged {
+            inode: inode(&netns),
+            old: Some(id),
+            new: None,
+        })
+    );
+}
+
+#[cfg(feature = "testutil")]
+#[tokio::test]
+async fn id_change_events_can_be_turned_off() {
+    skip_if_unprivileged!();
+    let netns = TempNetns::new().unwrap();
+    let mut tracker = FedTracker::start_with(TrackerOptions::new().id_change_events(false)).await;
+
+    let id = assign_id(&netns).await;
+    let assigned = tracker.resolve(id).await;
+    // Id is still tracked, only not announced.
+    let namespaces = snapshot(&tracker.requests, &mut tracker.responses).await;
+
+    tracker.stop().await;
+    assert_eq!(assigned, None);
+    let tracked = namespaces
+        .iter()
+        .find(|tracked| tracked.inode == inode(&netns))
+        .unwrap();
+    assert_eq!(tracked.id, Some(id));
+}
@@ -0,0 +1,110 @@
+// Every test here runs on real namespaces, with the `testutil` fixtures.
+#![cfg(feature = "testutil")]
+
+use std::{os::unix::fs::MetadataExt, process::Command, time::Duration};
+
+use net_device_mapping::{
+    netns::{NetworkNamespace, nsid_views},
+    nsid_monitor::{NetnsIdEvent, NetnsIdMonitor, monitor_netns_ids},
+    skip_if_unprivileged,
+    testutil::{TempNetns, ip_in},
+};
+
+/// Assigns `id` to namespace `target`, from within namespace `parent` (same as `ip -n <parent> netns set <target> <id>`).
+fn assign_nsid(parent: &TempNetns, target: &TempNetns, id: u32) {
+    ip_in(parent, &["netns", "set", target.name(), &id.to_string()]).unwrap();
+}
+
+#[tokio::test]
+async fn parents_see_child_under_different_ids() {
+    skip_if_unprivileged!();
+
+    let first = TempNetns::new().unwrap();
+    let second = TempNetns::new().unwrap();
+    let child = TempNetns::new().unwrap();
+    assign_nsid(&first, &child, 5);
+    assign_nsid(&second, &child, 7);
+
+    let (conn, mut handle, _) = rtnetlink::new_connection().unwrap();
+    tokio::spawn(conn);
+
+    let inode = |netns: &TempNetns| std::fs::metadata(netns.path()).unwrap().ino();
+    let views = nsid_views(&mut handle, inode(&child)).await.unwrap();
+
+    assert_eq!(views.get(&inode(&first)), Some(&5));
+    assert_eq!(views.get(&inode(&second)), Some(&7));
+}
+
+#[tokio::test]
+async fn lookups_share_one_connection() {
+    skip_if_unprivileged!();
+
+    let netns = TempNetns::new().unwrap();
+    let status = Command::new("ip")
+        .args(["netns", "set", netns.name(), "auto"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let inode = std::fs::metadata(netns.path()).unwrap().ino();
+
+    let (conn, mut handle, _) = rtnetlink::new_connection().unwrap();
+    tokio::spawn(conn);
+
+    // Same handle serves repeated scans.
+    let mut ids = vec![];
+    for _ in 0..2 {
+        let all = NetworkNamespace::all_with_handle(&mut handle)
+            .await
+            .unwrap();
+        let found = all.iter().find(|ns| ns.inode == inode).unwrap();
+        ids.push(found.id.unwrap());
+    }
+    assert_eq!(ids[0], ids[1]);
+
+    let by_id = NetworkNamespace::by_id_own_connection(ids[0])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(by_id.inode, inode);
+
+    let by_inode = NetworkNamespace::by_inode_own_connection(inode)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(by_inode.id, Some(ids[0]));
+}
+
+#[tokio::test]
+async fn monitor_stats_count_assigned_ids() {
+    skip_if_unprivileged!();
+
+    let NetnsIdMonitor {
+        mut events,
+        stats,
+        fut,
+    } = monitor_netns_ids().unwrap();
+    assert_eq!(stats.events_emitted(), 0);
+    let task = tokio::spawn(fut);
+
+    let netns = TempNetns::new().unwrap();
+    let status = Command::new("ip")
+        .args(["netns", "set", netns.name(), "auto"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let id = NetworkNamespace::id_by_path_own_connection(netns.path())
+        .await
+        .unwrap()
+        .unwrap();
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !matches!(events.recv().await.unwrap(), NetnsIdEvent::Added(added) if added == id) {}
+    })
+    .await
+    .expect("id assignment was not reported");
+    assert!(stats.events_emitted() >= 1);
+    assert!(stats.last_event().is_some());
+
+    drop(events);
+    task.await.unwrap().unwrap();
+}
@@ -0,0 +1,186 @@
+use std::{
+    collections::HashSet,
+    ffi::OsStr,
+    net::Ipv4Addr,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use net_device_mapping::{
+    mount_monitor::{ChangeSource, FsType, MountChange, MountOptions, MountPoint, ReadWrite},
+    net_device::{DeviceInfo, Kind, OperState, PeerRef, TunInfo},
+    netns::NetworkNamespace,
+};
+use serde_json::json;
+use uuid::Uuid;
+
+fn namespace(fs_path: HashSet<PathBuf>) -> NetworkNamespace {
+    NetworkNamespace {
+        inode: 4026531840,
+        id: Some(3),
+        fs_path,
+        pids: vec![1, 42],
+        fds: vec![],
+        uuid: Some(Uuid::new_v4()),
+        machine: None,
+    }
+}
+
+#[test]
+fn namespace_round_trips_through_json() {
+    let netns = namespace(HashSet::from([PathBuf::from("/run/netns/web")]));
+
+    let json = serde_json::to_value(&netns).unwrap();
+    assert_eq!(json["inode"], 4026531840u64);
+    assert_eq!(json["fs_path"], serde_json::json!(["/run/netns/web"]));
+    assert_eq!(json["pids"], serde_json::json!([1, 42]));
+
+    let parsed: NetworkNamespace = serde_json::from_value(json).unwrap();
+    assert_eq!(parsed, netns);
+}
+
+#[test]
+fn non_utf8_path_is_not_serialized() {
+    let path = Path::new(OsStr::from_bytes(b"/run/netns/\xff"));
+    let netns = namespace(HashSet::from([path.to_owned()]));
+
+    assert!(serde_json::to_string(&netns).is_err());
+}
+
+fn mount_point(fstype: FsType) -> MountPoint {
+    MountPoint {
+        id: Some(812),
+        parent_id: Some(29),
+        root: Some(PathBuf::from("/")),
+        what: "nsfs".to_owned(),
+        path: PathBuf::from("/run/netns/web"),
+        fstype,
+        options: MountOptions {
+            read_write: ReadWrite::ReadOnly,
+            others: vec!["nosuid".to_owned()],
+        },
+    }
+}
+
+#[test]
+fn mount_changes_round_trip_through_json() {
+    let changes = [
+        MountChange::Added(
+            Uuid::new_v4(),
+            mount_point(FsType::Other("nsfs".to_owned())),
+            ChangeSource::Initial,
+            Some(4026532281),
+        ),
+        MountChange::Removed(Uuid::new_v4(), ChangeSource::Kernel),
+        MountChange::Modified(
+            Uuid::new_v4(),
+            mount_point(FsType::Tmpfs),
+            ChangeSource::Userspace,
+            None,
+        ),
+    ];
+
+    for change in changes {
+        let json = serde_json::to_string(&change).unwrap();
+        let parsed: MountChange = serde_json::from_str(&json).unwrap();
+        // `MountChange` has no `PartialEq`, its debug output covers every field.
+        assert_eq!(format!("{parsed:?}"), format!("{change:?}"));
+    }
+}
+
+fn veth() -> DeviceInfo {
+    DeviceInfo {
+        kind: Kind::Veth {
+            peer: PeerRef {
+                name: "veth0p".to_owned(),
+                netns: 4026532281,
+            },
+        },
+        name: "veth0".to_owned(),
+        index: 7,
+        master_index: None,
+        mac_addr: Some([0x02, 0x42, 0xac, 0x11, 0x00, 0x02]),
+        ipv4_addrs: vec![(Ipv4Addr::new(10, 0, 0, 1), [255, 255, 255, 0])],
+        ipv6_addrs: vec![],
+        netns: 4026531840,
+        is_up: true,
+        oper_state: OperState::Up,
+        is_virtual: true,
+        speed_mbps: Some(10000),
+        duplex: None,
+        mtu: Some(1500),
+        group: 0,
+        driver: Some("veth".to_owned()),
+        bus_info: None,
+        stats: None,
+    }
+}
+
+#[test]
+fn veth_device_serializes_to_tagged_json() {
+    let json = serde_json::to_value(veth()).unwrap();
+
+    assert_eq!(
+        json,
+        json!({
+            "kind": {
+                "kind": "veth",
+                "peer": { "name": "veth0p", "netns": 4026532281u64 },
+            },
+            "name": "veth0",
+            "index": 7,
+            "master_index": null,
+            "mac_addr": "02:42:ac:11:00:02",
+            "ipv4_addrs": [["10.0.0.1", [255, 255, 255, 0]]],
+            "ipv6_addrs": [],
+            "netns": 4026531840u64,
+            "is_up": true,
+            "oper_state": "Up",
+            "is_virtual": true,
+            "speed_mbps": 10000,
+            "duplex": null,
+            "mtu": 1500,
+            "group": 0,
+            "driver": "veth",
+            "bus_info": null,
+            "stats": null,
+        })
+    );
+
+    let parsed: DeviceInfo = serde_json::from_value(json).unwrap();
+    assert_eq!(format!("{parsed:?}"), format!("{:?}", veth()));
+}
+
+#[test]
+fn kinds_without_fields_of_their_own_are_tagged() {
+    let kinds = [
+        (Kind::Loopback, json!({ "kind": "loopback" })),
+        (Kind::Tun(None), json!({ "kind": "tun", "info": null })),
+        (
+            Kind::Tap(Some(TunInfo {
+                owner: Some(1000),
+                ..TunInfo::default()
+            })),
+            json!({
+                "kind": "tap",
+                "info": {
+                    "owner": 1000,
+                    "group": null,
+                    "packet_info": false,
+                    "vnet_hdr": false,
+                    "multi_queue": false,
+                    "persistent": false,
+                },
+            }),
+        ),
+        (
+            Kind::Other("can".to_owned()),
+            json!({ "kind": "other", "name": "can" }),
+        ),
+    ];
+
+    for (kind, expected) in kinds {
+        assert_eq!(serde_json::to_value(&kind).unwrap(), expected);
+        assert_eq!(serde_json::from_value::<Kind>(expected).unwrap(), kind);
+    }
+}
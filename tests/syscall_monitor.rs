@@ -0,0 +1,213 @@
+use std::{collections::HashSet, time::Duration};
+
+use net_device_mapping::syscall_monitor::{
+    EbpfEvent, Error, EventFilter, EventSampler, EventType, KERNEL_BTF_PATH, SyscallMonitorBuilder,
+    require_kernel_btf,
+};
+use tokio::time::Instant;
+
+fn event(kind: EventType, uid: u32) -> EbpfEvent {
+    EbpfEvent {
+        kind,
+        pid: 1000,
+        tid: 1000,
+        uid,
+        gid: uid,
+        parent_pid: 1,
+        netns_inode: 0,
+        child_tid: 0,
+        command: [0; 16],
+    }
+}
+
+#[test]
+fn uid_allowlist_drops_other_users() {
+    let filter = EventFilter::new().uids([1000, 1001]);
+
+    assert!(filter.matches(&event(EventType::Fork, 1000)));
+    assert!(filter.matches(&event(EventType::Exit, 1001)));
+    assert!(!filter.matches(&event(EventType::Fork, 0)));
+    assert!(!filter.matches(&event(EventType::Fork, 1002)));
+}
+
+#[test]
+fn uid_allowlist_composes_with_kinds() {
+    let filter = EventFilter::new()
+        .kinds(&[EventType::Unshare, EventType::Setns])
+        .uids([1000]);
+
+    assert!(filter.matches(&event(EventType::Setns, 1000)));
+    assert!(!filter.matches(&event(EventType::Fork, 1000)));
+    assert!(!filter.matches(&event(EventType::Setns, 0)));
+}
+
+#[test]
+fn exit_only_filter_drops_forks() {
+    let filter = EventFilter::new().kinds(&[EventType::Exit]);
+
+    assert!(filter.matches(&event(EventType::Exit, 0)));
+    assert!(!filter.matches(&event(EventType::Fork, 0)));
+    assert!(!filter.matches(&event(EventType::Exec, 0)));
+}
+
+#[test]
+fn each_kind_has_its_own_tracepoint() {
+    assert_eq!(
+        EventType::Fork.tracepoint(),
+        ("sched", "sched_process_fork")
+    );
+    assert_eq!(
+        EventType::Exit.tracepoint(),
+        ("sched", "sched_process_exit")
+    );
+    assert_eq!(
+        EventType::Setns.tracepoint(),
+        ("syscalls", "sys_exit_setns")
+    );
+
+    let tracepoints: HashSet<_> = EventType::ALL.map(EventType::tracepoint).into();
+    assert_eq!(tracepoints.len(), EventType::ALL.len());
+}
+
+#[test]
+fn missing_kernel_btf_is_reported_clearly() {
+    let err = require_kernel_btf("/nonexistent/btf/vmlinux".as_ref()).unwrap_err();
+    assert!(matches!(err, Error::NoKernelBtf(_)));
+    assert!(
+        err.to_string()
+            .contains("kernel has no BTF at /nonexistent/btf/vmlinux")
+    );
+
+    if std::path::Path::new(KERNEL_BTF_PATH).exists() {
+        require_kernel_btf(KERNEL_BTF_PATH.as_ref()).unwrap();
+    }
+}
+
+fn object_not_found_error(object_dir: &std::path::Path) -> std::io::Error {
+    unsafe { std::env::set_var("EBPF_OBJECT_DIR", object_dir) };
+
+    let Err(Error::Io(err)) = SyscallMonitorBuilder::new().build() else {
+        panic!("expected an io error");
+    };
+    err
+}
+
+#[test]
+fn missing_object_is_reported_clearly() {
+    // Both cases are checked in one test, since no other test in this binary reads the environment.
+    let err = object_not_found_error("/nonexistent/ebpf".as_ref());
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    assert!(
+        err.to_string()
+            .contains("eBPF object not found at /nonexistent/ebpf/fork_monitor.bpf.o")
+    );
+
+    // Directory exists, but the object was not built into it.
+    let dir = std::env::temp_dir().join(format!("ebpf-empty-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let err = object_not_found_error(&dir);
+    std::fs::remove_dir(&dir).unwrap();
+
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    let expected = format!(
+        "eBPF object not found at {}",
+        dir.join("fork_monitor.bpf.o").display()
+    );
+    assert!(err.to_string().contains(&expected), "{err}");
+}
+
+#[test]
+fn fork_burst_from_one_pid_is_coalesced() {
+    let window = Duration::from_millis(10);
+    let start = Instant::now();
+    let mut sampler = EventSampler::new(window);
+
+    for i in 0..100 {
+        sampler.push(
+            event(EventType::Fork, 1000),
+            start + Duration::from_micros(i),
+        );
+    }
+    let mut other = event(EventType::Fork, 1000);
+    other.pid = 2000;
+    sampler.push(other, start + Duration::from_millis(1));
+    sampler.push(
+        event(EventType::Exec, 1000),
+        start + Duration::from_millis(2),
+    );
+
+    // Nothing is sent before the window closes.
+    assert!(sampler.take_due(start + window / 2).is_empty());
+    assert_eq!(sampler.next_due(), Some(start + window));
+
+    let due = sampler.take_due(start + window * 2);
+    let summary: Vec<_> = due
+        .iter()
+        .map(|sampled| (sampled.event.pid, sampled.event.kind, sampled.count))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (1000, EventType::Fork, 100),
+            (2000, EventType::Fork, 1),
+            (1000, EventType::Exec, 1),
+        ]
+    );
+    assert_eq!(sampler.next_due(), None);
+
+    // A new window opens after the previous one was sent.
+    sampler.push(event(EventType::Fork, 1000), start + window * 3);
+    assert_eq!(sampler.take_due(start + window * 4)[0].count, 1);
+}
+
+#[test]
+fn open_windows_are_flushed_on_request() {
+    let window = Duration::from_secs(60);
+    let start = Instant::now();
+    let mut sampler = EventSampler::new(window);
+
+    sampler.push(event(EventType::Fork, 1000), start);
+    sampler.push(event(EventType::Fork, 1000), start);
+    sampler.push(
+        event(EventType::Exit, 1000),
+        start + Duration::from_millis(1),
+    );
+
+    // Windows are still open, but nothing is left behind.
+    assert!(sampler.take_due(start).is_empty());
+    let flushed: Vec<_> = sampler
+        .take_all()
+        .iter()
+        .map(|sampled| (sampled.event.kind, sampled.count))
+        .collect();
+    assert_eq!(flushed, [(EventType::Fork, 2), (EventType::Exit, 1)]);
+    assert_eq!(sampler.next_due(), None);
+}
+
+#[test]
+fn groups_opened_at_the_same_instant_keep_their_order() {
+    let window = Duration::from_millis(10);
+    let now = Instant::now();
+    let mut sampler = EventSampler::new(window);
+
+    // One batch of the ring buffer: all events are received at once.
+    let pids = [3000, 1000, 5000, 2000, 4000];
+    for (pid, kind) in pids.into_iter().zip([
+        EventType::Fork,
+        EventType::Exec,
+        EventType::Exit,
+        EventType::Fork,
+        EventType::Exit,
+    ]) {
+        let mut event = event(kind, 1000);
+        event.pid = pid;
+        sampler.push(event, now);
+    }
+
+    let due: Vec<_> = sampler
+        .take_due(now + window)
+        .iter()
+        .map(|sampled| sampled.event.pid)
+        .collect();
+    assert_eq!(due, pids);
+}
@@ -0,0 +1,283 @@
+//! Separate from `syscall_monitor.rs`, which changes `EBPF_OBJECT_DIR` while its tests run.
+
+use std::{os::unix::fs::MetadataExt, process::Command, time::Duration};
+
+use net_device_mapping::syscall_monitor::{
+    EventFilter, EventType, SyscallMonitor, SyscallMonitorBuilder, monitor_syscalls,
+    monitor_syscalls_kinds, monitor_syscalls_sampled,
+};
+use tokio::sync::broadcast::error::RecvError;
+
+#[tokio::test]
+async fn stop_handle_ends_the_monitor() {
+    // Needs compiled eBPF objects and privileges to attach them.
+    let mut monitor = match SyscallMonitorBuilder::new().build() {
+        Ok(monitor) => monitor,
+        Err(err) => {
+            eprintln!("skipping test: syscall monitor could not start - {err}");
+            return;
+        }
+    };
+    let task = tokio::spawn(monitor.fut);
+
+    monitor.stop.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), task)
+        .await
+        .expect("monitor did not stop")
+        .unwrap()
+        .unwrap();
+
+    // Receiver was kept, and sees the end of the stream after the remaining events.
+    while let Ok(_) | Err(RecvError::Lagged(_)) = monitor.events.recv().await {}
+}
+
+#[tokio::test]
+async fn default_monitor_returns_stop_handle() {
+    let (events, mut stop, fut) = match monitor_syscalls() {
+        Ok(monitor) => monitor,
+        Err(err) => {
+            eprintln!("skipping test: syscall monitor could not start - {err}");
+            return;
+        }
+    };
+    let task = tokio::spawn(fut);
+
+    stop.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), task)
+        .await
+        .expect("monitor did not stop")
+        .unwrap()
+        .unwrap();
+    drop(events);
+}
+
+#[tokio::test]
+async fn all_tracepoints_are_attached() {
+    // Kinds are filtered in userspace, every tracepoint is attached regardless.
+    let monitor = match SyscallMonitorBuilder::new()
+        .kinds(&[EventType::Fork])
+        .build()
+    {
+        Ok(monitor) => monitor,
+        Err(err) => {
+            eprintln!("skipping test: syscall monitor could not start - {err}");
+            return;
+        }
+    };
+    drop(monitor.fut);
+
+    // Every tracepoint used by the monitor exists on kernels it supports.
+    assert_eq!(
+        monitor.status.attached_tracepoints,
+        EventType::ALL.map(EventType::tracepoint)
+    );
+    assert!(monitor.status.failed_tracepoints.is_empty());
+}
+
+#[tokio::test]
+async fn kinds_monitor_returns_stop_handle() {
+    let (events, mut stop, fut) = match monitor_syscalls_kinds(&[EventType::Exit]) {
+        Ok(monitor) => monitor,
+        Err(err) => {
+            eprintln!("skipping test: syscall monitor could not start - {err}");
+            return;
+        }
+    };
+    let task = tokio::spawn(fut);
+
+    stop.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), task)
+        .await
+        .expect("monitor did not stop")
+        .unwrap()
+        .unwrap();
+    drop(events);
+}
+
+#[tokio::test]
+async fn exit_only_monitor_does_not_forward_forks() {
+    let SyscallMonitor {
+        mut events, fut, ..
+    } = match SyscallMonitorBuilder::new()
+        .kinds(&[EventType::Exit])
+        .build()
+    {
+        Ok(monitor) => monitor,
+        Err(err) => {
+            eprintln!("skipping test: syscall monitor could not start - {err}");
+            return;
+        }
+    };
+    let task = tokio::spawn(fut);
+
+    let mut child = Command::new("true").spawn().unwrap();
+    let pid = child.id();
+    child.wait().unwrap();
+
+    let forwarded = tokio::time::timeout(Duration::from_secs(5), async {
+        let mut forwarded = vec![];
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    let exited = event.kind == EventType::Exit && event.pid == pid;
+                    forwarded.push(event);
+                    if exited {
+                        return forwarded;
+                    }
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => panic!("monitor stopped"),
+            }
+        }
+    })
+    .await
+    .expect("exit of the child was not reported");
+
+    assert!(
+        forwarded.iter().all(|event| event.kind == EventType::Exit),
+        "{forwarded:?}"
+    );
+
+    // Monitor stops once nobody receives its events.
+    drop(events);
+    task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn event_carries_namespace_of_the_process() {
+    let mut monitor = match SyscallMonitorBuilder::new()
+        .kinds(&[EventType::Exec])
+        .build()
+    {
+        Ok(monitor) => monitor,
+        Err(err) => {
+            eprintln!("skipping test: syscall monitor could not start - {err}");
+            return;
+        }
+    };
+    let task = tokio::spawn(monitor.fut);
+
+    // `sleep` is executed after `unshare` has moved the process to a namespace of its own.
+    // Exec is reported on entry, before the command changes: it is still `unshare` by then.
+    let mut child = Command::new("unshare")
+        .args(["-rn", "sleep", "5"])
+        .spawn()
+        .unwrap();
+    let pid = child.id();
+
+    let event = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            match monitor.events.recv().await {
+                Ok(event) if event.pid == pid && event.command_as_string() == "unshare" => {
+                    return event;
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => panic!("monitor stopped"),
+            }
+        }
+    })
+    .await
+    .expect("exec of sleep was not reported");
+
+    let actual = std::fs::metadata(format!("/proc/{pid}/ns/net"))
+        .unwrap()
+        .ino();
+    assert_eq!(event.netns(), Some(actual));
+    assert_ne!(
+        event.netns(),
+        Some(std::fs::metadata("/proc/self/ns/net").unwrap().ino())
+    );
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+    monitor.stop.send(()).unwrap();
+    task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn sampled_monitor_flushes_held_back_events_on_stop() {
+    // Window is long enough for nothing to be sent before the monitor is stopped.
+    let mut monitor = match SyscallMonitorBuilder::new()
+        .kinds(&[EventType::Exec])
+        .build_sampled(Duration::from_secs(60))
+    {
+        Ok(monitor) => monitor,
+        Err(err) => {
+            eprintln!("skipping test: syscall monitor could not start - {err}");
+            return;
+        }
+    };
+    let task = tokio::spawn(monitor.fut);
+
+    let status = Command::new("true").status().unwrap();
+    assert!(status.success());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    monitor.stop.send(()).unwrap();
+    task.await.unwrap().unwrap();
+
+    let mut flushed = 0;
+    while let Ok(sampled) = monitor.events.recv().await {
+        flushed += sampled.count;
+    }
+    assert!(flushed > 0);
+    assert!(monitor.stats.events_emitted() > 0);
+}
+
+#[tokio::test]
+async fn default_sampled_monitor_returns_stop_handle() {
+    let (events, mut stop, fut) = match monitor_syscalls_sampled(Duration::from_millis(100)) {
+        Ok(monitor) => monitor,
+        Err(err) => {
+            eprintln!("skipping test: syscall monitor could not start - {err}");
+            return;
+        }
+    };
+    let task = tokio::spawn(fut);
+
+    stop.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), task)
+        .await
+        .expect("monitor did not stop")
+        .unwrap()
+        .unwrap();
+    drop(events);
+}
+
+#[tokio::test]
+async fn sampled_monitor_coalesces_events() {
+    let SyscallMonitor {
+        mut events, fut, ..
+    } = match SyscallMonitorBuilder::new()
+        .filter(EventFilter::new().kinds(&[EventType::Exit]))
+        .build_sampled(Duration::from_millis(200))
+    {
+        Ok(monitor) => monitor,
+        Err(err) => {
+            eprintln!("skipping test: syscall monitor could not start - {err}");
+            return;
+        }
+    };
+    let task = tokio::spawn(fut);
+
+    let mut child = Command::new("true").spawn().unwrap();
+    let pid = child.id();
+    child.wait().unwrap();
+
+    let sampled = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            match events.recv().await {
+                Ok(sampled) if sampled.event.pid == pid => return sampled,
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => panic!("monitor stopped"),
+            }
+        }
+    })
+    .await
+    .expect("exit of the child was not reported");
+    assert_eq!(sampled.event.kind, EventType::Exit);
+    assert!(sampled.count >= 1);
+
+    drop(events);
+    task.await.unwrap().unwrap();
+}
@@ -0,0 +1,24 @@
+use net_device_mapping::{
+    skip_if_unprivileged,
+    testutil::{TempNetns, supports_link_kind},
+};
+
+#[test]
+fn temp_netns_is_created_and_removed() {
+    skip_if_unprivileged!();
+
+    let netns = TempNetns::new().expect("failed to create namespace");
+    let path = netns.path().to_owned();
+    assert!(path.exists());
+
+    drop(netns);
+    assert!(!path.exists());
+}
+
+#[test]
+fn unknown_link_kind_is_unsupported() {
+    skip_if_unprivileged!();
+
+    assert!(supports_link_kind("veth"));
+    assert!(!supports_link_kind("no_such_kind"));
+}
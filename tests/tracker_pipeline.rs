@@ -0,0 +1,205 @@
+//! End-to-end run of `monitor_network_namespaces`: real nsid and mount monitors, real namespaces and processes.
+//!
+//! Syscall events are faked, since the syscall monitor needs eBPF objects. They are only sent
+//! once the process is actually in the namespace, the same as the eBPF probes would report it.
+
+// Every test here runs on real namespaces, with the `testutil` fixtures.
+#![cfg(feature = "testutil")]
+
+use std::{
+    os::unix::fs::MetadataExt,
+    path::Path,
+    process::{Child, Command},
+    time::Duration,
+};
+
+use net_device_mapping::{
+    mount_monitor::{MountMonitor, monitor_mountinfo},
+    netns::{INode, NetworkNamespace},
+    netns_tracker::{Error, StateRequest, StateResponse, monitor_network_namespaces},
+    nsid_monitor::{NetnsIdMonitor, monitor_netns_ids},
+    skip_if_unprivileged,
+    syscall_monitor::{EbpfEvent, EventType},
+    testutil::TempNetns,
+};
+use tokio::{
+    sync::broadcast::{Receiver, Sender},
+    task::JoinHandle,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Tracker wired to real event sources, except for the syscalls, which are sent by the test.
+struct TrackerHarness {
+    requests: Sender<StateRequest>,
+    responses: Receiver<StateResponse>,
+    syscalls: Sender<EbpfEvent>,
+    monitors: Vec<JoinHandle<()>>,
+    tracker: JoinHandle<Result<(), Error>>,
+}
+
+impl TrackerHarness {
+    fn start() -> Self {
+        let NetnsIdMonitor {
+            events: nsid_events,
+            fut: nsid_fut,
+            ..
+        } = monitor_netns_ids().unwrap();
+        let MountMonitor {
+            events: mount_events,
+            fut: mount_fut,
+            ..
+        } = monitor_mountinfo().unwrap();
+        let (syscalls, syscall_events) = tokio::sync::broadcast::channel(16);
+
+        let (requests, responses, fut) =
+            monitor_network_namespaces(nsid_events, mount_events, syscall_events).unwrap();
+
+        let monitors = vec![
+            tokio::spawn(async move {
+                nsid_fut.await.unwrap();
+            }),
+            tokio::spawn(async move {
+                mount_fut.await.unwrap();
+            }),
+        ];
+
+        Self {
+            requests,
+            responses,
+            syscalls,
+            monitors,
+            tracker: tokio::spawn(fut),
+        }
+    }
+
+    fn syscall(&self, kind: EventType, pid: u32) {
+        self.syscalls
+            .send(EbpfEvent {
+                kind,
+                pid,
+                tid: pid,
+                uid: 0,
+                gid: 0,
+                parent_pid: std::process::id(),
+                netns_inode: 0,
+                child_tid: 0,
+                command: [0; _],
+            })
+            .unwrap();
+    }
+
+    async fn snapshot(&mut self) -> Vec<NetworkNamespace> {
+        self.requests.send(StateRequest::Snapshot).unwrap();
+        loop {
+            let response = tokio::time::timeout(TIMEOUT, self.responses.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            // Id changes come unrequested, in between.
+            if let StateResponse::Snapshot(namespaces) = response {
+                return namespaces;
+            }
+        }
+    }
+
+    /// Polls snapshots until `check` accepts one. Events are processed asynchronously, so the state lags behind.
+    async fn wait_for(
+        &mut self,
+        what: &str,
+        check: impl Fn(&[NetworkNamespace]) -> bool,
+    ) -> Vec<NetworkNamespace> {
+        let deadline = tokio::time::Instant::now() + TIMEOUT;
+        loop {
+            let namespaces = self.snapshot().await;
+            if check(&namespaces) {
+                return namespaces;
+            }
+            assert!(
+                tokio::time::Instant::now() < deadline,
+                "timed out waiting for {what}, last snapshot: {namespaces:#?}"
+            );
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    async fn stop(self) {
+        // Tracker stops once nobody listens to it.
+        drop(self.responses);
+        tokio::time::timeout(TIMEOUT, self.tracker)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        for monitor in self.monitors {
+            monitor.abort();
+        }
+    }
+}
+
+/// Runs `sleep` inside of the namespace, and waits until it has switched to it.
+fn spawn_in(netns: &TempNetns, inode: INode) -> Child {
+    let child = Command::new("ip")
+        .args(["netns", "exec", netns.name(), "sleep", "30"])
+        .spawn()
+        .unwrap();
+
+    let path = Path::new("/proc")
+        .join(child.id().to_string())
+        .join("ns")
+        .join("net");
+    for _ in 0..100 {
+        if std::fs::metadata(&path).is_ok_and(|meta| meta.ino() == inode) {
+            return child;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    panic!("process {} did not enter the namespace", child.id());
+}
+
+#[tokio::test]
+async fn created_namespace_and_its_process_are_tracked() {
+    skip_if_unprivileged!();
+
+    let mut tracker = TrackerHarness::start();
+    let netns = TempNetns::new().unwrap();
+    let inode = std::fs::metadata(netns.path()).unwrap().ino();
+    let path = netns.path().to_owned();
+
+    tracker
+        .wait_for("bound path", |namespaces| {
+            namespaces
+                .iter()
+                .any(|ns| ns.inode == inode && ns.fs_path.contains(&path))
+        })
+        .await;
+
+    let mut child = spawn_in(&netns, inode);
+    let pid = child.id();
+    tracker.syscall(EventType::Setns, pid);
+
+    let namespaces = tracker
+        .wait_for("process", |namespaces| {
+            namespaces
+                .iter()
+                .any(|ns| ns.inode == inode && ns.pids.contains(&pid))
+        })
+        .await;
+    let tracked = namespaces.iter().find(|ns| ns.inode == inode).unwrap();
+    assert_eq!(tracked.pids, [pid]);
+    assert_eq!(tracked.fs_path.iter().collect::<Vec<_>>(), [&path]);
+
+    // Without processes and bound files, namespace is gone.
+    child.kill().unwrap();
+    child.wait().unwrap();
+    tracker.syscall(EventType::Exit, pid);
+    drop(netns);
+
+    tracker
+        .wait_for("removal", |namespaces| {
+            namespaces.iter().all(|ns| ns.inode != inode)
+        })
+        .await;
+
+    tracker.stop().await;
+}
@@ -0,0 +1,96 @@
+use std::{io::Write, time::Duration};
+
+use net_device_mapping::util::{StoppableStream, TerminalRedraw, shutdown_tasks};
+use tokio::sync::broadcast::error::RecvError;
+
+#[tokio::test]
+async fn stuck_task_is_aborted_after_timeout() {
+    let finished = tokio::spawn(async { 1 });
+    let stuck = tokio::spawn(std::future::pending::<i32>());
+
+    let results = shutdown_tasks(
+        vec![("finished", finished), ("stuck", stuck)],
+        Duration::from_millis(50),
+    )
+    .await;
+
+    assert_eq!(results.len(), 2);
+    assert!(matches!(results[0], ("finished", Some(Ok(1)))));
+    assert!(matches!(results[1], ("stuck", None)));
+}
+
+async fn stopped_by_either(stop_first: bool) {
+    let (tx, rx) = tokio::sync::broadcast::channel::<u32>(8);
+    let (mut first_tx, first_rx) = async_oneshot::oneshot();
+    let (mut second_tx, second_rx) = async_oneshot::oneshot();
+    let mut stream = StoppableStream::with_stops(rx, vec![first_rx.into(), second_rx.into()]);
+
+    tx.send(1).unwrap();
+    assert_eq!(stream.recv().await.unwrap(), 1);
+
+    if stop_first {
+        first_tx.send(()).unwrap();
+    } else {
+        second_tx.send(()).unwrap();
+    }
+
+    let result = tokio::time::timeout(Duration::from_secs(1), stream.recv()).await;
+    assert!(matches!(result, Ok(Err(RecvError::Closed))));
+    assert!(stream.inner().is_none());
+}
+
+#[tokio::test]
+async fn stoppable_stream_ends_on_first_stop() {
+    stopped_by_either(true).await;
+}
+
+#[tokio::test]
+async fn stoppable_stream_ends_on_second_stop() {
+    stopped_by_either(false).await;
+}
+
+/// Number of "move up N rows and clear downwards" sequences written, with their N.
+fn clears(output: &[u8]) -> Vec<u16> {
+    let output = String::from_utf8_lossy(output);
+    let moves: Vec<u16> = output
+        .split("\x1b[")
+        .filter_map(|sequence| sequence.strip_suffix("A"))
+        .map(|rows| rows.parse().unwrap())
+        .collect();
+    assert_eq!(output.matches("\x1b[J").count(), moves.len());
+    moves
+}
+
+#[test]
+fn redraw_clears_rows_of_previous_frame() {
+    let mut redraw = TerminalRedraw::with_width(10);
+    let mut output = Vec::new();
+
+    redraw
+        .redraw(&mut output, |frame| writeln!(frame, "one\ntwo"))
+        .unwrap();
+    assert!(clears(&output).is_empty());
+    assert_eq!(redraw.last_rows(), 2);
+
+    // 25 columns wrap into 3 rows, the unterminated line adds none.
+    output.clear();
+    redraw
+        .redraw(&mut output, |frame| {
+            write!(frame, "{}\nlast", "x".repeat(25))
+        })
+        .unwrap();
+    assert_eq!(clears(&output), [2]);
+    assert_eq!(redraw.last_rows(), 3);
+
+    output.clear();
+    redraw.redraw(&mut output, |_frame| Ok(())).unwrap();
+    assert_eq!(clears(&output), [3]);
+    assert_eq!(redraw.last_rows(), 0);
+
+    // Nothing to clear after an empty frame.
+    output.clear();
+    redraw
+        .redraw(&mut output, |frame| writeln!(frame, "again"))
+        .unwrap();
+    assert!(clears(&output).is_empty());
+}
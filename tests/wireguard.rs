@@ -0,0 +1,63 @@
+use std::{
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use net_device_mapping::wireguard::{WgDevice, WgPeer, device_from_messages, query_devices};
+use netlink_packet_wireguard::{
+    Wireguard, WireguardCmd,
+    nlas::{WgDeviceAttrs, WgPeer as PeerNla, WgPeerAttrs},
+};
+
+fn peer(key: u8, handshake_secs: u64) -> PeerNla {
+    PeerNla(vec![
+        WgPeerAttrs::PublicKey([key; 32]),
+        WgPeerAttrs::LastHandshake(UNIX_EPOCH + Duration::from_secs(handshake_secs)),
+    ])
+}
+
+fn message(nlas: Vec<WgDeviceAttrs>) -> Wireguard {
+    Wireguard {
+        cmd: WireguardCmd::GetDevice,
+        nlas,
+    }
+}
+
+#[test]
+fn listen_port_and_peers_are_parsed() {
+    let first = message(vec![
+        WgDeviceAttrs::ListenPort(51820),
+        WgDeviceAttrs::Peers(vec![peer(1, 1_700_000_000), peer(2, 0)]),
+    ]);
+    // Second peer is continued in the next message, with the same key.
+    let second = message(vec![WgDeviceAttrs::Peers(vec![peer(2, 0)])]);
+
+    let device = device_from_messages(&[first, second]).unwrap();
+
+    assert_eq!(
+        device,
+        WgDevice {
+            listen_port: 51820,
+            peers: vec![
+                WgPeer {
+                    public_key: [1; 32],
+                    last_handshake: Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+                },
+                WgPeer {
+                    public_key: [2; 32],
+                    last_handshake: None,
+                },
+            ],
+        }
+    );
+}
+
+#[tokio::test]
+async fn missing_family_is_not_an_error() {
+    if Path::new("/sys/module/wireguard").exists() {
+        eprintln!("skipping test: wireguard module is loaded");
+        return;
+    }
+
+    assert!(query_devices(&[1]).await.unwrap().is_none());
+}